@@ -47,6 +47,7 @@ pub fn my_function() {
     span.in_scope(|| {
         tracing::info!("Hello from my_function");
         tracing::event!(tracing::Level::INFO, value = 42_i32, "This is an event");
+        tracing::info!(counter.my_function_calls = 1_u64, histogram.sleep_ms = 5.0);
         std::thread::sleep(std::time::Duration::from_millis(5));
     });
     std::thread::sleep(std::time::Duration::from_millis(5));
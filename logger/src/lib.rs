@@ -6,15 +6,40 @@ use parking_lot::Mutex;
 use rr_data::PubSubMsg;
 use std::sync::Arc;
 
+pub mod otlp;
+pub use ws_client;
+
+/// How to connect to the pub-sub server.
+#[derive(Clone, Debug, Default)]
+pub struct ConnectOptions {
+    /// For servers that require a token (see `pub_sub_server::Auth`). It needs to be a publish token.
+    ///
+    /// Instead of this you can also add `?token=…` to the url.
+    pub token: Option<String>,
+
+    /// Which certificates to trust for `wss://` urls.
+    pub root_certs: ws_client::RootCerts,
+}
+
 struct RrConnection {
-    send: ewebsock::WsSender,
-    _recv: ewebsock::WsReceiver,
+    send: ws_client::WsSender,
 }
 
 impl RrConnection {
-    fn to_pub_sub_server(url: String) -> Self {
-        let (send, _recv) = ewebsock::connect(url).unwrap();
-        Self { send, _recv }
+    fn to_pub_sub_server(url: String, options: ConnectOptions) -> Self {
+        // The server only sends us text when it rejects us, to tell us why:
+        let on_event: ewebsock::EventHandler = Arc::new(|event| {
+            if let ewebsock::WsEvent::Message(ewebsock::WsMessage::Text(text)) = event {
+                eprintln!("pub-sub server: {}", text);
+            }
+            std::ops::ControlFlow::Continue(())
+        });
+        let send = ws_client::ws_connect(url, &options.root_certs, on_event);
+        let mut connection = Self { send };
+        if let Some(token) = options.token {
+            connection.send(PubSubMsg::Authenticate(token));
+        }
+        connection
     }
 
     fn send(&mut self, msg: rr_data::PubSubMsg) {
@@ -27,23 +52,59 @@ impl RrConnection {
 pub struct RrLogger {
     topic_id: rr_data::TopicId,
     connection: Arc<Mutex<RrConnection>>,
+    /// Also send everything to an OpenTelemetry collector.
+    otlp: Option<Mutex<otlp::OtlpExporter>>,
 }
 
 // static_assertions::assert_impl_all!(RrLogger: Send, Sync);
 
 impl RrLogger {
     pub fn to_pub_sub_server(url: String, topic_meta: rr_data::TopicMeta) -> Self {
-        let mut connection = RrConnection::to_pub_sub_server(url);
+        Self::to_pub_sub_server_with_options(url, Default::default(), topic_meta)
+    }
+
+    /// For servers that require a token (see `pub_sub_server::Auth`). It needs to be a publish token.
+    ///
+    /// Instead of this you can also add `?token=…` to the url.
+    pub fn to_pub_sub_server_with_token(
+        url: String,
+        token: Option<String>,
+        topic_meta: rr_data::TopicMeta,
+    ) -> Self {
+        let options = ConnectOptions {
+            token,
+            ..Default::default()
+        };
+        Self::to_pub_sub_server_with_options(url, options, topic_meta)
+    }
+
+    /// Must be called from within a tokio runtime.
+    pub fn to_pub_sub_server_with_options(
+        url: String,
+        options: ConnectOptions,
+        topic_meta: rr_data::TopicMeta,
+    ) -> Self {
+        let mut connection = RrConnection::to_pub_sub_server(url, options);
         let topic_id = topic_meta.id;
         eprintln!("Sending PubSubMsg::NewTopic");
         connection.send(PubSubMsg::NewTopic(topic_meta));
         Self {
             topic_id,
             connection: Arc::new(Mutex::new(connection)),
+            otlp: None,
         }
     }
 
+    /// Also export spans and events to an OpenTelemetry collector.
+    pub fn with_otlp(mut self, config: otlp::Config) -> Self {
+        self.otlp = Some(Mutex::new(otlp::OtlpExporter::new(config)));
+        self
+    }
+
     pub fn send(&self, msg: rr_data::Message) {
+        if let Some(otlp) = &self.otlp {
+            otlp.lock().send(msg.clone());
+        }
         let msg = rr_data::PubSubMsg::TopicMsg(self.topic_id, msg.encode().into());
         self.connection.lock().send(msg);
     }
@@ -79,7 +140,9 @@ impl<S: tracing::Subscriber> tracing_subscriber::layer::Layer<S> for RrLogger {
         let field_names = metadata
             .fields()
             .iter()
-            .map(|field| field.name().to_owned())
+            .map(|field| field.name())
+            .filter(|name| metric_kind_and_name(name).is_none())
+            .map(|name| name.to_owned())
             .collect();
 
         let location = rr_data::Location {
@@ -88,6 +151,25 @@ impl<S: tracing::Subscriber> tracing_subscriber::layer::Layer<S> for RrLogger {
             line: metadata.line(),
         };
 
+        // Each metric field of an event gets its own callsite:
+        if metadata.is_event() {
+            for field in metadata.fields() {
+                if let Some((metric_kind, metric_name)) = metric_kind_and_name(field.name()) {
+                    let metric_callsite = rr_data::Callsite {
+                        id: to_metric_callsite_id(&metadata.callsite(), field.name()),
+                        kind: rr_data::CallsiteKind::Metric(metric_kind),
+                        name: metric_name.to_owned(),
+                        level,
+                        location: location.clone(),
+                        field_names: vec![],
+                    };
+                    self.send(rr_data::Message::now(rr_data::MessageEnum::NewCallsite(
+                        metric_callsite,
+                    )));
+                }
+            }
+        }
+
         let rr_callsite = rr_data::Callsite {
             id: to_callsite_id(&metadata.callsite()),
             kind,
@@ -168,10 +250,36 @@ impl<S: tracing::Subscriber> tracing_subscriber::layer::Layer<S> for RrLogger {
         let mut kv_collector = KvCollector::default();
         event.record(&mut kv_collector);
 
+        let callsite = event.metadata().callsite();
+        let mut fields = vec![];
+        let mut has_metrics = false;
+
+        for (name, value) in kv_collector.values {
+            if let Some((metric_kind, _)) = metric_kind_and_name(&name) {
+                has_metrics = true;
+                if let Some(value) = to_metric_value(metric_kind, &value) {
+                    let rr_metric = rr_data::Metric {
+                        callsite_id: to_metric_callsite_id(&callsite, &name),
+                        parent_span_id,
+                        value,
+                    };
+                    self.send(rr_data::Message::now(rr_data::MessageEnum::Metric(
+                        rr_metric,
+                    )));
+                }
+            } else {
+                fields.push((name, value));
+            }
+        }
+
+        if has_metrics && fields.is_empty() {
+            return; // Only metrics - no need to also send it as an event
+        }
+
         let rr_event = rr_data::DataEvent {
-            callsite_id: to_callsite_id(&event.metadata().callsite()),
+            callsite_id: to_callsite_id(&callsite),
             parent_span_id,
-            fields: kv_collector.values,
+            fields,
         };
 
         self.send(rr_data::Message::now(rr_data::MessageEnum::DataEvent(
@@ -250,6 +358,39 @@ impl tracing::field::Visit for KvCollector {
     }
 }
 
+/// Event fields with these prefixes are sent as metrics instead of as event fields,
+/// e.g. `tracing::info!(counter.requests = 1, histogram.latency_ms = 4.2)`.
+const METRIC_PREFIXES: [(&str, rr_data::MetricKind); 3] = [
+    ("counter.", rr_data::MetricKind::Counter),
+    ("gauge.", rr_data::MetricKind::Gauge),
+    ("histogram.", rr_data::MetricKind::Histogram),
+];
+
+/// `"counter.requests"` -> `(Counter, "requests")`
+fn metric_kind_and_name(field_name: &str) -> Option<(rr_data::MetricKind, &str)> {
+    METRIC_PREFIXES.iter().find_map(|(prefix, kind)| {
+        field_name
+            .strip_prefix(prefix)
+            .map(|metric_name| (*kind, metric_name))
+    })
+}
+
+fn to_metric_value(
+    metric_kind: rr_data::MetricKind,
+    value: &rr_data::Value,
+) -> Option<rr_data::MetricValue> {
+    match metric_kind {
+        rr_data::MetricKind::Counter => match value {
+            rr_data::Value::U64(increment) => Some(*increment),
+            rr_data::Value::I64(increment) => u64::try_from(*increment).ok(),
+            _ => None,
+        }
+        .map(rr_data::MetricValue::CounterIncrement),
+        rr_data::MetricKind::Gauge => value.as_f64().map(rr_data::MetricValue::GaugeSet),
+        rr_data::MetricKind::Histogram => value.as_f64().map(rr_data::MetricValue::HistogramSample),
+    }
+}
+
 /// Hash the given value with a predictable hasher.
 #[inline]
 pub fn hash(value: impl std::hash::Hash) -> u64 {
@@ -263,12 +404,54 @@ fn to_callsite_id(id: &tracing::callsite::Identifier) -> rr_data::CallsiteId {
     rr_data::CallsiteId(hash(id))
 }
 
+fn to_metric_callsite_id(
+    id: &tracing::callsite::Identifier,
+    field_name: &str,
+) -> rr_data::CallsiteId {
+    rr_data::CallsiteId(hash((id, field_name)))
+}
+
 fn to_span_id(id: &tracing::Id) -> rr_data::SpanId {
     rr_data::SpanId(hash(id))
 }
 
 /// `let url = format!("ws://127.0.0.1:{}", rr_data::DEFAULT_PUB_SUB_PORT);`
 pub fn setup_logging(pub_sub_url: &str) {
+    setup_logging_impl(pub_sub_url, Default::default(), None);
+}
+
+/// Like [`setup_logging`], but also sends everything to an OpenTelemetry collector.
+pub fn setup_logging_with_otlp(pub_sub_url: &str, otlp_config: otlp::Config) {
+    setup_logging_impl(pub_sub_url, Default::default(), Some(otlp_config));
+}
+
+/// Like [`setup_logging`], for a server that requires a publish token.
+pub fn setup_logging_with_token(pub_sub_url: &str, token: &str) {
+    let options = ConnectOptions {
+        token: Some(token.to_owned()),
+        ..Default::default()
+    };
+    setup_logging_impl(pub_sub_url, options, None);
+}
+
+/// Like [`setup_logging`], e.g. for a `wss://` server with a self-signed certificate:
+///
+/// ```no_run
+/// let options = logger::ConnectOptions {
+///     root_certs: logger::ws_client::RootCerts::from_pem_file("cert.pem").unwrap(),
+///     ..Default::default()
+/// };
+/// logger::setup_logging_with_options("wss://localhost:9002", options);
+/// ```
+pub fn setup_logging_with_options(pub_sub_url: &str, options: ConnectOptions) {
+    setup_logging_impl(pub_sub_url, options, None);
+}
+
+fn setup_logging_impl(
+    pub_sub_url: &str,
+    options: ConnectOptions,
+    otlp_config: Option<otlp::Config>,
+) {
     use tracing_subscriber::prelude::*;
 
     let stdout_logger = tracing_subscriber::fmt::layer();
@@ -286,7 +469,11 @@ pub fn setup_logging(pub_sub_url: &str) {
         created: rr_data::Time::now(),
         name: "logger".into(),
     };
-    let rr_logger = RrLogger::to_pub_sub_server(pub_sub_url.into(), topic_meta);
+    let mut rr_logger =
+        RrLogger::to_pub_sub_server_with_options(pub_sub_url.into(), options, topic_meta);
+    if let Some(otlp_config) = otlp_config {
+        rr_logger = rr_logger.with_otlp(otlp_config);
+    }
     let rr_logger = rr_logger.with_filter(tracing_subscriber::filter::filter_fn(|metadata| {
         metadata.level() <= &tracing::Level::INFO
     }));
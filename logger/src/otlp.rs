@@ -0,0 +1,794 @@
+//! Export to an [OpenTelemetry](https://opentelemetry.io) collector using OTLP over HTTP/protobuf.
+//!
+//! Spans are sent when they are destroyed, and [`rr_data::DataEvent`]s are sent as log records.
+
+use rr_data::{Message, MessageEnum, SpanId, Time};
+use std::collections::HashMap;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+/// Where and how to send the OTLP data.
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// Base URL of the collector, e.g. `http://127.0.0.1:4318` or `https://otel.example.com`.
+    ///
+    /// Spans are posted to `/v1/traces` and log records to `/v1/logs`.
+    pub endpoint: String,
+
+    /// Reported as the `service.name` resource attribute.
+    pub service_name: String,
+
+    /// Send what we have at least this often.
+    pub flush_interval: Duration,
+
+    /// Send as soon as we have this many spans or log records.
+    pub max_batch_size: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            endpoint: format!("http://127.0.0.1:{}", DEFAULT_OTLP_HTTP_PORT),
+            service_name: "unknown_service".to_owned(),
+            flush_interval: Duration::from_secs(1),
+            max_batch_size: 512,
+        }
+    }
+}
+
+/// The standard port for OTLP over HTTP.
+pub const DEFAULT_OTLP_HTTP_PORT: u16 = 4318;
+
+/// Converts [`Message`]s to OTLP and sends them in batches from a background thread.
+pub struct OtlpExporter {
+    tx: mpsc::Sender<Message>,
+}
+
+impl OtlpExporter {
+    pub fn new(config: Config) -> Self {
+        let (tx, rx) = mpsc::channel();
+        std::thread::Builder::new()
+            .name("otlp_exporter".to_owned())
+            .spawn(move || run(config, rx))
+            .expect("Failed to spawn thread");
+        Self { tx }
+    }
+
+    pub fn send(&self, message: Message) {
+        self.tx.send(message).ok();
+    }
+}
+
+fn run(config: Config, rx: mpsc::Receiver<Message>) {
+    let mut batcher = Batcher::default();
+    let mut last_flush = Instant::now();
+
+    loop {
+        let timeout = config.flush_interval.saturating_sub(last_flush.elapsed());
+        let disconnected = match rx.recv_timeout(timeout) {
+            Ok(message) => {
+                batcher.on_message(message);
+                false
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => false,
+            Err(mpsc::RecvTimeoutError::Disconnected) => true,
+        };
+
+        let batch_size = batcher.spans.len().max(batcher.log_records.len());
+        if disconnected
+            || config.max_batch_size <= batch_size
+            || config.flush_interval <= last_flush.elapsed()
+        {
+            batcher.flush(&config);
+            last_flush = Instant::now();
+        }
+        if disconnected {
+            return;
+        }
+    }
+}
+
+// ----------------------------------------------------------------------------
+
+/// What we know about a span that has not yet been destroyed.
+struct OpenSpan {
+    trace_id: Vec<u8>,
+    span_id: Vec<u8>,
+    parent_span_id: Vec<u8>,
+    callsite_id: rr_data::CallsiteId,
+    fields: rr_data::FieldSet,
+    start: Time,
+    /// When it was last entered, if it is entered now.
+    entered: Option<Time>,
+    busy_ns: i64,
+    links: Vec<proto::Link>,
+    has_errors: bool,
+}
+
+/// Converts [`Message`]s to OTLP spans and log records.
+#[derive(Default)]
+struct Batcher {
+    callsites: HashMap<rr_data::CallsiteId, rr_data::Callsite>,
+    open_spans: HashMap<SpanId, OpenSpan>,
+    spans: Vec<proto::Span>,
+    log_records: Vec<proto::LogRecord>,
+    /// To make span and trace ids unique, even when [`SpanId`]s are reused.
+    counter: u64,
+}
+
+impl Batcher {
+    fn new_id(&mut self, seed: impl std::hash::Hash) -> u64 {
+        self.counter += 1;
+        crate::hash((seed, self.counter, Time::now()))
+    }
+
+    fn on_message(&mut self, message: Message) {
+        let Message { log_time, msg_enum } = message;
+        match msg_enum {
+            MessageEnum::NewCallsite(callsite) => {
+                self.callsites.insert(callsite.id, callsite);
+            }
+            MessageEnum::NewSpan(span) => {
+                let parent = span
+                    .parent_span_id
+                    .and_then(|parent_id| self.open_spans.get(&parent_id))
+                    .map(|parent| (parent.trace_id.clone(), parent.span_id.clone()));
+                let (trace_id, parent_span_id) = match parent {
+                    Some(parent) => parent,
+                    None => {
+                        let mut trace_id = self.new_id(span.id).to_be_bytes().to_vec();
+                        trace_id.extend(self.new_id(span.id).to_be_bytes());
+                        (trace_id, vec![])
+                    }
+                };
+                let open_span = OpenSpan {
+                    trace_id,
+                    span_id: self.new_id(span.id).to_be_bytes().to_vec(),
+                    parent_span_id,
+                    callsite_id: span.callsite_id,
+                    fields: span.fields,
+                    start: log_time,
+                    entered: None,
+                    busy_ns: 0,
+                    links: vec![],
+                    has_errors: false,
+                };
+                self.open_spans.insert(span.id, open_span);
+            }
+            MessageEnum::EnterSpan(span_id) => {
+                if let Some(open_span) = self.open_spans.get_mut(&span_id) {
+                    open_span.entered = Some(log_time);
+                }
+            }
+            MessageEnum::ExitSpan(span_id) => {
+                if let Some(open_span) = self.open_spans.get_mut(&span_id) {
+                    if let Some(entered) = open_span.entered.take() {
+                        open_span.busy_ns +=
+                            log_time.nanos_since_epoch() - entered.nanos_since_epoch();
+                    }
+                }
+            }
+            MessageEnum::DestroySpan(span_id) => {
+                if let Some(open_span) = self.open_spans.remove(&span_id) {
+                    self.spans.push(self.to_proto_span(open_span, log_time));
+                }
+            }
+            MessageEnum::SpanFollowsFrom { span, follows } => {
+                let link = self.open_spans.get(&follows).map(|follows| proto::Link {
+                    trace_id: follows.trace_id.clone(),
+                    span_id: follows.span_id.clone(),
+                });
+                if let (Some(link), Some(open_span)) = (link, self.open_spans.get_mut(&span)) {
+                    open_span.links.push(link);
+                }
+            }
+            MessageEnum::DataEvent(event) => {
+                let level = self
+                    .callsites
+                    .get(&event.callsite_id)
+                    .map_or(rr_data::LogLevel::Info, |callsite| callsite.level);
+                let parent = event
+                    .parent_span_id
+                    .and_then(|span_id| self.open_spans.get_mut(&span_id));
+                let (trace_id, span_id) = match parent {
+                    Some(parent) => {
+                        parent.has_errors |= level == rr_data::LogLevel::Error;
+                        (parent.trace_id.clone(), parent.span_id.clone())
+                    }
+                    None => (vec![], vec![]),
+                };
+
+                let mut attributes = self.location_attributes(&event.callsite_id);
+                let mut body = None;
+                for (name, value) in event.fields {
+                    if name == "message" {
+                        body = Some(to_any_value(&value));
+                    } else {
+                        attributes.push(key_value(name, to_any_value(&value)));
+                    }
+                }
+
+                let time_unix_nano = log_time.nanos_since_epoch() as u64;
+                self.log_records.push(proto::LogRecord {
+                    time_unix_nano,
+                    observed_time_unix_nano: time_unix_nano,
+                    severity_number: severity_number(level),
+                    severity_text: level.to_string().to_uppercase(),
+                    body,
+                    attributes,
+                    trace_id,
+                    span_id,
+                });
+            }
+            MessageEnum::Metric(_) => {}
+        }
+    }
+
+    fn to_proto_span(&self, open_span: OpenSpan, end: Time) -> proto::Span {
+        let name = self.callsites.get(&open_span.callsite_id).map_or_else(
+            || open_span.callsite_id.to_string(),
+            |callsite| callsite.name.clone(),
+        );
+
+        let mut attributes = self.location_attributes(&open_span.callsite_id);
+        for (name, value) in &open_span.fields {
+            attributes.push(key_value(name.clone(), to_any_value(value)));
+        }
+        let lifetime_ns = end.nanos_since_epoch() - open_span.start.nanos_since_epoch();
+        attributes.push(key_value(
+            "busy_ns".to_owned(),
+            int_value(open_span.busy_ns),
+        ));
+        attributes.push(key_value(
+            "idle_ns".to_owned(),
+            int_value(lifetime_ns - open_span.busy_ns),
+        ));
+
+        proto::Span {
+            trace_id: open_span.trace_id,
+            span_id: open_span.span_id,
+            parent_span_id: open_span.parent_span_id,
+            name,
+            kind: proto::SPAN_KIND_INTERNAL,
+            start_time_unix_nano: open_span.start.nanos_since_epoch() as u64,
+            end_time_unix_nano: end.nanos_since_epoch() as u64,
+            attributes,
+            links: open_span.links,
+            status: Some(proto::Status {
+                message: String::new(),
+                code: if open_span.has_errors {
+                    proto::STATUS_CODE_ERROR
+                } else {
+                    proto::STATUS_CODE_UNSET
+                },
+            }),
+        }
+    }
+
+    /// [Semantic conventions](https://opentelemetry.io/docs/specs/semconv/general/attributes/#source-code-attributes)
+    /// for where in the code something happened.
+    fn location_attributes(&self, callsite_id: &rr_data::CallsiteId) -> Vec<proto::KeyValue> {
+        let mut attributes = vec![];
+        if let Some(callsite) = self.callsites.get(callsite_id) {
+            let location = &callsite.location;
+            attributes.push(key_value(
+                "code.namespace".to_owned(),
+                string_value(location.module.clone()),
+            ));
+            if let Some(file) = &location.file {
+                attributes.push(key_value(
+                    "code.filepath".to_owned(),
+                    string_value(file.clone()),
+                ));
+            }
+            if let Some(line) = location.line {
+                attributes.push(key_value("code.lineno".to_owned(), int_value(line as i64)));
+            }
+        }
+        attributes
+    }
+
+    fn flush(&mut self, config: &Config) {
+        let resource = Some(proto::Resource {
+            attributes: vec![key_value(
+                "service.name".to_owned(),
+                string_value(config.service_name.clone()),
+            )],
+        });
+        let scope = Some(proto::InstrumentationScope {
+            name: env!("CARGO_PKG_NAME").to_owned(),
+            version: env!("CARGO_PKG_VERSION").to_owned(),
+        });
+
+        if !self.spans.is_empty() {
+            let request = proto::ExportTraceServiceRequest {
+                resource_spans: vec![proto::ResourceSpans {
+                    resource: resource.clone(),
+                    scope_spans: vec![proto::ScopeSpans {
+                        scope: scope.clone(),
+                        spans: std::mem::take(&mut self.spans),
+                    }],
+                }],
+            };
+            post(config, "/v1/traces", &request);
+        }
+
+        if !self.log_records.is_empty() {
+            let request = proto::ExportLogsServiceRequest {
+                resource_logs: vec![proto::ResourceLogs {
+                    resource,
+                    scope_logs: vec![proto::ScopeLogs {
+                        scope,
+                        log_records: std::mem::take(&mut self.log_records),
+                    }],
+                }],
+            };
+            post(config, "/v1/logs", &request);
+        }
+    }
+}
+
+/// Errors are printed to stderr, since logging them would feed back into the exporter.
+fn post(config: &Config, path: &str, request: &impl prost::Message) {
+    let url = format!("{}{}", config.endpoint.trim_end_matches('/'), path);
+    let result = ureq::post(&url)
+        .set("Content-Type", "application/x-protobuf")
+        .send_bytes(&request.encode_to_vec());
+    if let Err(err) = result {
+        eprintln!("Failed to send OTLP data to {}: {}", url, err);
+    }
+}
+
+fn severity_number(level: rr_data::LogLevel) -> i32 {
+    match level {
+        rr_data::LogLevel::Trace => 1,
+        rr_data::LogLevel::Debug => 5,
+        rr_data::LogLevel::Info => 9,
+        rr_data::LogLevel::Warn => 13,
+        rr_data::LogLevel::Error => 17,
+    }
+}
+
+fn key_value(key: String, value: proto::AnyValue) -> proto::KeyValue {
+    proto::KeyValue {
+        key,
+        value: Some(value),
+    }
+}
+
+fn string_value(value: String) -> proto::AnyValue {
+    proto::AnyValue {
+        value: Some(proto::any_value::Value::StringValue(value)),
+    }
+}
+
+fn int_value(value: i64) -> proto::AnyValue {
+    proto::AnyValue {
+        value: Some(proto::any_value::Value::IntValue(value)),
+    }
+}
+
+fn to_any_value(value: &rr_data::Value) -> proto::AnyValue {
+    use proto::any_value::Value as V;
+    let value = match value {
+        rr_data::Value::String(string) | rr_data::Value::Debug(string) => {
+            V::StringValue(string.clone())
+        }
+        rr_data::Value::I64(value) => V::IntValue(*value),
+        rr_data::Value::U64(value) => match i64::try_from(*value) {
+            Ok(value) => V::IntValue(value),
+            Err(_) => V::StringValue(value.to_string()),
+        },
+        rr_data::Value::F64(value) => V::DoubleValue(*value),
+        rr_data::Value::Bool(value) => V::BoolValue(*value),
+        rr_data::Value::Error { .. } => V::StringValue(value.to_string()),
+    };
+    proto::AnyValue { value: Some(value) }
+}
+
+// ----------------------------------------------------------------------------
+
+/// The parts of the [OTLP protobuf messages](https://github.com/open-telemetry/opentelemetry-proto)
+/// that we send, with the same field numbers.
+mod proto {
+    pub const SPAN_KIND_INTERNAL: i32 = 1;
+    pub const STATUS_CODE_UNSET: i32 = 0;
+    pub const STATUS_CODE_ERROR: i32 = 2;
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ExportTraceServiceRequest {
+        #[prost(message, repeated, tag = "1")]
+        pub resource_spans: Vec<ResourceSpans>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ResourceSpans {
+        #[prost(message, optional, tag = "1")]
+        pub resource: Option<Resource>,
+        #[prost(message, repeated, tag = "2")]
+        pub scope_spans: Vec<ScopeSpans>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ScopeSpans {
+        #[prost(message, optional, tag = "1")]
+        pub scope: Option<InstrumentationScope>,
+        #[prost(message, repeated, tag = "2")]
+        pub spans: Vec<Span>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Span {
+        #[prost(bytes = "vec", tag = "1")]
+        pub trace_id: Vec<u8>,
+        #[prost(bytes = "vec", tag = "2")]
+        pub span_id: Vec<u8>,
+        #[prost(bytes = "vec", tag = "4")]
+        pub parent_span_id: Vec<u8>,
+        #[prost(string, tag = "5")]
+        pub name: String,
+        #[prost(int32, tag = "6")]
+        pub kind: i32,
+        #[prost(fixed64, tag = "7")]
+        pub start_time_unix_nano: u64,
+        #[prost(fixed64, tag = "8")]
+        pub end_time_unix_nano: u64,
+        #[prost(message, repeated, tag = "9")]
+        pub attributes: Vec<KeyValue>,
+        #[prost(message, repeated, tag = "13")]
+        pub links: Vec<Link>,
+        #[prost(message, optional, tag = "15")]
+        pub status: Option<Status>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Link {
+        #[prost(bytes = "vec", tag = "1")]
+        pub trace_id: Vec<u8>,
+        #[prost(bytes = "vec", tag = "2")]
+        pub span_id: Vec<u8>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Status {
+        #[prost(string, tag = "2")]
+        pub message: String,
+        #[prost(int32, tag = "3")]
+        pub code: i32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ExportLogsServiceRequest {
+        #[prost(message, repeated, tag = "1")]
+        pub resource_logs: Vec<ResourceLogs>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ResourceLogs {
+        #[prost(message, optional, tag = "1")]
+        pub resource: Option<Resource>,
+        #[prost(message, repeated, tag = "2")]
+        pub scope_logs: Vec<ScopeLogs>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ScopeLogs {
+        #[prost(message, optional, tag = "1")]
+        pub scope: Option<InstrumentationScope>,
+        #[prost(message, repeated, tag = "2")]
+        pub log_records: Vec<LogRecord>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct LogRecord {
+        #[prost(fixed64, tag = "1")]
+        pub time_unix_nano: u64,
+        #[prost(fixed64, tag = "11")]
+        pub observed_time_unix_nano: u64,
+        #[prost(int32, tag = "2")]
+        pub severity_number: i32,
+        #[prost(string, tag = "3")]
+        pub severity_text: String,
+        #[prost(message, optional, tag = "5")]
+        pub body: Option<AnyValue>,
+        #[prost(message, repeated, tag = "6")]
+        pub attributes: Vec<KeyValue>,
+        #[prost(bytes = "vec", tag = "9")]
+        pub trace_id: Vec<u8>,
+        #[prost(bytes = "vec", tag = "10")]
+        pub span_id: Vec<u8>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Resource {
+        #[prost(message, repeated, tag = "1")]
+        pub attributes: Vec<KeyValue>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct InstrumentationScope {
+        #[prost(string, tag = "1")]
+        pub name: String,
+        #[prost(string, tag = "2")]
+        pub version: String,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct KeyValue {
+        #[prost(string, tag = "1")]
+        pub key: String,
+        #[prost(message, optional, tag = "2")]
+        pub value: Option<AnyValue>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct AnyValue {
+        #[prost(oneof = "any_value::Value", tags = "1, 2, 3, 4")]
+        pub value: Option<any_value::Value>,
+    }
+
+    pub mod any_value {
+        #[allow(clippy::enum_variant_names)] // Same names as in the .proto
+        #[derive(Clone, PartialEq, prost::Oneof)]
+        pub enum Value {
+            #[prost(string, tag = "1")]
+            StringValue(String),
+            #[prost(bool, tag = "2")]
+            BoolValue(bool),
+            #[prost(int64, tag = "3")]
+            IntValue(i64),
+            #[prost(double, tag = "4")]
+            DoubleValue(f64),
+        }
+    }
+}
+
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prost::Message as _;
+    use proto::any_value::Value as V;
+    use rr_data::{CallsiteId, CallsiteKind, LogLevel, Value};
+    use std::io::{BufRead as _, BufReader, Read as _, Write as _};
+    use std::net::{TcpListener, TcpStream};
+
+    /// An OTLP collector that sends on the path and body of each request it gets.
+    fn start_stub_collector() -> (String, mpsc::Receiver<(String, Vec<u8>)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let tx = tx.clone();
+                std::thread::spawn(move || serve(stream.unwrap(), &tx));
+            }
+        });
+        (endpoint, rx)
+    }
+
+    /// Serve requests on one keep-alive connection.
+    fn serve(stream: TcpStream, tx: &mpsc::Sender<(String, Vec<u8>)>) {
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut writer = stream;
+        loop {
+            let mut request_line = String::new();
+            if reader.read_line(&mut request_line).unwrap_or(0) == 0 {
+                return;
+            }
+            let path = request_line.split_whitespace().nth(1).unwrap().to_owned();
+
+            let mut content_length = 0;
+            loop {
+                let mut header = String::new();
+                reader.read_line(&mut header).unwrap();
+                let header = header.trim_end();
+                if header.is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = header.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        content_length = value.trim().parse().unwrap();
+                    }
+                }
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).unwrap();
+
+            writer
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+                .unwrap();
+            if tx.send((path, body)).is_err() {
+                return;
+            }
+        }
+    }
+
+    /// Milliseconds after some point in time.
+    fn time(ms: i64) -> Time {
+        Time::from_nanos_since_epoch(1_600_000_000_000_000_000 + ms * 1_000_000)
+    }
+
+    fn callsite(id: u64, kind: CallsiteKind, name: &str, level: LogLevel) -> MessageEnum {
+        MessageEnum::NewCallsite(rr_data::Callsite {
+            id: CallsiteId(id),
+            kind,
+            name: name.to_owned(),
+            level,
+            location: rr_data::Location {
+                module: "my_app::module".to_owned(),
+                file: Some("src/module.rs".to_owned()),
+                line: Some(42),
+            },
+            field_names: vec![],
+        })
+    }
+
+    fn span(
+        id: u64,
+        parent_id: Option<u64>,
+        callsite_id: u64,
+        fields: rr_data::FieldSet,
+    ) -> MessageEnum {
+        MessageEnum::NewSpan(rr_data::Span {
+            id: SpanId(id),
+            parent_span_id: parent_id.map(SpanId),
+            callsite_id: CallsiteId(callsite_id),
+            fields,
+        })
+    }
+
+    fn attribute<'a>(attributes: &'a [proto::KeyValue], key: &str) -> Option<&'a V> {
+        attributes
+            .iter()
+            .find(|key_value| key_value.key == key)
+            .and_then(|key_value| key_value.value.as_ref()?.value.as_ref())
+    }
+
+    #[test]
+    fn export_to_stub_collector() {
+        let (endpoint, rx) = start_stub_collector();
+        let config = Config {
+            endpoint,
+            service_name: "test_service".to_owned(),
+            ..Default::default()
+        };
+
+        let messages = vec![
+            (0, callsite(1, CallsiteKind::Span, "outer", LogLevel::Info)),
+            (0, callsite(2, CallsiteKind::Span, "inner", LogLevel::Info)),
+            (
+                0,
+                callsite(3, CallsiteKind::Event, "event", LogLevel::Error),
+            ),
+            (
+                1,
+                span(
+                    10,
+                    None,
+                    1,
+                    vec![("user".to_owned(), Value::String("emil".to_owned()))],
+                ),
+            ),
+            (2, MessageEnum::EnterSpan(SpanId(10))),
+            (3, span(11, Some(10), 2, vec![])),
+            (4, MessageEnum::EnterSpan(SpanId(11))),
+            (
+                5,
+                MessageEnum::DataEvent(rr_data::DataEvent {
+                    callsite_id: CallsiteId(3),
+                    parent_span_id: Some(SpanId(11)),
+                    fields: vec![
+                        ("message".to_owned(), Value::String("oops".to_owned())),
+                        ("code".to_owned(), Value::I64(7)),
+                    ],
+                }),
+            ),
+            (6, MessageEnum::ExitSpan(SpanId(11))),
+            (7, MessageEnum::DestroySpan(SpanId(11))),
+            (8, MessageEnum::ExitSpan(SpanId(10))),
+            (10, MessageEnum::DestroySpan(SpanId(10))),
+        ];
+        let mut batcher = Batcher::default();
+        for (ms, msg_enum) in messages {
+            batcher.on_message(Message {
+                log_time: time(ms),
+                msg_enum,
+            });
+        }
+        batcher.flush(&config);
+
+        let mut posted: HashMap<String, Vec<u8>> = (0..2)
+            .map(|_| rx.recv_timeout(Duration::from_secs(10)).unwrap())
+            .collect();
+
+        // Spans:
+        let traces =
+            proto::ExportTraceServiceRequest::decode(&posted.remove("/v1/traces").unwrap()[..])
+                .unwrap();
+        let resource_spans = &traces.resource_spans[0];
+        assert_eq!(
+            attribute(
+                &resource_spans.resource.as_ref().unwrap().attributes,
+                "service.name"
+            ),
+            Some(&V::StringValue("test_service".to_owned()))
+        );
+        let spans = &resource_spans.scope_spans[0].spans;
+        assert_eq!(spans.len(), 2);
+        let (inner, outer) = (&spans[0], &spans[1]); // in the order they were destroyed
+
+        assert_eq!(outer.name, "outer");
+        assert_eq!(outer.trace_id.len(), 16);
+        assert_eq!(outer.span_id.len(), 8);
+        assert!(outer.parent_span_id.is_empty());
+        assert_eq!(
+            outer.start_time_unix_nano,
+            time(1).nanos_since_epoch() as u64
+        );
+        assert_eq!(
+            outer.end_time_unix_nano,
+            time(10).nanos_since_epoch() as u64
+        );
+        assert_eq!(
+            attribute(&outer.attributes, "user"),
+            Some(&V::StringValue("emil".to_owned()))
+        );
+        assert_eq!(
+            attribute(&outer.attributes, "busy_ns"),
+            Some(&V::IntValue(6_000_000))
+        );
+        assert_eq!(
+            attribute(&outer.attributes, "idle_ns"),
+            Some(&V::IntValue(3_000_000))
+        );
+        assert_eq!(
+            attribute(&outer.attributes, "code.lineno"),
+            Some(&V::IntValue(42))
+        );
+        assert_eq!(
+            outer.status.as_ref().unwrap().code,
+            proto::STATUS_CODE_UNSET
+        );
+
+        assert_eq!(inner.name, "inner");
+        assert_eq!(inner.trace_id, outer.trace_id);
+        assert_eq!(inner.parent_span_id, outer.span_id);
+        assert_ne!(inner.span_id, outer.span_id);
+        assert_eq!(
+            inner.start_time_unix_nano,
+            time(3).nanos_since_epoch() as u64
+        );
+        assert_eq!(inner.end_time_unix_nano, time(7).nanos_since_epoch() as u64);
+        assert_eq!(
+            attribute(&inner.attributes, "busy_ns"),
+            Some(&V::IntValue(2_000_000))
+        );
+        assert_eq!(
+            inner.status.as_ref().unwrap().code,
+            proto::STATUS_CODE_ERROR
+        );
+
+        // Log records:
+        let logs = proto::ExportLogsServiceRequest::decode(&posted.remove("/v1/logs").unwrap()[..])
+            .unwrap();
+        let log_records = &logs.resource_logs[0].scope_logs[0].log_records;
+        assert_eq!(log_records.len(), 1);
+        let record = &log_records[0];
+        assert_eq!(record.time_unix_nano, time(5).nanos_since_epoch() as u64);
+        assert_eq!(record.severity_number, 17);
+        assert_eq!(record.severity_text, "ERROR");
+        assert_eq!(
+            record.body.as_ref().and_then(|body| body.value.as_ref()),
+            Some(&V::StringValue("oops".to_owned()))
+        );
+        assert_eq!(attribute(&record.attributes, "code"), Some(&V::IntValue(7)));
+        assert_eq!(
+            attribute(&record.attributes, "code.namespace"),
+            Some(&V::StringValue("my_app::module".to_owned()))
+        );
+        assert_eq!(record.trace_id, inner.trace_id);
+        assert_eq!(record.span_id, inner.span_id);
+    }
+}
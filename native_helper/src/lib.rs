@@ -29,21 +29,30 @@ impl RemoteLogger {
         logger::setup_logging(&pub_sub_url); // This starts sending things to pub-sub server
 
         #[cfg(feature = "web_server")]
-        {
+        let viewer_url = {
             tracing::debug!("Starting web server…");
-            let port = rr_data::DEFAULT_VIEWER_WEB_SERVER_PORT;
+            let config = web_server::Config {
+                pub_sub_url: Some(pub_sub_url.clone()),
+                ..Default::default()
+            };
+            let viewer_url = format!("http://{}:{}", config.bind_ip, config.port);
             join_handles.push(tokio::spawn(async move {
-                web_server::run(port).await.unwrap();
+                web_server::run_with_config(config).await.unwrap();
             }));
-
-            #[cfg(feature = "webbrowser")]
-            {
-                std::thread::sleep(std::time::Duration::from_millis(100)); // give web server time to start
-                let viewer_url = format!("http://127.0.0.1:{}?pubsub={}", port, pub_sub_url);
-                webbrowser::open(&viewer_url).ok();
-            }
+            std::thread::sleep(std::time::Duration::from_millis(100)); // give web server time to start
+            viewer_url
         };
 
+        // The pub-sub server serves the viewer too:
+        #[cfg(not(feature = "web_server"))]
+        let viewer_url = format!("http://127.0.0.1:{}", pub_sub_port);
+
+        #[cfg(feature = "webbrowser")]
+        webbrowser::open(&viewer_url).ok();
+
+        #[cfg(not(feature = "webbrowser"))]
+        eprintln!("Viewer at {}", viewer_url);
+
         Self { join_handles }
     }
 
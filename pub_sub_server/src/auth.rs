@@ -0,0 +1,88 @@
+use hyper::{Body, Request};
+
+/// Which tokens give access to the server.
+///
+/// If no tokens are configured, anyone may do anything.
+/// Otherwise every websocket connection and HTTP API request needs a token,
+/// either as `?token=…` in the URL, or (for websockets) in a first
+/// [`rr_data::PubSubMsg::Authenticate`] message. HTTP API requests can also use
+/// an `Authorization: Bearer …` header.
+///
+/// The viewer web app itself is served without a token.
+#[derive(Clone, Debug, Default)]
+pub struct Auth {
+    /// May create topics and publish messages on them, but not read anything.
+    pub publish_tokens: Vec<String>,
+
+    /// May list, subscribe to, and search topics, but not publish anything.
+    pub read_tokens: Vec<String>,
+}
+
+/// What a connection or request may do.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+    Publish,
+    Read,
+    /// When there are no tokens.
+    Everything,
+}
+
+impl Access {
+    pub fn may_publish(self) -> bool {
+        self != Self::Read
+    }
+
+    pub fn may_read(self) -> bool {
+        self != Self::Publish
+    }
+
+    pub fn permissions(self) -> rr_data::Permissions {
+        rr_data::Permissions {
+            may_publish: self.may_publish(),
+            may_read: self.may_read(),
+        }
+    }
+}
+
+impl Auth {
+    pub fn is_enabled(&self) -> bool {
+        !self.publish_tokens.is_empty() || !self.read_tokens.is_empty()
+    }
+
+    /// `None` if the token doesn't give any access.
+    pub fn access(&self, token: Option<&str>) -> Option<Access> {
+        if !self.is_enabled() {
+            return Some(Access::Everything);
+        }
+        let token = token?;
+        let matches = |tokens: &[String]| tokens.iter().any(|valid| same_token(valid, token));
+        if matches(&self.publish_tokens) {
+            Some(Access::Publish)
+        } else if matches(&self.read_tokens) {
+            Some(Access::Read)
+        } else {
+            None
+        }
+    }
+}
+
+/// The token in the `Authorization` header or the `token` query parameter, if any.
+pub fn token_from_request(request: &Request<Body>) -> Option<String> {
+    let bearer = request
+        .headers()
+        .get(hyper::header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|token| token.trim().to_owned());
+    bearer.or_else(|| crate::http_api::query_param(request, "token"))
+}
+
+/// Compare without leaking how much of the token was right through timing.
+fn same_token(valid: &str, given: &str) -> bool {
+    valid.len() == given.len()
+        && valid
+            .bytes()
+            .zip(given.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
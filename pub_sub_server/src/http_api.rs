@@ -0,0 +1,335 @@
+//! A JSON HTTP API, for scripts and dashboards that don't speak the websocket protocol.
+//!
+//! * `GET /api/topics`: all topics, with their sizes.
+//! * `GET /api/topics/<id>`: one topic.
+//! * `GET /api/topics/<id>/index`: the callsites and spans of a topic, as a [`rr_data::TopicIndex`].
+//! * `GET /api/topics/<id>/messages`: the decoded [`rr_data::Message`]s of a topic, as JSON.
+//! * `GET /api/topics/<id>/recording`: the topic as an [`rr_data::Recording`] file.
+//! * `POST /api/topics[?name=…]`: upload an [`rr_data::Recording`] as a new topic.
+//! * `DELETE /api/topics/<id>`: forget a topic.
+//! * `GET /api/search?q=…[&topic=<id>][&max=…]`: find data events, as [`rr_data::SearchResults`].
+//!
+//! If the server requires tokens, pass one as `Authorization: Bearer <token>` or `?token=<token>`.
+//! `GET` and `HEAD` need a read token, `POST` and `DELETE` a publish token.
+
+use hyper::{body::HttpBody as _, header, Body, Method, Request, Response, StatusCode};
+use rr_data::{Recording, SearchQuery, Time, TopicId, TopicMeta};
+use serde_json::json;
+use std::sync::Arc;
+
+use crate::{auth::Auth, Broadcast, TopicStream, Topics};
+
+pub const PREFIX: &str = "/api/";
+
+/// Default for the `max` parameter of a search.
+const DEFAULT_MAX_SEARCH_HITS: u64 = 100;
+
+/// Larger uploaded recordings are refused, since we hold them in memory.
+const MAX_UPLOAD_BYTES: u64 = 256 * 1024 * 1024;
+
+pub async fn handle_request(
+    topics: Arc<Topics>,
+    auth: &Auth,
+    request: Request<Body>,
+) -> Response<Body> {
+    if let Some(response) = check_token(auth, &request) {
+        return response;
+    }
+
+    let path = request.uri().path().trim_end_matches('/');
+    let parts: Vec<&str> = path.split('/').skip(2).collect(); // skip "" and "api"
+
+    match (request.method(), parts.as_slice()) {
+        (&Method::GET, ["topics"]) => list_topics(&topics),
+        (&Method::POST, ["topics"]) => upload_recording(&topics, request).await,
+        (&Method::GET, ["search"]) => search(&topics, &request).await,
+        (method, ["topics", topic_id, rest @ ..]) => {
+            let topic_id = match topic_id.parse::<TopicId>() {
+                Ok(topic_id) => topic_id,
+                Err(_) => return error(StatusCode::BAD_REQUEST, "Bad topic id"),
+            };
+            match (method, rest) {
+                (&Method::GET, []) => get_topic(&topics, topic_id),
+                (&Method::GET, ["index"]) => get_index(&topics, topic_id),
+                (&Method::GET, ["messages"]) => get_messages(&topics, topic_id),
+                (&Method::GET, ["recording"]) => get_recording(&topics, topic_id),
+                (&Method::DELETE, []) => delete_topic(&topics, topic_id),
+                _ => error(StatusCode::NOT_FOUND, "No such endpoint"),
+            }
+        }
+        _ => error(StatusCode::NOT_FOUND, "No such endpoint"),
+    }
+}
+
+fn list_topics(topics: &Topics) -> Response<Body> {
+    let mut topic_jsons: Vec<(Time, serde_json::Value)> = topics
+        .topics
+        .lock()
+        .values()
+        .map(|topic_stream| (topic_stream.topic_meta.created, topic_json(topic_stream)))
+        .collect();
+    topic_jsons.sort_by_key(|(created, _)| *created);
+    let json: Vec<_> = topic_jsons.into_iter().map(|(_, json)| json).collect();
+    json_response(StatusCode::OK, &json!(json))
+}
+
+fn get_topic(topics: &Topics, topic_id: TopicId) -> Response<Body> {
+    let json = topics.topics.lock().get(&topic_id).map(topic_json);
+    match json {
+        Some(json) => json_response(StatusCode::OK, &json),
+        None => no_such_topic(),
+    }
+}
+
+fn get_index(topics: &Topics, topic_id: TopicId) -> Response<Body> {
+    let topic_index = topics
+        .topics
+        .lock()
+        .get(&topic_id)
+        .map(|topic_stream| topic_stream.index());
+    match topic_index.and_then(|topic_index| serde_json::to_value(topic_index).ok()) {
+        Some(json) => json_response(StatusCode::OK, &json),
+        None => no_such_topic(),
+    }
+}
+
+fn get_messages(topics: &Topics, topic_id: TopicId) -> Response<Body> {
+    let (_, messages) = match topic_messages(topics, topic_id) {
+        Some(topic_messages) => topic_messages,
+        None => return no_such_topic(),
+    };
+    let messages: Vec<_> = messages
+        .iter()
+        .filter_map(|message| match rr_data::Message::decode(message) {
+            Ok(message) => serde_json::to_value(message).ok(),
+            Err(err) => {
+                tracing::warn!("Bad message in topic {}: {}", topic_id, err);
+                None
+            }
+        })
+        .collect();
+    json_response(StatusCode::OK, &json!(messages))
+}
+
+fn get_recording(topics: &Topics, topic_id: TopicId) -> Response<Body> {
+    let (topic_meta, messages) = match topic_messages(topics, topic_id) {
+        Some(topic_messages) => topic_messages,
+        None => return no_such_topic(),
+    };
+    let file_name: String = topic_meta
+        .name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    let recording = Recording {
+        topic_meta,
+        messages,
+    };
+    Response::builder()
+        .header(header::CONTENT_TYPE, "application/octet-stream")
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}_{}.rr\"", file_name, topic_id),
+        )
+        .body(Body::from(recording.encode()))
+        .unwrap()
+}
+
+/// The uploaded topic gets a new id, so the same recording can be uploaded many times.
+async fn upload_recording(topics: &Topics, request: Request<Body>) -> Response<Body> {
+    let name = query_param(&request, "name");
+    let body = match read_body(request, MAX_UPLOAD_BYTES).await {
+        Ok(body) => body,
+        Err(response) => return response,
+    };
+    let recording = match Recording::decode(&body) {
+        Ok(recording) => recording,
+        Err(err) => {
+            return error(
+                StatusCode::BAD_REQUEST,
+                &format!("Expected a recording: {:#}", err),
+            )
+        }
+    };
+
+    let topic_meta = TopicMeta {
+        id: TopicId::random(),
+        created: Time::now(),
+        name: name.unwrap_or(recording.topic_meta.name),
+    };
+    tracing::debug!("Uploaded topic: {:?}", topic_meta);
+    let mut topic_stream = TopicStream::new(topic_meta.clone());
+    for message in recording.messages {
+        topic_stream.push(message);
+    }
+    let json = topic_json(&topic_stream);
+    topics.topics.lock().insert(topic_meta.id, topic_stream);
+    topics.tx.send(Broadcast::new_topic(topic_meta)).ok(); // tell everyone about the new topic
+
+    json_response(StatusCode::CREATED, &json)
+}
+
+async fn search(topics: &Topics, request: &Request<Body>) -> Response<Body> {
+    let text = match query_param(request, "q") {
+        Some(text) if !text.is_empty() => text,
+        _ => return error(StatusCode::BAD_REQUEST, "Missing search text 'q'"),
+    };
+    let topic_id = match query_param(request, "topic").map(|id| id.parse::<TopicId>()) {
+        None => None,
+        Some(Ok(topic_id)) => Some(topic_id),
+        Some(Err(_)) => return error(StatusCode::BAD_REQUEST, "Bad topic id"),
+    };
+    let max_hits = match query_param(request, "max").map(|max| max.parse()) {
+        None => DEFAULT_MAX_SEARCH_HITS,
+        Some(Ok(max_hits)) => max_hits,
+        Some(Err(_)) => return error(StatusCode::BAD_REQUEST, "Bad max"),
+    };
+
+    let query = SearchQuery {
+        text,
+        topic_id,
+        max_hits,
+    };
+    let snapshot = crate::search::Snapshot::new(topics, query);
+    match tokio::task::spawn_blocking(|| snapshot.search()).await {
+        Ok(search_results) => match serde_json::to_value(search_results) {
+            Ok(json) => json_response(StatusCode::OK, &json),
+            Err(err) => error(StatusCode::INTERNAL_SERVER_ERROR, &err.to_string()),
+        },
+        Err(err) => error(StatusCode::INTERNAL_SERVER_ERROR, &err.to_string()),
+    }
+}
+
+/// Everyone who may read is told, and subscriptions to the topic end.
+fn delete_topic(topics: &Topics, topic_id: TopicId) -> Response<Body> {
+    let removed = topics.topics.lock().remove(&topic_id);
+    match removed {
+        Some(_) => {
+            tracing::debug!("Deleted topic {}", topic_id);
+            topics.tx.send(Broadcast::topic_removed(topic_id)).ok();
+            Response::builder()
+                .status(StatusCode::NO_CONTENT)
+                .body(Body::empty())
+                .unwrap()
+        }
+        None => no_such_topic(),
+    }
+}
+
+// ----------------------------------------------------------------------------
+
+/// The error response, if the request isn't allowed.
+fn check_token(auth: &Auth, request: &Request<Body>) -> Option<Response<Body>> {
+    let token = crate::auth::token_from_request(request);
+    let access = match auth.access(token.as_deref()) {
+        Some(access) => access,
+        None if token.is_none() => return Some(error(StatusCode::UNAUTHORIZED, "Missing token")),
+        None => return Some(error(StatusCode::UNAUTHORIZED, "Invalid token")),
+    };
+    if request.method() == Method::GET || request.method() == Method::HEAD {
+        (!access.may_read()).then(|| error(StatusCode::FORBIDDEN, "This token may not read"))
+    } else {
+        (!access.may_publish()).then(|| error(StatusCode::FORBIDDEN, "This token may not publish"))
+    }
+}
+
+/// The encoded messages are shared, so this is cheap.
+fn topic_messages(topics: &Topics, topic_id: TopicId) -> Option<(TopicMeta, Vec<Arc<[u8]>>)> {
+    let topics = topics.topics.lock();
+    let topic_stream = topics.get(&topic_id)?;
+    Some((
+        topic_stream.topic_meta.clone(),
+        topic_stream.messages.clone(),
+    ))
+}
+
+/// The whole body, or an error response if it is larger than `max_bytes`.
+async fn read_body(request: Request<Body>, max_bytes: u64) -> Result<Vec<u8>, Response<Body>> {
+    let too_large = || {
+        error(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            &format!("The limit is {} bytes", max_bytes),
+        )
+    };
+    let content_length = request
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok());
+    if content_length.map_or(false, |content_length| content_length > max_bytes) {
+        return Err(too_large());
+    }
+
+    let mut body = request.into_body();
+    let mut bytes = Vec::with_capacity(content_length.unwrap_or(0) as usize);
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(|err| error(StatusCode::BAD_REQUEST, &err.to_string()))?;
+        if (bytes.len() + chunk.len()) as u64 > max_bytes {
+            return Err(too_large());
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(bytes)
+}
+
+fn topic_json(topic_stream: &TopicStream) -> serde_json::Value {
+    let TopicMeta { id, created, name } = &topic_stream.topic_meta;
+    let stats = topic_stream.stats();
+    json!({
+        "id": id.to_string(),
+        "name": name,
+        "created": created.format(),
+        "created_ns": created.nanos_since_epoch(),
+        "summary": stats.to_string(),
+        "num_messages": stats.num_messages,
+        "num_bytes": stats.num_bytes,
+        "num_events": stats.num_events(),
+        "num_errors": stats.num_events_of_level(rr_data::LogLevel::Error),
+        "num_warnings": stats.num_events_of_level(rr_data::LogLevel::Warn),
+        "num_spans": stats.num_spans,
+        "duration_ns": stats.duration_ns(),
+    })
+}
+
+pub(crate) fn query_param(request: &Request<Body>, key: &str) -> Option<String> {
+    request.uri().query()?.split('&').find_map(|pair| {
+        let (k, v) = pair.split_once('=')?;
+        (k == key).then(|| percent_decode(v))
+    })
+}
+
+fn percent_decode(s: &str) -> String {
+    let s = s.replace('+', " ");
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut rest = s.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let hex = tail.get(..2).and_then(|hex| std::str::from_utf8(hex).ok());
+        match hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+            Some(decoded) if byte == b'%' => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            _ => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+fn json_response(status: StatusCode, json: &serde_json::Value) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(json.to_string()))
+        .unwrap()
+}
+
+fn error(status: StatusCode, message: &str) -> Response<Body> {
+    json_response(status, &json!({ "error": message }))
+}
+
+fn no_such_topic() -> Response<Body> {
+    error(StatusCode::NOT_FOUND, "No such topic")
+}
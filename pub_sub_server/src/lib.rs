@@ -3,19 +3,43 @@
 #![allow(clippy::manual_range_contains)]
 
 use futures_util::{stream::SplitSink, SinkExt, StreamExt};
+use hyper::{upgrade::Upgraded, Body, Request, Response};
 use parking_lot::Mutex;
-use rr_data::{PubSubMsg, TopicId, TopicMeta};
-use std::{
-    collections::{HashMap, HashSet},
-    sync::Arc,
+use rr_data::{Filter, PubSubMsg, Subscription, TopicId, TopicMeta, TopicStats};
+use std::{collections::HashMap, sync::Arc};
+use std::{convert::Infallible, net::SocketAddr, ops::ControlFlow, time::Duration};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpListener,
 };
-use std::{net::SocketAddr, ops::ControlFlow, time::Duration};
-use tokio::net::{TcpListener, TcpStream};
-use tokio_tungstenite::{accept_async, tungstenite::Error, WebSocketStream};
+use tokio_tungstenite::{
+    tungstenite::{self, protocol::Role, Error},
+    WebSocketStream,
+};
+
+mod auth;
+mod http_api;
+mod search;
+#[cfg(feature = "tls")]
+mod tls;
+mod topic_stream;
+
+use auth::Access;
+pub use auth::Auth;
+use topic_stream::{Relevance, TopicStream};
+
+type WsSender = SplitSink<WebSocketStream<Upgraded>, tungstenite::Message>;
+
+/// Reply to [`PubSubMsg::QueryMessages`] in parts of at most this many messages,
+/// to stay well below the maximum websocket message size.
+const MAX_MESSAGES_PER_PART: u64 = 10_000;
+
+/// How often to send [`PubSubMsg::TopicStatsChanged`].
+const TOPIC_STATS_INTERVAL: Duration = Duration::from_secs(1);
 
 pub struct Topics {
     topics: Mutex<HashMap<TopicId, TopicStream>>,
-    tx: tokio::sync::broadcast::Sender<Arc<rr_data::PubSubMsg>>,
+    tx: tokio::sync::broadcast::Sender<Arc<Broadcast>>,
 }
 
 impl Default for Topics {
@@ -28,18 +52,39 @@ impl Default for Topics {
     }
 }
 
-#[derive(Clone)]
-struct TopicStream {
-    topic_meta: TopicMeta,
-    messages: Vec<Arc<[u8]>>,
+/// A message for other connections, encoded and classified once by the connection that got it.
+#[derive(Debug)]
+struct Broadcast {
+    /// An encoded [`PubSubMsg`].
+    encoded: Vec<u8>,
+    recipients: Recipients,
 }
 
-impl TopicStream {
-    fn new(topic_meta: TopicMeta) -> Self {
-        Self {
-            topic_meta,
-            messages: Default::default(),
-        }
+#[derive(Debug)]
+enum Recipients {
+    /// Everyone who may read.
+    Readers,
+
+    /// The subscribers to this topic whose filter lets the message through.
+    Subscribers(TopicId, Relevance),
+
+    /// Everyone who may read. Also ends all subscriptions to the topic, which is gone.
+    ReadersAfterRemoving(TopicId),
+}
+
+impl Broadcast {
+    fn new_topic(topic_meta: TopicMeta) -> Arc<Self> {
+        Arc::new(Self {
+            encoded: PubSubMsg::NewTopic(topic_meta).encode(),
+            recipients: Recipients::Readers,
+        })
+    }
+
+    fn topic_removed(topic_id: TopicId) -> Arc<Self> {
+        Arc::new(Self {
+            encoded: PubSubMsg::TopicRemoved(topic_id).encode(),
+            recipients: Recipients::ReadersAfterRemoving(topic_id),
+        })
     }
 }
 
@@ -47,6 +92,9 @@ impl TopicStream {
 
 pub struct Server {
     listener: TcpListener,
+    auth: Arc<Auth>,
+    #[cfg(feature = "tls")]
+    tls: Option<tokio_rustls::TlsAcceptor>,
 }
 
 impl Server {
@@ -61,13 +109,56 @@ impl Server {
             .with_context(|| format!("Can't listen on {:?}", bind_addr))?;
         eprintln!("Pub-sub listening on: {}", bind_addr);
 
-        Ok(Self { listener })
+        Ok(Self {
+            listener,
+            auth: Default::default(),
+            #[cfg(feature = "tls")]
+            tls: None,
+        })
+    }
+
+    /// Where we listen, e.g. to find out which port we got when asking for port 0.
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Only accept TLS connections (`wss://` and `https://`), using the given PEM files.
+    #[cfg(feature = "tls")]
+    pub fn with_tls(
+        mut self,
+        cert_path: impl AsRef<std::path::Path>,
+        key_path: impl AsRef<std::path::Path>,
+    ) -> anyhow::Result<Self> {
+        self.tls = Some(tls::acceptor(cert_path.as_ref(), key_path.as_ref())?);
+        eprintln!("Pub-sub uses TLS: connect with wss:// and https:// instead");
+        Ok(self)
+    }
+
+    /// Require tokens from clients. By default anyone may do anything.
+    pub fn with_auth(mut self, auth: Auth) -> Self {
+        if auth.is_enabled() {
+            eprintln!(
+                "Pub-sub requires a token ({} publish, {} read)",
+                auth.publish_tokens.len(),
+                auth.read_tokens.len()
+            );
+        }
+        self.auth = Arc::new(auth);
+        self
     }
 
     /// Accept new connections forever
     pub async fn run(self) -> anyhow::Result<()> {
         use anyhow::Context as _;
 
+        if cfg!(feature = "viewer") {
+            #[cfg(feature = "tls")]
+            let scheme = if self.tls.is_some() { "https" } else { "http" };
+            #[cfg(not(feature = "tls"))]
+            let scheme = "http";
+            eprintln!("Serving viewer on {}://{}", scheme, self.local_addr()?);
+        }
+
         let topics = Arc::new(Topics::default());
 
         while let Ok((stream, _)) = self.listener.accept().await {
@@ -75,46 +166,169 @@ impl Server {
                 .peer_addr()
                 .context("connected streams should have a peer address")?;
             let topics = topics.clone();
-            tokio::spawn(accept_connection(topics, peer, stream));
+            let auth = self.auth.clone();
+
+            #[cfg(feature = "tls")]
+            if let Some(tls) = self.tls.clone() {
+                tokio::spawn(async move {
+                    match tls.accept(stream).await {
+                        Ok(stream) => accept_connection(topics, auth, peer, stream).await,
+                        Err(err) => tracing::warn!("TLS handshake with {} failed: {}", peer, err),
+                    }
+                });
+                continue;
+            }
+
+            tokio::spawn(accept_connection(topics, auth, peer, stream));
         }
 
         Ok(())
     }
 }
 
-async fn accept_connection(topics: Arc<Topics>, _peer: SocketAddr, stream: TcpStream) {
-    // let span = tracing::span!(
-    //     tracing::Level::INFO,
-    //     "Connection",
-    //     peer = _peer.to_string().as_str()
-    // );
-    // let _enter = span.enter();
+/// Serve HTTP on the connection, and upgrade websocket requests to the pub-sub protocol.
+async fn accept_connection(
+    topics: Arc<Topics>,
+    auth: Arc<Auth>,
+    peer: SocketAddr,
+    stream: impl AsyncRead + AsyncWrite + Unpin + Send + 'static,
+) {
+    let service = hyper::service::service_fn(move |request| {
+        let topics = topics.clone();
+        let auth = auth.clone();
+        async move { Ok::<_, Infallible>(handle_request(topics, auth, request).await) }
+    });
+    if let Err(err) = hyper::server::conn::Http::new()
+        .http1_only(true)
+        .serve_connection(stream, service)
+        .with_upgrades()
+        .await
+    {
+        tracing::warn!("Error serving {}: {}", peer, err);
+    }
+}
+
+async fn handle_request(
+    topics: Arc<Topics>,
+    auth: Arc<Auth>,
+    request: Request<Body>,
+) -> Response<Body> {
+    if is_websocket_upgrade(&request) {
+        return upgrade_to_websocket(topics, auth, request);
+    }
 
-    tracing::info!("New WebSocket connection");
+    if request.uri().path().starts_with(http_api::PREFIX) {
+        return http_api::handle_request(topics, &auth, request).await;
+    }
 
-    if let Err(e) = handle_connection(&topics, stream).await {
-        match e {
-            Error::ConnectionClosed | Error::Protocol(_) | Error::Utf8 => (),
-            err => tracing::error!("Error processing connection: {}", err),
-        }
+    #[cfg(feature = "viewer")]
+    {
+        web_server::serve_embedded(&request).await
+    }
+
+    #[cfg(not(feature = "viewer"))]
+    {
+        Response::builder()
+            .status(hyper::StatusCode::NOT_FOUND)
+            .body(Body::from(
+                "This pub-sub server only accepts websocket connections",
+            ))
+            .unwrap()
     }
 }
 
-async fn handle_connection(topics: &Topics, stream: TcpStream) -> tungstenite::Result<()> {
-    let ws_stream = accept_async(stream).await.expect("Failed to accept");
+/// The viewer connects to `/ws`, so that `/` can serve it.
+/// Loggers (and older viewers) connect to the bare `ws://host:port`, i.e. `/`, which we keep accepting.
+fn is_websocket_upgrade(request: &Request<Body>) -> bool {
+    matches!(request.uri().path(), "/ws" | "/")
+        && request
+            .headers()
+            .get(hyper::header::UPGRADE)
+            .and_then(|upgrade| upgrade.to_str().ok())
+            .map_or(false, |upgrade| upgrade.eq_ignore_ascii_case("websocket"))
+}
+
+/// Complete the websocket handshake, then run the pub-sub protocol on the upgraded connection.
+///
+/// A bad token still gets a websocket, so that we can tell the client why we close it
+/// (browsers don't show websocket clients the HTTP status of a failed handshake).
+fn upgrade_to_websocket(
+    topics: Arc<Topics>,
+    auth: Arc<Auth>,
+    mut request: Request<Body>,
+) -> Response<Body> {
+    use hyper::header::{CONNECTION, SEC_WEBSOCKET_ACCEPT, SEC_WEBSOCKET_KEY, UPGRADE};
+
+    let accept_key = match request.headers().get(SEC_WEBSOCKET_KEY) {
+        Some(key) => tungstenite::handshake::derive_accept_key(key.as_bytes()),
+        None => {
+            return Response::builder()
+                .status(hyper::StatusCode::BAD_REQUEST)
+                .body(Body::from("Missing Sec-WebSocket-Key"))
+                .unwrap();
+        }
+    };
+
+    let token = auth::token_from_request(&request);
+    let access = auth.access(token.as_deref());
+    let invalid_token = token.is_some() && access.is_none();
+
+    tokio::spawn(async move {
+        match hyper::upgrade::on(&mut request).await {
+            Ok(upgraded) => {
+                let ws_stream =
+                    WebSocketStream::from_raw_socket(upgraded, Role::Server, None).await;
+                tracing::info!("New WebSocket connection");
+                let result = if invalid_token {
+                    let (mut ws_sender, _) = ws_stream.split();
+                    reject(&mut ws_sender, "Invalid token").await;
+                    Ok(())
+                } else {
+                    handle_connection(&topics, &auth, access, ws_stream).await
+                };
+                if let Err(err) = result {
+                    match err {
+                        Error::ConnectionClosed | Error::Protocol(_) | Error::Utf8 => (),
+                        err => tracing::error!("Error processing connection: {}", err),
+                    }
+                }
+            }
+            Err(err) => tracing::error!("Failed to upgrade to websocket: {}", err),
+        }
+    });
+
+    Response::builder()
+        .status(hyper::StatusCode::SWITCHING_PROTOCOLS)
+        .header(CONNECTION, "Upgrade")
+        .header(UPGRADE, "websocket")
+        .header(SEC_WEBSOCKET_ACCEPT, accept_key)
+        .body(Body::empty())
+        .unwrap()
+}
+
+/// `access: None` means the client has yet to send a [`PubSubMsg::Authenticate`].
+async fn handle_connection(
+    topics: &Topics,
+    auth: &Auth,
+    mut access: Option<Access>,
+    ws_stream: WebSocketStream<Upgraded>,
+) -> tungstenite::Result<()> {
     let (mut ws_sender, mut ws_receiver) = ws_stream.split();
-    let mut interval = tokio::time::interval(Duration::from_millis(1000));
+    let mut interval = tokio::time::interval(TOPIC_STATS_INTERVAL);
 
     let mut broadcast_rx = topics.tx.subscribe();
 
-    let mut subscribed_topics = HashSet::default();
+    let mut subscribed_topics = HashMap::default();
+
+    // What we last told the client, once it has asked for stats.
+    let mut sent_stats = None;
 
     loop {
         tokio::select! {
             ws_msg = ws_receiver.next() => {
                 match ws_msg {
                     Some(Ok(msg)) => {
-                        if on_msg(&mut subscribed_topics, topics, &mut ws_sender, msg).await == ControlFlow::Break(()) {
+                        if on_msg(&mut subscribed_topics, &mut sent_stats, topics, auth, &mut access, &mut ws_sender, msg).await == ControlFlow::Break(()) {
                             break;
                         }
                     }
@@ -127,26 +341,32 @@ async fn handle_connection(topics: &Topics, stream: TcpStream) -> tungstenite::R
                     }
                 }
             }
-            pub_sub_msg = broadcast_rx.recv() => {
-                let pub_sub_msg = pub_sub_msg.unwrap();
-                let client_wants_msg = match &*pub_sub_msg {
-                    rr_data::PubSubMsg::NewTopic(_) => {
-                        true // Inform everyone about all new topics
-                    }
-                    rr_data::PubSubMsg::TopicMsg(topic_id, _) => {
-                        subscribed_topics.contains(topic_id)
-                    }
-                    rr_data::PubSubMsg::SubscribeTo(_) | rr_data::PubSubMsg::ListTopics | rr_data::PubSubMsg::AllTopics(_) => {
-                        unreachable!("Not broadcast")
+            broadcast = broadcast_rx.recv() => {
+                let broadcast = broadcast.unwrap();
+                let client_wants_msg = match &broadcast.recipients {
+                    // Inform everyone who may read about all new topics
+                    Recipients::Readers => access.map_or(false, Access::may_read),
+                    Recipients::Subscribers(topic_id, relevance) => subscribed_topics
+                        .get(topic_id)
+                        .map_or(false, |filter| relevance.passes(filter)),
+                    Recipients::ReadersAfterRemoving(topic_id) => {
+                        subscribed_topics.remove(topic_id);
+                        access.map_or(false, Access::may_read)
                     }
                 };
                 if client_wants_msg {
                     tracing::debug!("Passing on message");
-                    ws_sender.send(tungstenite::Message::Binary(pub_sub_msg.encode())).await?;
+                    ws_sender.send(tungstenite::Message::Binary(broadcast.encoded.clone())).await?;
                 }
             }
             _ = interval.tick() => {
-                // ws_sender.send(Message::Text("tick".to_owned())).await?;
+                if let Some(sent_stats) = &mut sent_stats {
+                    let changed = changed_stats(topics, sent_stats);
+                    if !changed.is_empty() {
+                        let pub_sub_msg = PubSubMsg::TopicStatsChanged(changed);
+                        ws_sender.send(tungstenite::Message::Binary(pub_sub_msg.encode())).await?;
+                    }
+                }
             }
         }
     }
@@ -154,10 +374,30 @@ async fn handle_connection(topics: &Topics, stream: TcpStream) -> tungstenite::R
     Ok(())
 }
 
+/// The stats that differ from `sent_stats`, which is updated to match.
+fn changed_stats(
+    topics: &Topics,
+    sent_stats: &mut HashMap<TopicId, TopicStats>,
+) -> Vec<(TopicId, TopicStats)> {
+    let topics = topics.topics.lock();
+    sent_stats.retain(|topic_id, _| topics.contains_key(topic_id));
+    let mut changed = vec![];
+    for (topic_id, topic_stream) in topics.iter() {
+        let stats = topic_stream.stats();
+        if sent_stats.insert(*topic_id, stats) != Some(stats) {
+            changed.push((*topic_id, stats));
+        }
+    }
+    changed
+}
+
 async fn on_msg(
-    subscribed_topics: &mut HashSet<rr_data::TopicId>,
+    subscribed_topics: &mut HashMap<TopicId, Filter>,
+    sent_stats: &mut Option<HashMap<TopicId, TopicStats>>,
     topics: &Topics,
-    ws_sender: &mut SplitSink<WebSocketStream<TcpStream>, tungstenite::Message>,
+    auth: &Auth,
+    access: &mut Option<Access>,
+    ws_sender: &mut WsSender,
     msg: tungstenite::Message,
 ) -> ControlFlow<()> {
     match msg {
@@ -167,7 +407,20 @@ async fn on_msg(
         }
         tungstenite::Message::Binary(binary) => {
             if let Ok(pub_sub_msg) = rr_data::PubSubMsg::decode(&binary) {
-                handle_pub_sub_msg(subscribed_topics, topics, ws_sender, pub_sub_msg).await
+                if let Err(reason) = authorize(auth, access, &pub_sub_msg) {
+                    reject(ws_sender, reason).await;
+                    return ControlFlow::Break(());
+                }
+                let access = access.expect("checked by `authorize`");
+                handle_pub_sub_msg(
+                    subscribed_topics,
+                    sent_stats,
+                    topics,
+                    access,
+                    ws_sender,
+                    pub_sub_msg,
+                )
+                .await
             } else {
                 tracing::warn!("Received unknown binary message of length {}", binary.len());
                 ControlFlow::Continue(())
@@ -199,9 +452,11 @@ async fn on_msg(
 }
 
 async fn handle_pub_sub_msg(
-    subscribed_topics: &mut HashSet<rr_data::TopicId>,
+    subscribed_topics: &mut HashMap<TopicId, Filter>,
+    sent_stats: &mut Option<HashMap<TopicId, TopicStats>>,
     topics: &Topics,
-    ws_sender: &mut SplitSink<WebSocketStream<TcpStream>, tungstenite::Message>,
+    access: Access,
+    ws_sender: &mut WsSender,
     pub_sub_msg: rr_data::PubSubMsg,
 ) -> ControlFlow<()> {
     match &pub_sub_msg {
@@ -212,44 +467,62 @@ async fn handle_pub_sub_msg(
                 .lock()
                 .insert(topic_meta.id, TopicStream::new(topic_meta.clone()));
             assert!(previous.is_none());
-            topics.tx.send(pub_sub_msg.into()).unwrap(); // tell everyone about the new topic
+            topics
+                .tx
+                .send(Broadcast::new_topic(topic_meta.clone()))
+                .unwrap(); // tell everyone about the new topic
         }
         rr_data::PubSubMsg::TopicMsg(topic_id, message) => {
             tracing::trace!("TopicMsg");
-            if let Some(topic_stream) = topics.topics.lock().get_mut(topic_id) {
-                topic_stream.messages.push(message.clone());
-            }
-            topics.tx.send(pub_sub_msg.into()).unwrap(); // tell everyone about the new message
+            let relevance = match topics.topics.lock().get_mut(topic_id) {
+                Some(topic_stream) => topic_stream.push(message.clone()),
+                None => Relevance::Context, // unknown topic: nothing to filter on
+            };
+            let broadcast = Broadcast {
+                encoded: pub_sub_msg.encode(),
+                recipients: Recipients::Subscribers(*topic_id, relevance),
+            };
+            topics.tx.send(Arc::new(broadcast)).unwrap(); // tell everyone about the new message
         }
         rr_data::PubSubMsg::SubscribeTo(topic_id) => {
             tracing::debug!("Subscribing to {:?}", topic_id);
-            let topic_stream = topics.topics.lock().get(topic_id).cloned();
-            if let Some(topic_stream) = topic_stream {
-                let messages = &topic_stream.messages;
-
-                tracing::debug!("Sending a backlog of {} messages", messages.len());
-                for message in messages {
-                    let pub_sub_msg = PubSubMsg::TopicMsg(*topic_id, message.clone());
-                    if let Err(err) = ws_sender
-                        .send(tungstenite::Message::Binary(pub_sub_msg.encode()))
-                        .await
-                    {
-                        tracing::error!("Error sending: {:?}", err);
-                        return ControlFlow::Break(());
-                    }
-                }
-            }
-            subscribed_topics.insert(*topic_id);
+            return subscribe(subscribed_topics, topics, ws_sender, *topic_id, None).await;
         }
-        rr_data::PubSubMsg::ListTopics => {
-            tracing::debug!("ListTopics");
-            let all_topic_metas = topics
+        rr_data::PubSubMsg::Subscribe(topic_id, subscription) => {
+            tracing::debug!("Subscribing to {:?}: {:?}", topic_id, subscription);
+            return subscribe(
+                subscribed_topics,
+                topics,
+                ws_sender,
+                *topic_id,
+                Some(subscription),
+            )
+            .await;
+        }
+        rr_data::PubSubMsg::UnsubscribeFrom(topic_id) => {
+            tracing::debug!("Unsubscribing from {:?}", topic_id);
+            subscribed_topics.remove(topic_id);
+        }
+        rr_data::PubSubMsg::ListTopics | rr_data::PubSubMsg::ListTopicsWithStats => {
+            tracing::debug!("{:?}", pub_sub_msg);
+            let mut all_topics: Vec<_> = topics
                 .topics
                 .lock()
                 .values()
-                .map(|ts| ts.topic_meta.clone())
+                .map(|ts| (ts.topic_meta.clone(), ts.stats()))
                 .collect();
-            let pub_sub_msg = PubSubMsg::AllTopics(all_topic_metas);
+            all_topics.sort_by_key(|(topic_meta, _)| topic_meta.created);
+            let pub_sub_msg = if matches!(pub_sub_msg, rr_data::PubSubMsg::ListTopics) {
+                PubSubMsg::AllTopics(all_topics.into_iter().map(|(meta, _)| meta).collect())
+            } else {
+                *sent_stats = Some(
+                    all_topics
+                        .iter()
+                        .map(|(topic_meta, stats)| (topic_meta.id, *stats))
+                        .collect(),
+                );
+                PubSubMsg::AllTopicsWithStats(all_topics)
+            };
             if let Err(err) = ws_sender
                 .send(tungstenite::Message::Binary(pub_sub_msg.encode()))
                 .await
@@ -258,9 +531,220 @@ async fn handle_pub_sub_msg(
                 return ControlFlow::Break(());
             }
         }
-        rr_data::PubSubMsg::AllTopics(_) => {
+        rr_data::PubSubMsg::AllTopics(_) | rr_data::PubSubMsg::AllTopicsWithStats(_) => {
             tracing::debug!("Client sent AllTopics message. Weird");
         }
+        rr_data::PubSubMsg::TopicRange(_, _) => {
+            tracing::debug!("Client sent TopicRange message. Weird");
+        }
+        rr_data::PubSubMsg::QueryTopicIndex(topic_id) => {
+            tracing::debug!("QueryTopicIndex {:?}", topic_id);
+            let topic_index = topics
+                .topics
+                .lock()
+                .get(topic_id)
+                .map(|topic_stream| topic_stream.index());
+            if let Some(topic_index) = topic_index {
+                let pub_sub_msg = PubSubMsg::TopicIndex(*topic_id, topic_index);
+                if let Err(err) = ws_sender
+                    .send(tungstenite::Message::Binary(pub_sub_msg.encode()))
+                    .await
+                {
+                    tracing::error!("Error sending: {:?}", err);
+                    return ControlFlow::Break(());
+                }
+            }
+        }
+        rr_data::PubSubMsg::TopicIndex(_, _) => {
+            tracing::debug!("Client sent TopicIndex message. Weird");
+        }
+        rr_data::PubSubMsg::Search(query) => {
+            tracing::debug!("Search: {:?}", query);
+            let snapshot = search::Snapshot::new(topics, query.clone());
+            let search_results = match tokio::task::spawn_blocking(|| snapshot.search()).await {
+                Ok(search_results) => search_results,
+                Err(err) => {
+                    tracing::error!("Search failed: {}", err);
+                    return ControlFlow::Continue(());
+                }
+            };
+            let pub_sub_msg = PubSubMsg::SearchResults(search_results);
+            if let Err(err) = ws_sender
+                .send(tungstenite::Message::Binary(pub_sub_msg.encode()))
+                .await
+            {
+                tracing::error!("Error sending: {:?}", err);
+                return ControlFlow::Break(());
+            }
+        }
+        rr_data::PubSubMsg::SearchResults(_) => {
+            tracing::debug!("Client sent SearchResults message. Weird");
+        }
+        rr_data::PubSubMsg::Authenticate(_) => {} // handled by `authorize`
+        rr_data::PubSubMsg::QueryPermissions => {
+            let pub_sub_msg = PubSubMsg::Permissions(access.permissions());
+            if let Err(err) = ws_sender
+                .send(tungstenite::Message::Binary(pub_sub_msg.encode()))
+                .await
+            {
+                tracing::error!("Error sending: {:?}", err);
+                return ControlFlow::Break(());
+            }
+        }
+        rr_data::PubSubMsg::Permissions(_) => {
+            tracing::debug!("Client sent Permissions message. Weird");
+        }
+        rr_data::PubSubMsg::TopicRemoved(_) => {
+            tracing::debug!("Client sent TopicRemoved message. Weird");
+        }
+        rr_data::PubSubMsg::TopicStatsChanged(_) => {
+            tracing::debug!("Client sent TopicStatsChanged message. Weird");
+        }
+        rr_data::PubSubMsg::QueryMessages(topic_id, query) => {
+            tracing::debug!("QueryMessages {:?}: {:?}", topic_id, query);
+            let mut parts: Vec<PubSubMsg> = match topics.topics.lock().get(topic_id) {
+                Some(topic_stream) => {
+                    let end = query.end.min(topic_stream.messages.len() as u64);
+                    let start = query.start.min(end);
+                    let part_starts = (start..end).step_by(MAX_MESSAGES_PER_PART as usize);
+                    part_starts
+                        .enumerate()
+                        .map(|(part_nr, part_start)| {
+                            let part_end = (part_start + MAX_MESSAGES_PER_PART).min(end);
+                            let messages = topic_stream.messages(
+                                part_start as usize,
+                                part_end as usize,
+                                &query.filter,
+                                part_nr == 0,
+                            );
+                            let part_query = rr_data::MessageQuery {
+                                start: part_start,
+                                end: part_end,
+                                filter: query.filter.clone(),
+                            };
+                            PubSubMsg::Messages(*topic_id, part_query, messages)
+                        })
+                        .collect()
+                }
+                None => vec![],
+            };
+            if parts.is_empty() {
+                // Always reply, so the client knows we're done:
+                parts.push(PubSubMsg::Messages(*topic_id, query.clone(), vec![]));
+            }
+            for pub_sub_msg in parts {
+                if let Err(err) = ws_sender
+                    .send(tungstenite::Message::Binary(pub_sub_msg.encode()))
+                    .await
+                {
+                    tracing::error!("Error sending: {:?}", err);
+                    return ControlFlow::Break(());
+                }
+            }
+        }
+        rr_data::PubSubMsg::Messages(_, _, _) => {
+            tracing::debug!("Client sent Messages message. Weird");
+        }
+    }
+    ControlFlow::Continue(())
+}
+
+/// May the client send this message? Also handles [`PubSubMsg::Authenticate`].
+///
+/// On error, returns why the connection should be closed.
+fn authorize(
+    auth: &Auth,
+    access: &mut Option<Access>,
+    pub_sub_msg: &PubSubMsg,
+) -> Result<(), &'static str> {
+    if let PubSubMsg::Authenticate(token) = pub_sub_msg {
+        *access = auth.access(Some(token));
+        return access.map(|_| ()).ok_or("Invalid token");
+    }
+
+    let access = access.ok_or(
+        "This server requires a token: add ?token=… to the URL or send Authenticate first",
+    )?;
+    match pub_sub_msg {
+        PubSubMsg::NewTopic(_) | PubSubMsg::TopicMsg(_, _) if !access.may_publish() => {
+            Err("This token may not publish")
+        }
+        PubSubMsg::SubscribeTo(_)
+        | PubSubMsg::Subscribe(_, _)
+        | PubSubMsg::UnsubscribeFrom(_)
+        | PubSubMsg::ListTopics
+        | PubSubMsg::ListTopicsWithStats
+        | PubSubMsg::QueryTopicIndex(_)
+        | PubSubMsg::QueryMessages(_, _)
+        | PubSubMsg::Search(_)
+            if !access.may_read() =>
+        {
+            Err("This token may not read")
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Tell the client why, then close the connection.
+///
+/// The reason is sent both as a text message and in the close frame,
+/// since not all websocket clients show the close reason.
+async fn reject(ws_sender: &mut WsSender, reason: &str) {
+    use tungstenite::protocol::{frame::coding::CloseCode, CloseFrame};
+
+    tracing::warn!("Closing connection: {}", reason);
+    let close_frame = CloseFrame {
+        code: CloseCode::Policy,
+        reason: reason.to_owned().into(),
+    };
+    let result = async {
+        ws_sender
+            .send(tungstenite::Message::Text(reason.to_owned()))
+            .await?;
+        ws_sender
+            .send(tungstenite::Message::Close(Some(close_frame)))
+            .await
+    }
+    .await;
+    if let Err(err) = result {
+        tracing::debug!("Error closing connection: {:?}", err);
+    }
+}
+
+/// Send the backlog, and then all new messages that pass the filter.
+///
+/// With `subscription: None` we send everything, and no [`PubSubMsg::TopicRange`] (for older clients).
+async fn subscribe(
+    subscribed_topics: &mut HashMap<TopicId, Filter>,
+    topics: &Topics,
+    ws_sender: &mut WsSender,
+    topic_id: TopicId,
+    subscription: Option<&Subscription>,
+) -> ControlFlow<()> {
+    let default_subscription = Subscription::default();
+    let topic_backlog =
+        topics.topics.lock().get(&topic_id).map(|topic_stream| {
+            topic_stream.backlog(subscription.unwrap_or(&default_subscription))
+        });
+    if let Some((range, messages)) = topic_backlog {
+        let range_msg = subscription.map(|_| PubSubMsg::TopicRange(topic_id, range));
+
+        tracing::debug!("Sending a backlog of {} messages", messages.len());
+        let backlog_msgs = messages
+            .into_iter()
+            .map(|message| PubSubMsg::TopicMsg(topic_id, message));
+        for pub_sub_msg in range_msg.into_iter().chain(backlog_msgs) {
+            if let Err(err) = ws_sender
+                .send(tungstenite::Message::Binary(pub_sub_msg.encode()))
+                .await
+            {
+                tracing::error!("Error sending: {:?}", err);
+                return ControlFlow::Break(());
+            }
+        }
     }
+    let filter =
+        subscription.map_or_else(Filter::default, |subscription| subscription.filter.clone());
+    subscribed_topics.insert(topic_id, filter);
     ControlFlow::Continue(())
 }
@@ -2,10 +2,91 @@
 #![warn(clippy::all, rust_2018_idioms)]
 #![allow(clippy::manual_range_contains)]
 
+const USAGE: &str = "Usage: pub_sub_server [--port PORT] [--cert PEM --key PEM]
+                      [--publish-token TOKEN]… [--read-token TOKEN]…
+
+Records log events from loggers and forwards them to viewers.
+
+  --port PORT             Port to listen on (default: 9002).
+  --cert PEM              Use TLS (wss:// and https://) with this certificate chain…
+  --key PEM               …and this private key.
+  --publish-token TOKEN   Allow clients with this token to publish topics.
+  --read-token TOKEN      Allow clients with this token to list, view and search topics.
+
+Both token flags can be repeated. Without any tokens, anyone may do anything.
+Clients pass the token as ?token=… in the URL (or in the first message);
+the HTTP API also accepts an `Authorization: Bearer …` header.";
+
 #[tokio::main]
 async fn main() {
     tracing_subscriber::fmt::init();
-    let port = rr_data::DEFAULT_PUB_SUB_PORT;
-    let server = pub_sub_server::Server::new(port).await.unwrap();
-    server.run().await.unwrap();
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, USAGE);
+            std::process::exit(2);
+        }
+    };
+    if let Err(err) = run(args).await {
+        eprintln!("{:#}", err);
+        std::process::exit(1);
+    }
+}
+
+struct Args {
+    port: u16,
+    auth: pub_sub_server::Auth,
+    cert: Option<String>,
+    key: Option<String>,
+}
+
+async fn run(args: Args) -> anyhow::Result<()> {
+    let server = pub_sub_server::Server::new(args.port)
+        .await?
+        .with_auth(args.auth);
+    let server = match (args.cert, args.key) {
+        (None, None) => server,
+        #[cfg(feature = "tls")]
+        (Some(cert), Some(key)) => server.with_tls(cert, key)?,
+        #[cfg(not(feature = "tls"))]
+        (Some(_), Some(_)) => anyhow::bail!("Compiled without the \"tls\" feature"),
+        _ => anyhow::bail!("--cert and --key go together"),
+    };
+    server.run().await
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut port = rr_data::DEFAULT_PUB_SUB_PORT;
+    let mut auth = pub_sub_server::Auth::default();
+    let (mut cert, mut key) = (None, None);
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("Missing value for {}", arg))
+        };
+        match arg.as_str() {
+            "--port" => {
+                let value = value()?;
+                port = value
+                    .parse()
+                    .map_err(|err| format!("Bad --port {:?}: {}", value, err))?;
+            }
+            "--cert" => cert = Some(value()?),
+            "--key" => key = Some(value()?),
+            "--publish-token" => auth.publish_tokens.push(value()?),
+            "--read-token" => auth.read_tokens.push(value()?),
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                std::process::exit(0);
+            }
+            _ => return Err(format!("Unknown argument {:?}", arg)),
+        }
+    }
+    Ok(Args {
+        port,
+        auth,
+        cert,
+        key,
+    })
 }
@@ -0,0 +1,129 @@
+//! Full-text search of the [`rr_data::DataEvent`]s of stored topics.
+
+use rr_data::{CallsiteId, MessageEnum, SearchHit, SearchQuery, SearchResults, TopicMeta};
+use std::{collections::HashMap, sync::Arc};
+
+use crate::Topics;
+
+/// How many bytes of context to show on each side of a match.
+const SNIPPET_CONTEXT: usize = 40;
+
+/// A copy of the topics to search, so that we don't block the topics while searching.
+pub struct Snapshot {
+    query: SearchQuery,
+    /// Newest first.
+    topics: Vec<TopicSnapshot>,
+}
+
+struct TopicSnapshot {
+    topic_meta: TopicMeta,
+    messages: Vec<Arc<[u8]>>,
+    callsite_names: HashMap<CallsiteId, String>,
+}
+
+impl Snapshot {
+    pub fn new(topics: &Topics, query: SearchQuery) -> Self {
+        let mut topic_snapshots: Vec<TopicSnapshot> = topics
+            .topics
+            .lock()
+            .values()
+            .filter(|topic_stream| {
+                query
+                    .topic_id
+                    .map_or(true, |topic_id| topic_id == topic_stream.topic_meta.id)
+            })
+            .map(|topic_stream| TopicSnapshot {
+                topic_meta: topic_stream.topic_meta.clone(),
+                messages: topic_stream.messages.clone(),
+                callsite_names: topic_stream.callsite_names(),
+            })
+            .collect();
+        topic_snapshots.sort_by_key(|topic| std::cmp::Reverse(topic.topic_meta.created));
+        Self {
+            query,
+            topics: topic_snapshots,
+        }
+    }
+
+    /// This can be slow, so call it outside of the async runtime.
+    pub fn search(self) -> SearchResults {
+        let needle = self.query.text.to_lowercase();
+        let max_hits = self.query.max_hits as usize;
+        let mut hits = vec![];
+        let mut truncated = false;
+
+        'topics: for topic in &self.topics {
+            for (index, message) in topic.messages.iter().enumerate() {
+                let message = match rr_data::Message::decode(message) {
+                    Ok(message) => message,
+                    Err(_) => continue,
+                };
+                let data_event = match &message.msg_enum {
+                    MessageEnum::DataEvent(data_event) => data_event,
+                    _ => continue,
+                };
+                let callsite_name = topic
+                    .callsite_names
+                    .get(&data_event.callsite_id)
+                    .cloned()
+                    .unwrap_or_default();
+
+                let field_snippets = data_event
+                    .fields
+                    .iter()
+                    .filter_map(|(name, value)| snippet(&format!("{}={}", name, value), &needle));
+                let snippet = field_snippets
+                    .chain(std::iter::once_with(|| snippet(&callsite_name, &needle)).flatten())
+                    .next();
+
+                if let Some(snippet) = snippet {
+                    if hits.len() == max_hits {
+                        truncated = true;
+                        break 'topics;
+                    }
+                    hits.push(SearchHit {
+                        topic_id: topic.topic_meta.id,
+                        index: index as u64,
+                        log_time: message.log_time,
+                        callsite_name,
+                        snippet,
+                    });
+                }
+            }
+        }
+
+        SearchResults {
+            query: self.query,
+            hits,
+            truncated,
+        }
+    }
+}
+
+/// The text around the first case-insensitive match of `needle_lower`, if any.
+fn snippet(text: &str, needle_lower: &str) -> Option<String> {
+    let lower = text.to_lowercase();
+    let start = lower.find(needle_lower)?;
+    // Lowercasing can change the length of some characters:
+    let text = if lower.len() == text.len() {
+        text
+    } else {
+        lower.as_str()
+    };
+
+    let mut from = start.saturating_sub(SNIPPET_CONTEXT);
+    while !text.is_char_boundary(from) {
+        from -= 1;
+    }
+    let mut to = (start + needle_lower.len() + SNIPPET_CONTEXT).min(text.len());
+    while !text.is_char_boundary(to) {
+        to += 1;
+    }
+
+    Some(format!(
+        "{}{}{}",
+        if from > 0 { "…" } else { "" },
+        &text[from..to],
+        if to < text.len() { "…" } else { "" }
+    ))
+}
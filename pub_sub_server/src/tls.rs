@@ -0,0 +1,46 @@
+//! TLS termination, so that clients can connect with `wss://` (and `https://` for the viewer and HTTP API).
+
+use anyhow::Context as _;
+use std::{fs::File, io::BufReader, path::Path, sync::Arc};
+use tokio_rustls::{rustls, TlsAcceptor};
+
+/// From a PEM file with the certificate chain, and a PEM file with its private key.
+pub fn acceptor(cert_path: &Path, key_path: &Path) -> anyhow::Result<TlsAcceptor> {
+    let certs = load_certs(cert_path)?;
+    let key = load_key(key_path)?;
+    let config = rustls::ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .context("Bad certificate or key")?;
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+fn load_certs(path: &Path) -> anyhow::Result<Vec<rustls::Certificate>> {
+    let mut reader = open(path)?;
+    let certs = rustls_pemfile::certs(&mut reader)
+        .with_context(|| format!("Bad certificate file {:?}", path))?;
+    anyhow::ensure!(!certs.is_empty(), "No certificates in {:?}", path);
+    Ok(certs.into_iter().map(rustls::Certificate).collect())
+}
+
+/// The first private key in the file.
+fn load_key(path: &Path) -> anyhow::Result<rustls::PrivateKey> {
+    let mut reader = open(path)?;
+    loop {
+        let item = rustls_pemfile::read_one(&mut reader)
+            .with_context(|| format!("Bad key file {:?}", path))?;
+        match item {
+            Some(rustls_pemfile::Item::RSAKey(key))
+            | Some(rustls_pemfile::Item::PKCS8Key(key))
+            | Some(rustls_pemfile::Item::ECKey(key)) => return Ok(rustls::PrivateKey(key)),
+            Some(_) => {}
+            None => anyhow::bail!("No private key in {:?}", path),
+        }
+    }
+}
+
+fn open(path: &Path) -> anyhow::Result<BufReader<File>> {
+    let file = File::open(path).with_context(|| format!("Can't open {:?}", path))?;
+    Ok(BufReader::new(file))
+}
@@ -0,0 +1,318 @@
+use rr_data::{
+    Backlog, Callsite, CallsiteId, Filter, MessageEnum, SpanId, SpanSummary, Subscription, Time,
+    TopicIndex, TopicMeta, TopicRange, TopicStats,
+};
+use std::{collections::HashMap, sync::Arc};
+
+/// All the messages of a topic, and indices of what's in them.
+pub(crate) struct TopicStream {
+    pub topic_meta: TopicMeta,
+
+    /// Each an encoded [`rr_data::Message`].
+    pub messages: Vec<Arc<[u8]>>,
+
+    /// The log time of each message.
+    log_times: Vec<Time>,
+
+    /// Indices of the [`MessageEnum::NewCallsite`] messages.
+    callsites: Vec<usize>,
+
+    /// For filtering.
+    callsite_index: HashMap<CallsiteId, Arc<Callsite>>,
+
+    /// The callsite of each span, and the indices of its
+    /// [`MessageEnum::NewSpan`] and [`MessageEnum::DestroySpan`] messages.
+    spans: HashMap<SpanId, SpanInfo>,
+
+    stats: TopicStats,
+}
+
+#[derive(Clone, Copy)]
+struct SpanInfo {
+    parent_span_id: Option<SpanId>,
+    callsite_id: CallsiteId,
+    created: usize,
+    destroyed: Option<usize>,
+}
+
+impl TopicStream {
+    pub fn new(topic_meta: TopicMeta) -> Self {
+        Self {
+            topic_meta,
+            messages: Default::default(),
+            log_times: Default::default(),
+            callsites: Default::default(),
+            callsite_index: Default::default(),
+            spans: Default::default(),
+            stats: Default::default(),
+        }
+    }
+
+    /// Returns what subscription filters need to know about the message.
+    pub fn push(&mut self, message: Arc<[u8]>) -> Relevance {
+        let index = self.messages.len();
+        let relevance;
+        match rr_data::Message::decode(&message) {
+            Ok(decoded) => {
+                relevance = self.relevance(&decoded.msg_enum);
+                self.log_times.push(decoded.log_time);
+                let log_time = decoded.log_time;
+                self.stats.time_range = Some(match self.stats.time_range {
+                    Some((min, max)) => (min.min(log_time), max.max(log_time)),
+                    None => (log_time, log_time),
+                });
+                match decoded.msg_enum {
+                    MessageEnum::NewCallsite(callsite) => {
+                        self.callsites.push(index);
+                        self.callsite_index.insert(callsite.id, Arc::new(callsite));
+                    }
+                    MessageEnum::NewSpan(span) => {
+                        self.stats.num_spans += 1;
+                        let span_info = SpanInfo {
+                            parent_span_id: span.parent_span_id,
+                            callsite_id: span.callsite_id,
+                            created: index,
+                            destroyed: None,
+                        };
+                        self.spans.insert(span.id, span_info);
+                    }
+                    MessageEnum::DestroySpan(span_id) => {
+                        if let Some(span_info) = self.spans.get_mut(&span_id) {
+                            span_info.destroyed = Some(index);
+                        }
+                    }
+                    MessageEnum::DataEvent(data_event) => {
+                        if let Some(callsite) = self.callsite_index.get(&data_event.callsite_id) {
+                            self.stats.events_per_level[callsite.level as usize] += 1;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Err(err) => {
+                tracing::warn!("Bad message in topic {:?}: {}", self.topic_meta.name, err);
+                relevance = Relevance::Garbage;
+                let log_time = self
+                    .log_times
+                    .last()
+                    .copied()
+                    .unwrap_or(self.topic_meta.created);
+                self.log_times.push(log_time);
+            }
+        }
+        self.stats.num_messages += 1;
+        self.stats.num_bytes += message.len() as u64;
+        self.messages.push(message);
+        relevance
+    }
+
+    pub fn stats(&self) -> TopicStats {
+        self.stats
+    }
+
+    pub fn callsite_names(&self) -> HashMap<CallsiteId, String> {
+        self.callsite_index
+            .iter()
+            .map(|(id, callsite)| (*id, callsite.name.clone()))
+            .collect()
+    }
+
+    pub fn index(&self) -> TopicIndex {
+        let mut callsites: Vec<Callsite> = self
+            .callsite_index
+            .values()
+            .map(|callsite| Callsite::clone(callsite))
+            .collect();
+        callsites.sort_by(|a, b| {
+            (&a.location.module, &a.name, a.id.0).cmp(&(&b.location.module, &b.name, b.id.0))
+        });
+
+        let mut spans: Vec<(usize, SpanSummary)> = self
+            .spans
+            .iter()
+            .map(|(span_id, span_info)| {
+                let span_summary = SpanSummary {
+                    id: *span_id,
+                    parent_span_id: span_info.parent_span_id,
+                    callsite_id: span_info.callsite_id,
+                    created: self.log_times[span_info.created],
+                    destroyed: span_info.destroyed.map(|index| self.log_times[index]),
+                };
+                (span_info.created, span_summary)
+            })
+            .collect();
+        spans.sort_by_key(|(created, _)| *created);
+
+        TopicIndex {
+            stats: self.stats,
+            callsites,
+            spans: spans
+                .into_iter()
+                .map(|(_, span_summary)| span_summary)
+                .collect(),
+        }
+    }
+
+    /// What to send a new subscriber, in order.
+    pub fn backlog(&self, subscription: &Subscription) -> (TopicRange, Vec<Arc<[u8]>>) {
+        let num_messages = self.messages.len();
+        let start = match subscription.backlog {
+            Backlog::All => 0,
+            Backlog::None => num_messages,
+            Backlog::FromIndex(index) => (index as usize).min(num_messages),
+            Backlog::FromTime(time) => self
+                .log_times
+                .iter()
+                .position(|&log_time| log_time >= time)
+                .unwrap_or(num_messages),
+            Backlog::Last(count) => num_messages.saturating_sub(count as usize),
+        };
+
+        let range = TopicRange {
+            num_messages: num_messages as u64,
+            backlog_start: start as u64,
+            time_range: self
+                .log_times
+                .first()
+                .zip(self.log_times.last())
+                .map(|(first, last)| (*first, *last)),
+        };
+
+        let messages = self.messages(start, num_messages, &subscription.filter, true);
+        (range, messages)
+    }
+
+    /// The messages with index in `start..end` that pass the filter.
+    ///
+    /// With `with_context`, they are preceded by the callsites and live spans from before `start`.
+    pub fn messages(
+        &self,
+        start: usize,
+        end: usize,
+        filter: &Filter,
+        with_context: bool,
+    ) -> Vec<Arc<[u8]>> {
+        let end = end.min(self.messages.len());
+        let start = start.min(end);
+        let context = with_context
+            .then(|| self.context(start))
+            .into_iter()
+            .flatten();
+        context
+            .chain(self.slice(start, end, filter))
+            .cloned()
+            .collect()
+    }
+
+    /// The callsites and live spans from before the start, so that what follows makes sense.
+    fn context(&self, start: usize) -> impl Iterator<Item = &Arc<[u8]>> + '_ {
+        let mut structure: Vec<usize> = self
+            .callsites
+            .iter()
+            .copied()
+            .take_while(|&index| index < start)
+            .collect();
+        structure.extend(
+            self.spans
+                .values()
+                .filter(|span_info| {
+                    span_info.created < start
+                        && span_info
+                            .destroyed
+                            .map_or(true, |destroyed| destroyed >= start)
+                })
+                .map(|span_info| span_info.created),
+        );
+        structure.sort_unstable();
+        structure
+            .into_iter()
+            .map(move |index| &self.messages[index])
+    }
+
+    fn slice<'a>(
+        &'a self,
+        start: usize,
+        end: usize,
+        filter: &'a Filter,
+    ) -> impl Iterator<Item = &'a Arc<[u8]>> + 'a {
+        self.messages[start..end]
+            .iter()
+            .filter(move |message| self.passes_encoded(filter, message))
+    }
+
+    /// Should a subscriber with this filter get this encoded [`rr_data::Message`]?
+    fn passes_encoded(&self, filter: &Filter, message: &[u8]) -> bool {
+        if filter.lets_everything_through() {
+            return true;
+        }
+        match rr_data::Message::decode(message) {
+            Ok(message) => self.relevance(&message.msg_enum).passes(filter),
+            Err(_) => false,
+        }
+    }
+
+    fn relevance(&self, msg_enum: &MessageEnum) -> Relevance {
+        let callsite = |callsite_id: &CallsiteId| self.callsite_index.get(callsite_id).cloned();
+        let span_callsite = |span_id: &SpanId| {
+            self.spans
+                .get(span_id)
+                .and_then(|span_info| callsite(&span_info.callsite_id))
+        };
+
+        match msg_enum {
+            MessageEnum::NewCallsite(_)
+            | MessageEnum::NewSpan(_)
+            | MessageEnum::DestroySpan(_)
+            | MessageEnum::SpanFollowsFrom { .. } => Relevance::Context,
+            MessageEnum::EnterSpan(span_id) | MessageEnum::ExitSpan(span_id) => {
+                Relevance::SpanActivity(span_callsite(span_id))
+            }
+            MessageEnum::DataEvent(data_event) => {
+                Relevance::DataEvent(callsite(&data_event.callsite_id))
+            }
+            MessageEnum::Metric(metric) => Relevance::Metric(callsite(&metric.callsite_id)),
+        }
+    }
+}
+
+// ----------------------------------------------------------------------------
+
+/// What a subscription [`Filter`] needs to know about a message.
+///
+/// Worked out once per message, so that subscribers can filter
+/// without decoding the message or looking up its callsite.
+#[derive(Clone, Debug)]
+pub(crate) enum Relevance {
+    /// Callsites and span lifetimes, which every subscriber needs to make sense of the rest.
+    Context,
+
+    /// Entering or exiting a span with this callsite (if known).
+    SpanActivity(Option<Arc<Callsite>>),
+
+    /// An event from this callsite (if known).
+    DataEvent(Option<Arc<Callsite>>),
+
+    /// A metric from this callsite (if known).
+    Metric(Option<Arc<Callsite>>),
+
+    /// Not a valid [`rr_data::Message`].
+    Garbage,
+}
+
+impl Relevance {
+    /// Should a subscriber with this filter get the message?
+    pub fn passes(&self, filter: &Filter) -> bool {
+        let callsite_passes = |callsite: &Option<Arc<Callsite>>| {
+            callsite
+                .as_ref()
+                .map_or(true, |callsite| filter.accepts_callsite(callsite))
+        };
+        match self {
+            Self::Context => true,
+            Self::SpanActivity(callsite) => !filter.skip_span_activity && callsite_passes(callsite),
+            Self::DataEvent(callsite) => !filter.skip_events && callsite_passes(callsite),
+            Self::Metric(callsite) => !filter.skip_metrics && callsite_passes(callsite),
+            Self::Garbage => filter.lets_everything_through(),
+        }
+    }
+}
@@ -0,0 +1,142 @@
+//! A logger publishing to a server over `wss://`, trusting only the server's self-signed certificate.
+
+#![cfg(feature = "tls")]
+
+use ewebsock::{WsEvent, WsMessage};
+use rr_data::PubSubMsg;
+use std::{sync::Arc, time::Duration};
+use ws_client::RootCerts;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn logger_over_wss() {
+    let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_owned()]).unwrap();
+    let cert_pem = cert.serialize_pem().unwrap();
+    let dir = std::env::temp_dir().join(format!("pub_sub_server_tls_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (cert_path, key_path) = (dir.join("cert.pem"), dir.join("key.pem"));
+    std::fs::write(&cert_path, &cert_pem).unwrap();
+    std::fs::write(&key_path, cert.serialize_private_key_pem()).unwrap();
+
+    let server = pub_sub_server::Server::new(0)
+        .await
+        .unwrap()
+        .with_tls(&cert_path, &key_path)
+        .unwrap();
+    let url = format!("wss://localhost:{}", server.local_addr().unwrap().port());
+    tokio::spawn(server.run());
+    std::fs::remove_dir_all(&dir).ok();
+
+    let root_certs = RootCerts::from_pem(cert_pem.as_bytes()).unwrap();
+
+    // Without the certificate, the handshake fails:
+    let (mut untrusting, mut events) = connect(&url, &RootCerts::default());
+    untrusting.send(WsMessage::Binary(PubSubMsg::ListTopics.encode()));
+    match next_event(&mut events).await {
+        WsEvent::Error(err) => assert!(err.contains("certificate"), "{}", err),
+        event => panic!("Expected a certificate error, got {:?}", event),
+    }
+
+    // A viewer that is told about new topics:
+    let (mut viewer, mut events) = connect(&url, &root_certs);
+    viewer.send(WsMessage::Binary(PubSubMsg::ListTopics.encode()));
+    assert!(matches!(next_event(&mut events).await, WsEvent::Opened));
+    assert!(matches!(
+        next_msg(&mut events).await,
+        PubSubMsg::AllTopics(_)
+    ));
+
+    let topic_meta = rr_data::TopicMeta {
+        id: rr_data::TopicId::random(),
+        created: rr_data::Time::now(),
+        name: "tls test".into(),
+    };
+    let options = logger::ConnectOptions {
+        root_certs,
+        ..Default::default()
+    };
+    let rr_logger =
+        logger::RrLogger::to_pub_sub_server_with_options(url.clone(), options, topic_meta.clone());
+    let span_id = rr_data::SpanId(42);
+    rr_logger.send(rr_data::Message::now(rr_data::MessageEnum::EnterSpan(
+        span_id,
+    )));
+
+    match next_msg(&mut events).await {
+        PubSubMsg::NewTopic(new_topic) => assert_eq!(new_topic.id, topic_meta.id),
+        msg => panic!("Expected NewTopic, got {:?}", msg),
+    }
+    viewer.send(WsMessage::Binary(
+        PubSubMsg::SubscribeTo(topic_meta.id).encode(),
+    ));
+    loop {
+        match next_msg(&mut events).await {
+            PubSubMsg::TopicRange(_, _) => {}
+            PubSubMsg::TopicMsg(topic_id, payload) => {
+                assert_eq!(topic_id, topic_meta.id);
+                let message = rr_data::Message::decode(&payload).unwrap();
+                assert!(
+                    matches!(message.msg_enum, rr_data::MessageEnum::EnterSpan(id) if id == span_id)
+                );
+                break;
+            }
+            msg => panic!("Expected the logged message, got {:?}", msg),
+        }
+    }
+
+    // Once asked for stats, the server keeps them fresh:
+    viewer.send(WsMessage::Binary(PubSubMsg::ListTopicsWithStats.encode()));
+    match next_msg(&mut events).await {
+        PubSubMsg::AllTopicsWithStats(all_topics) => {
+            assert_eq!(all_topics.len(), 1);
+            assert_eq!(all_topics[0].1.num_messages, 1);
+        }
+        msg => panic!("Expected AllTopicsWithStats, got {:?}", msg),
+    }
+    rr_logger.send(rr_data::Message::now(rr_data::MessageEnum::ExitSpan(
+        span_id,
+    )));
+    loop {
+        match next_msg(&mut events).await {
+            PubSubMsg::TopicMsg(_, _) => {}
+            PubSubMsg::TopicStatsChanged(changed) => {
+                assert_eq!(changed.len(), 1);
+                assert_eq!(changed[0].0, topic_meta.id);
+                assert_eq!(changed[0].1.num_messages, 2);
+                break;
+            }
+            msg => panic!("Expected TopicStatsChanged, got {:?}", msg),
+        }
+    }
+}
+
+fn connect(
+    url: &str,
+    root_certs: &RootCerts,
+) -> (
+    ws_client::WsSender,
+    tokio::sync::mpsc::UnboundedReceiver<WsEvent>,
+) {
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    let on_event: ewebsock::EventHandler = Arc::new(move |event| {
+        tx.send(event).ok();
+        std::ops::ControlFlow::Continue(())
+    });
+    (
+        ws_client::ws_connect(url.to_owned(), root_certs, on_event),
+        rx,
+    )
+}
+
+async fn next_event(events: &mut tokio::sync::mpsc::UnboundedReceiver<WsEvent>) -> WsEvent {
+    tokio::time::timeout(Duration::from_secs(10), events.recv())
+        .await
+        .expect("timeout")
+        .expect("connection task ended")
+}
+
+async fn next_msg(events: &mut tokio::sync::mpsc::UnboundedReceiver<WsEvent>) -> PubSubMsg {
+    match next_event(events).await {
+        WsEvent::Message(WsMessage::Binary(payload)) => PubSubMsg::decode(&payload).unwrap(),
+        event => panic!("Expected a message, got {:?}", event),
+    }
+}
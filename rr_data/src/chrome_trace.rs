@@ -0,0 +1,420 @@
+//! Conversion to and from the [Chrome Trace Event format](https://docs.google.com/document/d/1CvAClvFfyA5R-PhYUmn5OOQtYMH4h6I0nSsKchNAySU).
+
+mod export;
+mod import;
+
+pub use export::export;
+pub use import::import;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Message, MessageEnum, SpanId};
+    use serde_json::{json, Value as Json};
+
+    fn span_name(messages: &[Message], span_id: SpanId) -> String {
+        let callsite_id = messages
+            .iter()
+            .find_map(|message| match &message.msg_enum {
+                MessageEnum::NewSpan(span) if span.id == span_id => Some(span.callsite_id),
+                _ => None,
+            })
+            .unwrap();
+        messages
+            .iter()
+            .find_map(|message| match &message.msg_enum {
+                MessageEnum::NewCallsite(callsite) if callsite.id == callsite_id => {
+                    Some(callsite.name.clone())
+                }
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    /// `(name, parent name, enter ns, exit ns)` of each span.
+    fn spans(messages: &[Message]) -> Vec<(String, Option<String>, i64, i64)> {
+        let mut spans = vec![];
+        for message in messages {
+            if let MessageEnum::NewSpan(span) = &message.msg_enum {
+                let time_of = |wanted: fn(&MessageEnum) -> Option<SpanId>| {
+                    messages
+                        .iter()
+                        .find(|message| wanted(&message.msg_enum) == Some(span.id))
+                        .unwrap()
+                        .log_time
+                        .nanos_since_epoch()
+                };
+                spans.push((
+                    span_name(messages, span.id),
+                    span.parent_span_id
+                        .map(|parent| span_name(messages, parent)),
+                    time_of(|msg| match msg {
+                        MessageEnum::EnterSpan(id) => Some(*id),
+                        _ => None,
+                    }),
+                    time_of(|msg| match msg {
+                        MessageEnum::ExitSpan(id) => Some(*id),
+                        _ => None,
+                    }),
+                ));
+            }
+        }
+        spans
+    }
+
+    /// `(parent name, ns)` of each data event.
+    fn events(messages: &[Message]) -> Vec<(Option<String>, i64)> {
+        messages
+            .iter()
+            .filter_map(|message| match &message.msg_enum {
+                MessageEnum::DataEvent(event) => Some((
+                    event.parent_span_id.map(|id| span_name(messages, id)),
+                    message.log_time.nanos_since_epoch(),
+                )),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// `(span name, follows name)` of each follows-from relationship.
+    fn follows(messages: &[Message]) -> Vec<(String, String)> {
+        messages
+            .iter()
+            .filter_map(|message| match &message.msg_enum {
+                MessageEnum::SpanFollowsFrom { span, follows } => {
+                    Some((span_name(messages, *span), span_name(messages, *follows)))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn owned(name: &str) -> Option<String> {
+        Some(name.to_owned())
+    }
+
+    #[test]
+    fn import_begin_end_pairs() {
+        let messages = import(
+            &json!([
+                { "ph": "B", "name": "outer", "ts": 1, "pid": 1, "tid": 1 },
+                { "ph": "B", "name": "inner", "ts": 2, "pid": 1, "tid": 1 },
+                { "ph": "B", "name": "other thread", "ts": 2.5, "pid": 1, "tid": 2 },
+                { "ph": "E", "ts": 3, "pid": 1, "tid": 1 },
+                { "ph": "E", "ts": 4, "pid": 1, "tid": 1 },
+                { "ph": "E", "ts": 5, "pid": 1, "tid": 2 },
+            ])
+            .to_string(),
+        )
+        .unwrap();
+        assert_eq!(
+            spans(&messages),
+            vec![
+                ("outer".to_owned(), None, 1_000, 4_000),
+                ("inner".to_owned(), owned("outer"), 2_000, 3_000),
+                ("other thread".to_owned(), None, 2_500, 5_000),
+            ]
+        );
+    }
+
+    #[test]
+    fn import_complete_events() {
+        let messages = import(
+            &json!({ "traceEvents": [
+                { "ph": "X", "name": "child", "ts": 20, "dur": 10, "pid": 1, "tid": 1 },
+                { "ph": "X", "name": "parent", "ts": 10, "dur": 30, "pid": 1, "tid": 1 },
+                { "ph": "X", "name": "sibling", "ts": 40, "dur": 5, "pid": 1, "tid": 1 },
+            ]})
+            .to_string(),
+        )
+        .unwrap();
+        assert_eq!(
+            spans(&messages),
+            vec![
+                ("parent".to_owned(), None, 10_000, 40_000),
+                ("child".to_owned(), owned("parent"), 20_000, 30_000),
+                ("sibling".to_owned(), None, 40_000, 45_000),
+            ]
+        );
+    }
+
+    #[test]
+    fn import_instant_events() {
+        let messages = import(
+            &json!([
+                { "ph": "X", "name": "span", "ts": 10, "dur": 10, "pid": 1, "tid": 1 },
+                { "ph": "i", "name": "in span", "ts": 15, "pid": 1, "tid": 1 },
+                { "ph": "I", "name": "legacy", "ts": 10, "pid": 1, "tid": 1, "s": "t" },
+                { "ph": "i", "name": "global", "ts": 15, "pid": 1, "tid": 1, "s": "g" },
+                { "ph": "i", "name": "after", "ts": 25, "pid": 1, "tid": 1 },
+            ])
+            .to_string(),
+        )
+        .unwrap();
+        assert_eq!(
+            events(&messages),
+            vec![
+                (owned("span"), 10_000),
+                (owned("span"), 15_000),
+                (None, 15_000),
+                (None, 25_000),
+            ]
+        );
+    }
+
+    #[test]
+    fn import_flows() {
+        let messages = import(
+            &json!([
+                { "ph": "X", "name": "spawner", "ts": 0, "dur": 10, "pid": 1, "tid": 1 },
+                { "ph": "s", "name": "flow", "cat": "c", "id": 7, "ts": 5, "pid": 1, "tid": 1 },
+                { "ph": "X", "name": "task", "ts": 20, "dur": 10, "pid": 1, "tid": 2 },
+                { "ph": "f", "name": "flow", "cat": "c", "id": 7, "ts": 25, "pid": 1, "tid": 2 },
+                // Same id, but another category, so another flow without a start:
+                { "ph": "f", "name": "flow", "cat": "d", "id": 7, "ts": 25, "pid": 1, "tid": 2 },
+            ])
+            .to_string(),
+        )
+        .unwrap();
+        assert_eq!(
+            follows(&messages),
+            vec![("task".to_owned(), "spawner".to_owned())]
+        );
+    }
+
+    #[test]
+    fn import_ignores_unmatched_end() {
+        let messages = import(
+            &json!([
+                { "ph": "E", "ts": 1, "pid": 1, "tid": 1 },
+                { "ph": "B", "name": "span", "ts": 2, "pid": 1, "tid": 1 },
+                { "ph": "E", "ts": 3, "pid": 1, "tid": 1 },
+                { "ph": "E", "ts": 4, "pid": 1, "tid": 1 },
+            ])
+            .to_string(),
+        )
+        .unwrap();
+        assert_eq!(
+            spans(&messages),
+            vec![("span".to_owned(), None, 2_000, 3_000)]
+        );
+    }
+
+    #[test]
+    fn import_huge_times() {
+        let huge = 1e18;
+        let messages = import(
+            &json!([
+                { "ph": "X", "name": "span", "ts": huge, "dur": huge, "pid": 1, "tid": 1 },
+                { "ph": "B", "name": "open", "ts": huge, "pid": 1, "tid": 2 },
+            ])
+            .to_string(),
+        )
+        .unwrap();
+        for (_, _, enter_ns, exit_ns) in spans(&messages) {
+            assert!(enter_ns <= exit_ns);
+        }
+    }
+
+    #[test]
+    fn import_rejects_other_json() {
+        assert!(import("{}").is_err());
+        assert!(import("42").is_err());
+        assert!(import("not json").is_err());
+    }
+
+    // ------------------------------------------------------------------------
+
+    fn trace_events(json: &str) -> Vec<Json> {
+        let trace: Json = serde_json::from_str(json).unwrap();
+        trace["traceEvents"].as_array().unwrap().clone()
+    }
+
+    fn of_phase<'a>(trace_events: &'a [Json], phase: &str) -> Vec<&'a Json> {
+        trace_events
+            .iter()
+            .filter(|trace_event| trace_event["ph"] == phase)
+            .collect()
+    }
+
+    #[test]
+    fn export_logged_messages() {
+        use crate::{
+            Callsite, CallsiteId, CallsiteKind, DataEvent, Location, LogLevel, Span, Time, Value,
+        };
+
+        let callsite = |id, kind, name: &str| {
+            MessageEnum::NewCallsite(Callsite {
+                id: CallsiteId(id),
+                kind,
+                name: name.to_owned(),
+                level: LogLevel::Warn,
+                location: Location {
+                    module: "test".to_owned(),
+                    file: None,
+                    line: None,
+                },
+                field_names: vec![],
+            })
+        };
+        let new_span = |id, parent: Option<u64>, callsite_id| {
+            MessageEnum::NewSpan(Span {
+                id: SpanId(id),
+                parent_span_id: parent.map(SpanId),
+                callsite_id: CallsiteId(callsite_id),
+                fields: vec![],
+            })
+        };
+        let event = |parent: Option<u64>| {
+            MessageEnum::DataEvent(DataEvent {
+                callsite_id: CallsiteId(3),
+                parent_span_id: parent.map(SpanId),
+                fields: vec![("answer".to_owned(), Value::I64(42))],
+            })
+        };
+        let (request, task) = (SpanId(1), SpanId(2));
+        let messages: Vec<Message> = vec![
+            (0, callsite(1, CallsiteKind::Span, "request")),
+            (0, callsite(2, CallsiteKind::Span, "task")),
+            (0, callsite(3, CallsiteKind::Event, "done")),
+            (1_000, new_span(1, None, 1)),
+            (1_000, MessageEnum::EnterSpan(request)),
+            (2_000, event(Some(1))),
+            (3_000, new_span(2, Some(1), 2)),
+            (
+                3_000,
+                MessageEnum::SpanFollowsFrom {
+                    span: task,
+                    follows: request,
+                },
+            ),
+            (4_000, MessageEnum::ExitSpan(request)),
+            // The task runs after the request is exited, so it gets a thread of its own:
+            (5_000, MessageEnum::EnterSpan(task)),
+            (7_000, MessageEnum::ExitSpan(task)),
+            // Never exited:
+            (8_000, MessageEnum::EnterSpan(request)),
+            (9_000, event(None)),
+        ]
+        .into_iter()
+        .map(|(ns, msg_enum)| Message {
+            log_time: Time::from_nanos_since_epoch(ns),
+            msg_enum,
+        })
+        .collect();
+
+        let trace_events = trace_events(&export(&messages, "logged"));
+
+        let slices: Vec<(&str, f64, f64, u64)> = of_phase(&trace_events, "X")
+            .iter()
+            .map(|x| {
+                (
+                    x["name"].as_str().unwrap(),
+                    x["ts"].as_f64().unwrap(),
+                    x["dur"].as_f64().unwrap(),
+                    x["tid"].as_u64().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            slices,
+            vec![
+                ("request", 0.0, 3.0, 1),
+                ("request", 7.0, 1.0, 1), // open until the last message
+                ("task", 4.0, 2.0, 2),
+            ]
+        );
+
+        let instants: Vec<(f64, u64)> = of_phase(&trace_events, "i")
+            .iter()
+            .map(|i| (i["ts"].as_f64().unwrap(), i["tid"].as_u64().unwrap()))
+            .collect();
+        assert_eq!(instants, vec![(1.0, 1), (8.0, 0)]);
+        let instant = &of_phase(&trace_events, "i")[0];
+        assert_eq!(instant["name"], "done");
+        assert_eq!(instant["cat"], "Warn");
+        assert_eq!(instant["args"]["answer"], 42);
+
+        let (flow_starts, flow_ends) = (of_phase(&trace_events, "s"), of_phase(&trace_events, "f"));
+        assert_eq!((flow_starts.len(), flow_ends.len()), (1, 1));
+        assert_eq!(flow_starts[0]["id"], flow_ends[0]["id"]);
+        assert_eq!(
+            (&flow_starts[0]["ts"], &flow_starts[0]["tid"]),
+            (&json!(0.0), &json!(1))
+        );
+        assert_eq!(
+            (&flow_ends[0]["ts"], &flow_ends[0]["tid"]),
+            (&json!(4.0), &json!(2))
+        );
+    }
+
+    #[test]
+    fn export_then_import() {
+        let original = import(
+            &json!([
+                { "ph": "X", "name": "main", "ts": 100, "dur": 50, "pid": 1, "tid": 1 },
+                { "ph": "B", "name": "work", "ts": 110, "pid": 1, "tid": 1 },
+                { "ph": "i", "name": "progress", "ts": 115, "pid": 1, "tid": 1 },
+                { "ph": "E", "ts": 120, "pid": 1, "tid": 1 },
+                { "ph": "s", "name": "spawn", "id": 1, "ts": 130, "pid": 1, "tid": 1 },
+                { "ph": "X", "name": "task", "ts": 140, "dur": 20, "pid": 1, "tid": 2 },
+                { "ph": "f", "name": "spawn", "id": 1, "ts": 140, "pid": 1, "tid": 2 },
+            ])
+            .to_string(),
+        )
+        .unwrap();
+
+        let exported = export(&original, "round trip");
+        let trace_events = trace_events(&exported);
+        let process_name = &of_phase(&trace_events, "M")[0];
+        assert_eq!(process_name["args"]["name"], "round trip");
+
+        // Times are relative to the first message:
+        let slices: Vec<(&str, f64, f64)> = of_phase(&trace_events, "X")
+            .iter()
+            .map(|x| {
+                let (ts, dur) = (x["ts"].as_f64().unwrap(), x["dur"].as_f64().unwrap());
+                (x["name"].as_str().unwrap(), ts, dur)
+            })
+            .collect();
+        assert_eq!(
+            slices,
+            vec![
+                ("main", 0.0, 50.0),
+                ("work", 10.0, 10.0),
+                ("task", 40.0, 20.0)
+            ]
+        );
+
+        let instants = of_phase(&trace_events, "i");
+        assert_eq!(instants.len(), 1);
+        assert_eq!(instants[0]["name"], "progress");
+        assert_eq!(instants[0]["ts"], 15.0);
+
+        let (flow_starts, flow_ends) = (of_phase(&trace_events, "s"), of_phase(&trace_events, "f"));
+        assert_eq!((flow_starts.len(), flow_ends.len()), (1, 1));
+        assert_eq!(flow_starts[0]["id"], flow_ends[0]["id"]);
+        assert_eq!(flow_starts[0]["ts"], 0.0); // the start of "main"
+        assert_eq!(flow_ends[0]["ts"], 40.0);
+        assert_ne!(flow_starts[0]["tid"], flow_ends[0]["tid"]);
+
+        let reimported = import(&exported).unwrap();
+        let shifted = |spans: Vec<(String, Option<String>, i64, i64)>, offset_ns| {
+            spans
+                .into_iter()
+                .map(|(name, parent, enter_ns, exit_ns)| {
+                    (name, parent, enter_ns - offset_ns, exit_ns - offset_ns)
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            shifted(spans(&reimported), 0),
+            shifted(spans(&original), 100_000)
+        );
+        assert_eq!(events(&reimported), vec![(owned("work"), 15_000)]);
+        assert_eq!(
+            follows(&reimported),
+            vec![("task".to_owned(), "main".to_owned())]
+        );
+    }
+}
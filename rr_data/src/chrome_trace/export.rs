@@ -0,0 +1,368 @@
+//! Export to the Chrome Trace Event format, which can be loaded by
+//! [Perfetto](https://ui.perfetto.dev) and `chrome://tracing`.
+
+use serde_json::{json, Value as Json};
+use std::collections::HashMap;
+
+use crate::{Callsite, CallsiteId, DataEvent, FieldSet, Message, MessageEnum, Span, SpanId, Value};
+
+/// All spans and events go in the same process.
+const PID: u32 = 1;
+
+/// Thread id of events outside of any span.
+const ORPHAN_TID: u32 = 0;
+
+/// Convert the messages of a topic to Chrome Trace Event JSON.
+///
+/// Each active interval of a span becomes a complete (`"X"`) event,
+/// each data event an instant (`"i"`) event,
+/// and each follows-from relationship a pair of flow (`"s"` and `"f"`) events.
+///
+/// Spans that are not direct children of their parent (e.g. spawned async tasks)
+/// get a thread of their own, so that the slices on each thread nest properly.
+///
+/// Timestamps are in microseconds since the first message of the topic.
+pub fn export<'a>(messages: impl IntoIterator<Item = &'a Message>, topic_name: &str) -> String {
+    let exporter = Exporter::new(messages);
+    let mut trace_events = vec![json!({
+        "ph": "M",
+        "name": "process_name",
+        "pid": PID,
+        "tid": ORPHAN_TID,
+        "args": { "name": topic_name },
+    })];
+    exporter.thread_names(&mut trace_events);
+    exporter.spans(&mut trace_events);
+    exporter.events(&mut trace_events);
+    exporter.flows(&mut trace_events);
+
+    let trace = json!({
+        "traceEvents": trace_events,
+        "displayTimeUnit": "ns",
+    });
+    trace.to_string()
+}
+
+/// What we know about a span.
+struct SpanInfo<'a> {
+    span: &'a Span,
+    /// Periods when the span is entered, in nanoseconds. `None` where we don't know.
+    intervals: Vec<(Option<i64>, Option<i64>)>,
+    /// In the order they were created.
+    children: Vec<SpanId>,
+    follows: Option<SpanId>,
+}
+
+impl SpanInfo<'_> {
+    fn is_active_at(&self, ns: i64) -> bool {
+        self.intervals.iter().any(|&(min, max)| {
+            min.map_or(true, |min| min <= ns) && max.map_or(true, |max| ns <= max)
+        })
+    }
+
+    /// True if the parent is active whenever the child is.
+    fn is_direct_child_of(&self, parent: &SpanInfo<'_>) -> bool {
+        self.intervals.iter().all(|&(min, max)| {
+            min.map_or(true, |ns| parent.is_active_at(ns))
+                && max.map_or(true, |ns| parent.is_active_at(ns))
+        })
+    }
+}
+
+struct Exporter<'a> {
+    callsites: HashMap<CallsiteId, &'a Callsite>,
+
+    /// In the order they were created.
+    spans: Vec<(SpanId, SpanInfo<'a>)>,
+
+    /// Index into `spans`.
+    span_index: HashMap<SpanId, usize>,
+
+    /// Spans without a (known) parent, in the order they were created.
+    roots: Vec<SpanId>,
+
+    /// With the time they were logged.
+    events: Vec<(i64, &'a DataEvent)>,
+
+    /// All times are relative to this.
+    origin_ns: i64,
+
+    /// Where open intervals end.
+    end_ns: i64,
+
+    /// Which thread each span is shown on.
+    tids: HashMap<SpanId, u32>,
+
+    /// The spans that start a new thread, in thread order.
+    thread_roots: Vec<SpanId>,
+}
+
+impl<'a> Exporter<'a> {
+    fn new(messages: impl IntoIterator<Item = &'a Message>) -> Self {
+        let mut exporter = Self {
+            callsites: Default::default(),
+            spans: Default::default(),
+            span_index: Default::default(),
+            roots: Default::default(),
+            events: Default::default(),
+            origin_ns: i64::MAX,
+            end_ns: i64::MIN,
+            tids: Default::default(),
+            thread_roots: Default::default(),
+        };
+        for message in messages {
+            exporter.add(message);
+        }
+        if exporter.end_ns < exporter.origin_ns {
+            // No times at all
+            exporter.origin_ns = 0;
+            exporter.end_ns = 0;
+        }
+
+        for span_id in exporter.roots.clone() {
+            exporter.assign_tids(span_id, None);
+        }
+        exporter
+    }
+
+    fn add(&mut self, message: &'a Message) {
+        let ns = message.log_time.nanos_since_epoch();
+        if let MessageEnum::NewCallsite(callsite) = &message.msg_enum {
+            // Callsites may be sent long before they are used, so their time doesn't count.
+            self.callsites.insert(callsite.id, callsite);
+            return;
+        }
+        self.origin_ns = self.origin_ns.min(ns);
+        self.end_ns = self.end_ns.max(ns);
+
+        match &message.msg_enum {
+            MessageEnum::NewSpan(span) => {
+                let parent_index = span
+                    .parent_span_id
+                    .and_then(|parent_span_id| self.span_index.get(&parent_span_id).copied());
+                match parent_index {
+                    Some(parent_index) => self.spans[parent_index].1.children.push(span.id),
+                    None => self.roots.push(span.id),
+                }
+                self.span_index.insert(span.id, self.spans.len());
+                let info = SpanInfo {
+                    span,
+                    intervals: vec![],
+                    children: vec![],
+                    follows: None,
+                };
+                self.spans.push((span.id, info));
+            }
+            MessageEnum::EnterSpan(span_id) => {
+                if let Some(info) = self.span_mut(span_id) {
+                    info.intervals.push((Some(ns), None));
+                }
+            }
+            MessageEnum::ExitSpan(span_id) => {
+                if let Some(info) = self.span_mut(span_id) {
+                    match info.intervals.last_mut() {
+                        Some((_, max @ None)) => *max = Some(ns),
+                        _ => info.intervals.push((None, Some(ns))),
+                    }
+                }
+            }
+            MessageEnum::SpanFollowsFrom { span, follows } => {
+                if let Some(info) = self.span_mut(span) {
+                    info.follows = Some(*follows);
+                }
+            }
+            MessageEnum::DataEvent(data_event) => {
+                self.events.push((ns, data_event));
+            }
+            MessageEnum::NewCallsite(_) | MessageEnum::DestroySpan(_) | MessageEnum::Metric(_) => {}
+        }
+    }
+
+    fn span(&self, span_id: &SpanId) -> Option<&SpanInfo<'a>> {
+        let index = *self.span_index.get(span_id)?;
+        Some(&self.spans[index].1)
+    }
+
+    fn span_mut(&mut self, span_id: &SpanId) -> Option<&mut SpanInfo<'a>> {
+        let index = *self.span_index.get(span_id)?;
+        Some(&mut self.spans[index].1)
+    }
+
+    /// Direct children share the thread of their parent, other spans start a new one.
+    fn assign_tids(&mut self, span_id: SpanId, parent: Option<(SpanId, u32)>) {
+        let info = match self.span(&span_id) {
+            Some(info) => info,
+            None => return,
+        };
+        let parent_tid = parent.and_then(|(parent_id, parent_tid)| {
+            let parent = self.span(&parent_id)?;
+            info.is_direct_child_of(parent).then(|| parent_tid)
+        });
+        let children = info.children.clone();
+        let tid = match parent_tid {
+            Some(parent_tid) => parent_tid,
+            None => {
+                self.thread_roots.push(span_id);
+                self.thread_roots.len() as u32
+            }
+        };
+        self.tids.insert(span_id, tid);
+
+        for child_id in children {
+            self.assign_tids(child_id, Some((span_id, tid)));
+        }
+    }
+
+    fn tid(&self, span_id: Option<&SpanId>) -> u32 {
+        span_id
+            .and_then(|span_id| self.tids.get(span_id).copied())
+            .unwrap_or(ORPHAN_TID)
+    }
+
+    fn us(&self, ns: i64) -> f64 {
+        (ns - self.origin_ns) as f64 * 1e-3
+    }
+
+    fn callsite_name(&self, callsite_id: &CallsiteId) -> String {
+        match self.callsites.get(callsite_id) {
+            Some(callsite) => callsite.name.clone(),
+            None => callsite_id.to_string(),
+        }
+    }
+
+    fn thread_names(&self, trace_events: &mut Vec<Json>) {
+        trace_events.push(json!({
+            "ph": "M",
+            "name": "thread_name",
+            "pid": PID,
+            "tid": ORPHAN_TID,
+            "args": { "name": "events outside spans" },
+        }));
+        for (index, span_id) in self.thread_roots.iter().enumerate() {
+            let name = match self.span(span_id) {
+                Some(info) => self.callsite_name(&info.span.callsite_id),
+                None => span_id.to_string(),
+            };
+            trace_events.push(json!({
+                "ph": "M",
+                "name": "thread_name",
+                "pid": PID,
+                "tid": index + 1,
+                "args": { "name": name },
+            }));
+        }
+    }
+
+    fn spans(&self, trace_events: &mut Vec<Json>) {
+        for (span_id, info) in &self.spans {
+            let name = self.callsite_name(&info.span.callsite_id);
+            let mut args = fields_to_json(&info.span.fields);
+            args.insert("span_id".to_owned(), json!(span_id.to_string()));
+            let args = Json::Object(args);
+
+            for &(min_ns, max_ns) in &info.intervals {
+                let min_ns = match min_ns {
+                    Some(min_ns) => min_ns,
+                    None => continue, // We don't know when it started
+                };
+                let max_ns = max_ns.unwrap_or(self.end_ns);
+                trace_events.push(json!({
+                    "ph": "X",
+                    "name": name,
+                    "cat": "span",
+                    "pid": PID,
+                    "tid": self.tid(Some(span_id)),
+                    "ts": self.us(min_ns),
+                    "dur": (max_ns - min_ns) as f64 * 1e-3,
+                    "args": args,
+                }));
+            }
+        }
+    }
+
+    fn events(&self, trace_events: &mut Vec<Json>) {
+        for (ns, event) in &self.events {
+            let mut args = fields_to_json(&event.fields);
+            let category = match self.callsites.get(&event.callsite_id) {
+                Some(callsite) => {
+                    args.insert("level".to_owned(), json!(callsite.level.to_string()));
+                    args.insert("location".to_owned(), json!(callsite.location.to_string()));
+                    callsite.level.to_string()
+                }
+                None => "event".to_owned(),
+            };
+            trace_events.push(json!({
+                "ph": "i",
+                "s": "t",
+                "name": self.callsite_name(&event.callsite_id),
+                "cat": category,
+                "pid": PID,
+                "tid": self.tid(event.parent_span_id.as_ref()),
+                "ts": self.us(*ns),
+                "args": Json::Object(args),
+            }));
+        }
+    }
+
+    /// An arrow to the first activity of the follower,
+    /// from the last activity of the followed span before that.
+    fn flows(&self, trace_events: &mut Vec<Json>) {
+        let mut flow_id = 0;
+        for (span_id, info) in &self.spans {
+            let (follows_id, follows) =
+                match info.follows.and_then(|id| Some((id, self.span(&id)?))) {
+                    Some(follows) => follows,
+                    None => continue,
+                };
+            let end = match info.intervals.iter().find_map(|&(min, _)| min) {
+                Some(end) => end,
+                None => continue, // Nothing to attach the arrow to
+            };
+            let starts = || follows.intervals.iter().filter_map(|&(min, _)| min);
+            let start = starts().filter(|&start| start <= end).max();
+            let start = match start.or_else(|| starts().min()) {
+                Some(start) => start,
+                None => continue,
+            };
+
+            flow_id += 1;
+            trace_events.push(json!({
+                "ph": "s",
+                "name": "follows from",
+                "cat": "follows_from",
+                "id": flow_id,
+                "pid": PID,
+                "tid": self.tid(Some(&follows_id)),
+                "ts": self.us(start),
+            }));
+            trace_events.push(json!({
+                "ph": "f",
+                "bp": "e",
+                "name": "follows from",
+                "cat": "follows_from",
+                "id": flow_id,
+                "pid": PID,
+                "tid": self.tid(Some(span_id)),
+                "ts": self.us(end),
+            }));
+        }
+    }
+}
+
+fn fields_to_json(fields: &FieldSet) -> serde_json::Map<String, Json> {
+    fields
+        .iter()
+        .map(|(name, value)| (name.clone(), value_to_json(value)))
+        .collect()
+}
+
+fn value_to_json(value: &Value) -> Json {
+    match value {
+        Value::String(string) | Value::Debug(string) => json!(string),
+        Value::I64(value) => json!(value),
+        Value::U64(value) => json!(value),
+        Value::F64(value) => json!(value),
+        Value::Bool(value) => json!(value),
+        Value::Error { .. } => json!(value.to_string()),
+    }
+}
@@ -0,0 +1,373 @@
+//! Import from the Chrome Trace Event format, as written by Chrome, Perfetto and many profilers.
+
+use serde_json::Value as Json;
+use std::collections::HashMap;
+
+use crate::{
+    Callsite, CallsiteId, CallsiteKind, DataEvent, FieldSet, Location, LogLevel, Message,
+    MessageEnum, Span, SpanId, Time, Value,
+};
+
+/// Convert Chrome Trace Event JSON to the messages of a topic.
+///
+/// * Duration (`"B"`/`"E"`) and complete (`"X"`) events become spans, each entered once.
+///   The parent of a span is the innermost span enclosing it on the same thread.
+/// * Instant (`"i"`/`"I"`) events become [`DataEvent`]s in the innermost span at that time.
+/// * Flow (`"s"`/`"f"`) events become [`MessageEnum::SpanFollowsFrom`].
+///
+/// A [`Callsite`] is synthesized for each name and category.
+/// Other kinds of events are ignored.
+///
+/// The microsecond timestamps of the trace are kept as they are,
+/// so traces with relative times will be shown as such.
+pub fn import(json: &str) -> anyhow::Result<Vec<Message>> {
+    use anyhow::Context as _;
+
+    let json: Json = serde_json::from_str(json).context("JSON")?;
+    let trace_events = match &json {
+        Json::Array(trace_events) => trace_events,
+        Json::Object(object) => match object.get("traceEvents") {
+            Some(Json::Array(trace_events)) => trace_events,
+            _ => anyhow::bail!("Expected a 'traceEvents' array"),
+        },
+        _ => anyhow::bail!("Expected a JSON array or object"),
+    };
+
+    let mut importer = Importer::default();
+    for trace_event in trace_events {
+        if let Some(trace_event) = TraceEvent::parse(trace_event) {
+            importer.add(trace_event);
+        }
+    }
+    Ok(importer.finish())
+}
+
+/// The parts of a trace event that we care about.
+struct TraceEvent {
+    phase: char,
+    name: String,
+    category: String,
+    /// Nanoseconds
+    ts: i64,
+    /// Nanoseconds, for complete events.
+    dur: Option<i64>,
+    thread: (i64, i64),
+    /// For flow events
+    id: Option<String>,
+    /// Scope of instant events: `"t"`hread, `"p"`rocess or `"g"`lobal.
+    scope: Option<String>,
+    args: FieldSet,
+}
+
+impl TraceEvent {
+    fn parse(json: &Json) -> Option<Self> {
+        let phase = json.get("ph")?.as_str()?.chars().next()?;
+        let str_field = |key: &str| json.get(key).and_then(Json::as_str).map(str::to_owned);
+        let int_field = |key: &str| match json.get(key) {
+            Some(Json::Number(number)) => number.as_i64(),
+            Some(Json::String(string)) => string.parse().ok(),
+            _ => None,
+        };
+        let ns_field = |key: &str| {
+            let us = json.get(key)?.as_f64()?;
+            Some((us * 1e3).round() as i64)
+        };
+        let id = match json.get("id").or_else(|| json.get("bind_id")) {
+            Some(Json::String(string)) => Some(string.clone()),
+            Some(Json::Number(number)) => Some(number.to_string()),
+            _ => None,
+        };
+        let args = match json.get("args") {
+            Some(Json::Object(args)) => args
+                .iter()
+                .map(|(key, value)| (key.clone(), value_from_json(value)))
+                .collect(),
+            _ => vec![],
+        };
+
+        Some(Self {
+            phase,
+            name: str_field("name").unwrap_or_default(),
+            category: str_field("cat").unwrap_or_default(),
+            ts: ns_field("ts")?,
+            dur: ns_field("dur"),
+            thread: (int_field("pid").unwrap_or(0), int_field("tid").unwrap_or(0)),
+            id,
+            scope: str_field("s"),
+            args,
+        })
+    }
+}
+
+fn value_from_json(json: &Json) -> Value {
+    match json {
+        Json::String(string) => Value::String(string.clone()),
+        Json::Bool(value) => Value::Bool(*value),
+        Json::Number(number) => {
+            if let Some(value) = number.as_i64() {
+                Value::I64(value)
+            } else if let Some(value) = number.as_u64() {
+                Value::U64(value)
+            } else {
+                Value::F64(number.as_f64().unwrap_or(f64::NAN))
+            }
+        }
+        _ => Value::Debug(json.to_string()),
+    }
+}
+
+// ----------------------------------------------------------------------------
+
+/// A span, or something that happens at a point in time within one.
+struct Item {
+    start_ns: i64,
+    end_ns: i64,
+    kind: ItemKind,
+    callsite_id: CallsiteId,
+    args: FieldSet,
+}
+
+#[derive(Clone, PartialEq)]
+enum ItemKind {
+    Span,
+    /// With the scope of the instant event.
+    Instant {
+        in_thread: bool,
+    },
+    /// Start or end of an arrow, with the id of the flow.
+    FlowStart(String),
+    FlowEnd(String),
+}
+
+#[derive(Default)]
+struct Importer {
+    callsites: HashMap<(String, String, bool), Callsite>,
+    items: HashMap<(i64, i64), Vec<Item>>,
+    /// Began but not yet ended spans of each thread.
+    open: HashMap<(i64, i64), Vec<Item>>,
+    max_ns: i64,
+}
+
+impl Importer {
+    fn callsite_id(&mut self, trace_event: &TraceEvent, is_span: bool) -> CallsiteId {
+        let key = (
+            trace_event.name.clone(),
+            trace_event.category.clone(),
+            is_span,
+        );
+        let next_id = CallsiteId(self.callsites.len() as u64 + 1);
+        let callsite = self.callsites.entry(key).or_insert_with(|| Callsite {
+            id: next_id,
+            kind: if is_span {
+                CallsiteKind::Span
+            } else {
+                CallsiteKind::Event
+            },
+            name: trace_event.name.clone(),
+            level: LogLevel::Info,
+            location: Location {
+                module: trace_event.category.clone(),
+                file: None,
+                line: None,
+            },
+            field_names: Default::default(),
+        });
+        for (name, _) in &trace_event.args {
+            if !callsite.field_names.contains(name) {
+                callsite.field_names.push(name.clone());
+            }
+        }
+        callsite.id
+    }
+
+    fn add(&mut self, trace_event: TraceEvent) {
+        self.max_ns = self
+            .max_ns
+            .max(trace_event.ts.saturating_add(trace_event.dur.unwrap_or(0)));
+
+        let kind = match trace_event.phase {
+            'B' => {
+                let item = Item {
+                    start_ns: trace_event.ts,
+                    end_ns: i64::MAX, // until we get the matching 'E'
+                    kind: ItemKind::Span,
+                    callsite_id: self.callsite_id(&trace_event, true),
+                    args: trace_event.args,
+                };
+                self.open.entry(trace_event.thread).or_default().push(item);
+                return;
+            }
+            'E' => {
+                if let Some(mut item) = self
+                    .open
+                    .get_mut(&trace_event.thread)
+                    .and_then(|open| open.pop())
+                {
+                    item.end_ns = trace_event.ts;
+                    item.args.extend(trace_event.args);
+                    self.items.entry(trace_event.thread).or_default().push(item);
+                }
+                return;
+            }
+            'X' => ItemKind::Span,
+            'i' | 'I' => ItemKind::Instant {
+                in_thread: trace_event
+                    .scope
+                    .as_deref()
+                    .map_or(true, |scope| scope == "t"),
+            },
+            's' => match &trace_event.id {
+                Some(id) => ItemKind::FlowStart(format!("{}/{}", trace_event.category, id)),
+                None => return,
+            },
+            'f' => match &trace_event.id {
+                Some(id) => ItemKind::FlowEnd(format!("{}/{}", trace_event.category, id)),
+                None => return,
+            },
+            _ => return,
+        };
+
+        let is_span = kind == ItemKind::Span;
+        let callsite_id = match kind {
+            ItemKind::Span | ItemKind::Instant { .. } => self.callsite_id(&trace_event, is_span),
+            ItemKind::FlowStart(_) | ItemKind::FlowEnd(_) => CallsiteId(0), // not used
+        };
+        let item = Item {
+            start_ns: trace_event.ts,
+            end_ns: trace_event
+                .ts
+                .saturating_add(trace_event.dur.unwrap_or(0).max(0)),
+            kind,
+            callsite_id,
+            args: trace_event.args,
+        };
+        self.items.entry(trace_event.thread).or_default().push(item);
+    }
+
+    fn finish(mut self) -> Vec<Message> {
+        // Spans that never ended last until the end of the trace:
+        for (thread, open) in std::mem::take(&mut self.open) {
+            for mut item in open {
+                item.end_ns = self.max_ns;
+                self.items.entry(thread).or_default().push(item);
+            }
+        }
+
+        // (time, order within the same time, message)
+        let mut messages: Vec<(i64, i64, MessageEnum)> = self
+            .callsites
+            .into_values()
+            .map(|callsite| (i64::MIN, 0, MessageEnum::NewCallsite(callsite)))
+            .collect();
+        let mut flow_starts: HashMap<String, SpanId> = Default::default();
+        let mut flow_ends: Vec<(i64, String, SpanId)> = vec![];
+        let mut next_span_id = 1;
+
+        let mut threads: Vec<_> = self.items.into_iter().collect();
+        threads.sort_by_key(|(thread, _)| *thread);
+
+        for (_thread, mut items) in threads {
+            // Parents before children, and spans before what happens in them:
+            items.sort_by_key(|item| {
+                (
+                    item.start_ns,
+                    item.kind != ItemKind::Span,
+                    std::cmp::Reverse(item.end_ns),
+                )
+            });
+
+            // The spans enclosing the current item:
+            let mut stack: Vec<(SpanId, i64)> = vec![];
+
+            for item in items {
+                let is_span = item.kind == ItemKind::Span;
+                while let Some(&(_, end_ns)) = stack.last() {
+                    let has_ended = if is_span {
+                        end_ns <= item.start_ns
+                    } else {
+                        end_ns < item.start_ns
+                    };
+                    if has_ended {
+                        stack.pop();
+                    } else {
+                        break;
+                    }
+                }
+                let parent_span_id = stack.last().map(|&(span_id, _)| span_id);
+
+                match item.kind {
+                    ItemKind::Span => {
+                        let span_id = SpanId(next_span_id);
+                        next_span_id += 1;
+                        let depth = stack.len() as i64;
+
+                        messages.push((
+                            item.start_ns,
+                            1,
+                            MessageEnum::NewSpan(Span {
+                                id: span_id,
+                                parent_span_id,
+                                callsite_id: item.callsite_id,
+                                fields: item.args,
+                            }),
+                        ));
+                        messages.push((item.start_ns, 2, MessageEnum::EnterSpan(span_id)));
+                        // Children exit before their parents, but not before they are entered:
+                        let exit_order = if item.end_ns == item.start_ns {
+                            4
+                        } else {
+                            -depth - 2
+                        };
+                        messages.push((item.end_ns, exit_order, MessageEnum::ExitSpan(span_id)));
+                        messages.push((item.end_ns, 5, MessageEnum::DestroySpan(span_id)));
+
+                        stack.push((span_id, item.end_ns));
+                    }
+                    ItemKind::Instant { in_thread } => {
+                        messages.push((
+                            item.start_ns,
+                            3,
+                            MessageEnum::DataEvent(DataEvent {
+                                callsite_id: item.callsite_id,
+                                parent_span_id: parent_span_id.filter(|_| in_thread),
+                                fields: item.args,
+                            }),
+                        ));
+                    }
+                    ItemKind::FlowStart(flow_id) => {
+                        if let Some(span_id) = parent_span_id {
+                            flow_starts.insert(flow_id, span_id);
+                        }
+                    }
+                    ItemKind::FlowEnd(flow_id) => {
+                        if let Some(span_id) = parent_span_id {
+                            flow_ends.push((item.start_ns, flow_id, span_id));
+                        }
+                    }
+                }
+            }
+        }
+
+        for (time_ns, flow_id, span) in flow_ends {
+            if let Some(&follows) = flow_starts.get(&flow_id) {
+                messages.push((time_ns, 3, MessageEnum::SpanFollowsFrom { span, follows }));
+            }
+        }
+
+        // Stable, so that parents are created before their children:
+        messages.sort_by_key(|(time_ns, order, _)| (*time_ns, *order));
+
+        let first_ns = messages
+            .iter()
+            .map(|(time_ns, _, _)| *time_ns)
+            .find(|&time_ns| time_ns != i64::MIN)
+            .unwrap_or(0);
+        messages
+            .into_iter()
+            .map(|(time_ns, _, msg_enum)| Message {
+                log_time: Time::from_nanos_since_epoch(time_ns.max(first_ns)),
+                msg_enum,
+            })
+            .collect()
+    }
+}
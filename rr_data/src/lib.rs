@@ -4,6 +4,8 @@
 
 use std::sync::Arc;
 
+pub mod chrome_trace;
+
 pub const DEFAULT_PUB_SUB_PORT: u16 = 9002;
 pub const DEFAULT_VIEWER_WEB_SERVER_PORT: u16 = 8787;
 
@@ -24,6 +26,305 @@ pub enum PubSubMsg {
 
     /// List of all existing topics
     AllTopics(Vec<TopicMeta>),
+
+    /// Stop telling me about new messages on this topic.
+    UnsubscribeFrom(TopicId),
+
+    /// Please tell me about the messages on this topic that I'm interested in.
+    ///
+    /// [`Self::SubscribeTo`] is the same as the default [`Subscription`].
+    /// Subscribing again to the same topic replaces the filter and resends the backlog.
+    Subscribe(TopicId, Subscription),
+
+    /// Sent in reply to [`Self::Subscribe`], before the backlog.
+    TopicRange(TopicId, TopicRange),
+
+    /// Please tell me about the callsites and spans of this topic.
+    QueryTopicIndex(TopicId),
+
+    /// Reply to [`Self::QueryTopicIndex`].
+    TopicIndex(TopicId, TopicIndex),
+
+    /// Please find the data events that contain this text.
+    Search(SearchQuery),
+
+    /// Reply to [`Self::Search`].
+    SearchResults(SearchResults),
+
+    /// Please give me the access of this token.
+    ///
+    /// If the server requires a token and none was given in the websocket URL (`?token=…`),
+    /// this must be the first message. Otherwise the server closes the connection.
+    Authenticate(String),
+
+    /// Please send me these messages of a topic, without changing my subscription.
+    ///
+    /// Used to load older messages than the backlog of a [`Self::Subscribe`].
+    QueryMessages(TopicId, MessageQuery),
+
+    /// Reply to [`Self::QueryMessages`], in one or more parts, in order.
+    ///
+    /// The query of each part says which messages it covers.
+    /// The first part also starts with the callsites and the spans still alive at its start.
+    Messages(TopicId, MessageQuery, Vec<Arc<[u8]>>),
+
+    /// Like [`Self::ListTopics`], but with what the server knows about each topic.
+    ///
+    /// After this the server also sends [`Self::TopicStatsChanged`] when the stats change.
+    ListTopicsWithStats,
+
+    /// Reply to [`Self::ListTopicsWithStats`].
+    AllTopicsWithStats(Vec<(TopicMeta, TopicStats)>),
+
+    /// Please tell me what I may do on this connection.
+    ///
+    /// Send it after [`Self::Authenticate`], if any.
+    QueryPermissions,
+
+    /// Reply to [`Self::QueryPermissions`].
+    Permissions(Permissions),
+
+    /// The server has forgotten this topic, and ended all subscriptions to it.
+    TopicRemoved(TopicId),
+
+    /// The new stats of the topics that changed, pushed every so often
+    /// to the clients that have sent [`Self::ListTopicsWithStats`].
+    TopicStatsChanged(Vec<(TopicId, TopicStats)>),
+}
+
+/// Which messages of a topic a subscriber wants.
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Subscription {
+    pub backlog: Backlog,
+    pub filter: Filter,
+}
+
+/// Which of the old messages of a topic to send to a new subscriber.
+///
+/// Whatever is skipped, the callsites and the spans that are still alive at the start
+/// of the backlog are always sent, so that the rest of the messages make sense.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum Backlog {
+    /// Every message of the topic.
+    All,
+
+    /// Only messages that arrive after subscribing.
+    None,
+
+    /// Starting with the message with this index.
+    FromIndex(u64),
+
+    /// Starting with the first message logged at or after this time.
+    FromTime(Time),
+
+    /// The last this many messages.
+    Last(u64),
+}
+
+impl Default for Backlog {
+    fn default() -> Self {
+        Self::All
+    }
+}
+
+/// Which messages of a topic to send to a subscriber, decided by their [`Callsite`].
+///
+/// Callsites and the span structure ([`MessageEnum::NewSpan`], [`MessageEnum::DestroySpan`]
+/// and [`MessageEnum::SpanFollowsFrom`]) are always sent, so the messages that pass have context.
+///
+/// The default lets everything through.
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Filter {
+    /// Skip anything less severe than this.
+    pub min_level: Option<LogLevel>,
+
+    /// If non-empty, skip callsites whose [`Location::module`] (the `tracing` target)
+    /// doesn't start with one of these.
+    pub target_prefixes: Vec<String>,
+
+    /// Skip [`MessageEnum::DataEvent`]s.
+    pub skip_events: bool,
+
+    /// Skip [`MessageEnum::EnterSpan`] and [`MessageEnum::ExitSpan`].
+    pub skip_span_activity: bool,
+
+    /// Skip [`MessageEnum::Metric`]s.
+    pub skip_metrics: bool,
+}
+
+impl Filter {
+    /// Only the data events, and what they need.
+    pub fn events_only() -> Self {
+        Self {
+            skip_span_activity: true,
+            skip_metrics: true,
+            ..Default::default()
+        }
+    }
+
+    pub fn lets_everything_through(&self) -> bool {
+        self == &Self::default()
+    }
+
+    /// Does this callsite pass the level and target filters?
+    pub fn accepts_callsite(&self, callsite: &Callsite) -> bool {
+        let level_ok = self
+            .min_level
+            .map_or(true, |min_level| callsite.level >= min_level);
+        let target_ok = self.target_prefixes.is_empty()
+            || self
+                .target_prefixes
+                .iter()
+                .any(|prefix| callsite.location.module.starts_with(prefix.as_str()));
+        level_ok && target_ok
+    }
+}
+
+/// The messages with index in `start..end` that pass the filter.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct MessageQuery {
+    pub start: u64,
+    pub end: u64,
+    pub filter: Filter,
+}
+
+/// What a connection may do, given its token.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Permissions {
+    /// Create topics and publish messages on them.
+    pub may_publish: bool,
+    /// List, subscribe to, and search topics.
+    pub may_read: bool,
+}
+
+/// The messages of a topic on the server, and which of them a subscriber gets.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct TopicRange {
+    /// Number of messages the server has on the topic.
+    pub num_messages: u64,
+
+    /// Index of the first message of the backlog.
+    ///
+    /// This is `num_messages` if no old messages will be sent.
+    pub backlog_start: u64,
+
+    /// Log time of the first and last message, if any.
+    pub time_range: Option<(Time, Time)>,
+}
+
+/// What the server knows about the contents of a topic.
+///
+/// Displayed like "1203 events, 3 errors, 12.4 s".
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct TopicStats {
+    pub num_messages: u64,
+
+    /// Total size of the encoded messages.
+    pub num_bytes: u64,
+
+    /// Number of [`DataEvent`]s of each [`LogLevel`], indexed by `LogLevel as usize`.
+    ///
+    /// Events from unknown callsites are not counted here.
+    pub events_per_level: [u64; 5],
+
+    pub num_spans: u64,
+
+    /// Earliest and latest log time.
+    pub time_range: Option<(Time, Time)>,
+}
+
+impl TopicStats {
+    pub fn num_events(&self) -> u64 {
+        self.events_per_level.iter().sum()
+    }
+
+    pub fn num_events_of_level(&self, level: LogLevel) -> u64 {
+        self.events_per_level[level as usize]
+    }
+
+    pub fn duration_ns(&self) -> i64 {
+        self.time_range.map_or(0, |(min, max)| {
+            max.nanos_since_epoch() - min.nanos_since_epoch()
+        })
+    }
+}
+
+impl std::fmt::Display for TopicStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let plural = |count: u64| if count == 1 { "" } else { "s" };
+        let num_events = self.num_events();
+        write!(f, "{} event{}", num_events, plural(num_events))?;
+        let num_errors = self.num_events_of_level(LogLevel::Error);
+        if num_errors > 0 {
+            write!(f, ", {} error{}", num_errors, plural(num_errors))?;
+        }
+        let seconds = self.duration_ns() as f64 * 1e-9;
+        if seconds >= 1.0 {
+            write!(f, ", {:.1} s", seconds)
+        } else {
+            write!(f, ", {:.1} ms", seconds * 1e3)
+        }
+    }
+}
+
+/// The callsites and spans of a topic, as indexed by the server.
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct TopicIndex {
+    pub stats: TopicStats,
+    pub callsites: Vec<Callsite>,
+    /// In the order they were created.
+    pub spans: Vec<SpanSummary>,
+}
+
+/// Find [`DataEvent`]s by the values of their fields or the name of their callsite.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct SearchQuery {
+    /// Case-insensitive.
+    pub text: String,
+
+    /// Search only this topic, or all of them.
+    pub topic_id: Option<TopicId>,
+
+    /// Stop after this many hits.
+    pub max_hits: u64,
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct SearchResults {
+    pub query: SearchQuery,
+
+    /// Newest topic first, and in message order within each topic.
+    pub hits: Vec<SearchHit>,
+
+    /// There were more than [`SearchQuery::max_hits`] hits.
+    pub truncated: bool,
+}
+
+/// A [`DataEvent`] that matched a [`SearchQuery`].
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct SearchHit {
+    pub topic_id: TopicId,
+
+    /// Index of the message in the topic.
+    pub index: u64,
+
+    pub log_time: Time,
+
+    pub callsite_name: String,
+
+    /// The part of the event that matched, e.g. `message=… connection refused …`.
+    pub snippet: String,
+}
+
+/// When a span was created and destroyed.
+#[derive(Clone, Copy, Debug, serde::Serialize, serde::Deserialize)]
+pub struct SpanSummary {
+    pub id: SpanId,
+    pub parent_span_id: Option<SpanId>,
+    pub callsite_id: CallsiteId,
+    pub created: Time,
+    /// `None` if the span is still alive.
+    pub destroyed: Option<Time>,
 }
 
 impl PubSubMsg {
@@ -50,6 +351,20 @@ impl TopicId {
     }
 }
 
+impl std::fmt::Display for TopicId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl std::str::FromStr for TopicId {
+    type Err = uuid::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        uuid::Uuid::parse_str(s).map(Self)
+    }
+}
+
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct TopicMeta {
     pub id: TopicId,
@@ -57,6 +372,30 @@ pub struct TopicMeta {
     pub name: String,
 }
 
+/// All the messages of a topic, e.g. for saving to a file.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Recording {
+    pub topic_meta: TopicMeta,
+
+    /// Each an encoded [`Message`].
+    pub messages: Vec<Arc<[u8]>>,
+}
+
+impl Recording {
+    pub fn encode(&self) -> Vec<u8> {
+        use bincode::Options as _;
+        bincode::DefaultOptions::new().serialize(self).unwrap()
+    }
+
+    pub fn decode(bytes: &[u8]) -> anyhow::Result<Self> {
+        use anyhow::Context as _;
+        use bincode::Options as _;
+        bincode::DefaultOptions::new()
+            .deserialize(bytes)
+            .context("bincode")
+    }
+}
+
 // ----------------------------------------------------------------------------
 
 /// A date-time represented as nanoseconds since unix epoch
@@ -71,6 +410,11 @@ impl Time {
         Self(nanos_since_epoch())
     }
 
+    #[inline]
+    pub fn from_nanos_since_epoch(nanos_since_epoch: i64) -> Self {
+        Self(nanos_since_epoch)
+    }
+
     #[inline]
     pub fn nanos_since_epoch(&self) -> i64 {
         self.0
@@ -174,6 +518,9 @@ pub enum MessageEnum {
     },
 
     DataEvent(DataEvent),
+
+    /// A new value for a metric.
+    Metric(Metric),
 }
 
 /// A place in the source code where we may be logging data from.
@@ -220,7 +567,10 @@ pub struct Span {
     pub fields: FieldSet,
 }
 
-#[derive(Copy, Clone, Debug, Hash, Eq, PartialEq, serde::Serialize, serde::Deserialize)]
+/// Ordered by severity, so that `Trace < Error`.
+#[derive(
+    Copy, Clone, Debug, Hash, Eq, PartialEq, Ord, PartialOrd, serde::Serialize, serde::Deserialize,
+)]
 pub enum LogLevel {
     /// The "trace" level.
     ///
@@ -264,6 +614,8 @@ impl std::fmt::Display for LogLevel {
 pub enum CallsiteKind {
     Event,
     Span,
+    /// The [`Callsite::name`] is the name of the metric.
+    Metric(MetricKind),
 }
 
 impl std::fmt::Display for CallsiteKind {
@@ -271,6 +623,27 @@ impl std::fmt::Display for CallsiteKind {
         match self {
             Self::Event => "Event".fmt(f),
             Self::Span => "Span".fmt(f),
+            Self::Metric(kind) => write!(f, "{} metric", kind),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub enum MetricKind {
+    /// A monotonically increasing count, e.g. number of requests.
+    Counter,
+    /// A value that can go up and down, e.g. memory use.
+    Gauge,
+    /// A distribution of samples, e.g. latencies.
+    Histogram,
+}
+
+impl std::fmt::Display for MetricKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Counter => "Counter".fmt(f),
+            Self::Gauge => "Gauge".fmt(f),
+            Self::Histogram => "Histogram".fmt(f),
         }
     }
 }
@@ -284,6 +657,34 @@ pub struct DataEvent {
 
 pub type FieldSet = Vec<(String, Value)>;
 
+/// Logged from a callsite of kind [`CallsiteKind::Metric`].
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Metric {
+    pub callsite_id: CallsiteId,
+    pub parent_span_id: Option<SpanId>,
+    pub value: MetricValue,
+}
+
+#[derive(Clone, Copy, Debug, serde::Serialize, serde::Deserialize)]
+pub enum MetricValue {
+    /// Add this much to a [`MetricKind::Counter`].
+    CounterIncrement(u64),
+    /// Set a [`MetricKind::Gauge`] to this value.
+    GaugeSet(f64),
+    /// Add a sample to a [`MetricKind::Histogram`].
+    HistogramSample(f64),
+}
+
+impl std::fmt::Display for MetricValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::CounterIncrement(increment) => write!(f, "+{}", increment),
+            Self::GaugeSet(value) => write!(f, "= {}", value),
+            Self::HistogramSample(sample) => write!(f, "sample {}", sample),
+        }
+    }
+}
+
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub enum Value {
     String(String),
@@ -298,6 +699,18 @@ pub enum Value {
     },
 }
 
+impl Value {
+    /// The value as a number, if it is one.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Self::I64(value) => Some(*value as f64),
+            Self::U64(value) => Some(*value as f64),
+            Self::F64(value) => Some(*value),
+            Self::String(_) | Self::Bool(_) | Self::Debug(_) | Self::Error { .. } => None,
+        }
+    }
+}
+
 impl std::fmt::Display for Value {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -1,12 +1,31 @@
 use crate::viewer::Viewer;
 use eframe::{egui, epi};
 
+#[cfg(target_arch = "wasm32")]
+pub(crate) use ewebsock::WsSender;
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) use ws_client::WsSender;
+
 #[derive(Default)]
 pub struct WsClientApp {
     pubsub_url: String,
+    /// From `?token=` of the web page. Natively, add `?token=…` to the pub-sub url instead.
+    token: Option<String>,
+    /// Which certificates to trust for `wss://` urls. On the web, the browser decides.
+    #[cfg(not(target_arch = "wasm32"))]
+    root_certs: ws_client::RootCerts,
     frontend: Option<Viewer>,
 }
 
+#[cfg(not(target_arch = "wasm32"))]
+impl WsClientApp {
+    /// E.g. to trust the self-signed certificate of a pub-sub server.
+    pub fn with_root_certs(mut self, root_certs: ws_client::RootCerts) -> Self {
+        self.root_certs = root_certs;
+        self
+    }
+}
+
 impl epi::App for WsClientApp {
     fn name(&self) -> &str {
         "Live Log Viewer"
@@ -19,8 +38,17 @@ impl epi::App for WsClientApp {
         _storage: Option<&dyn epi::Storage>,
     ) {
         if let Some(web_info) = &frame.info().web_info {
+            self.token = web_info.location.query_map.get("token").cloned();
             if let Some(pubsub_url) = web_info.location.query_map.get("pubsub") {
                 self.pubsub_url = pubsub_url.clone()
+            } else {
+                // Connect back to the pub-sub server that served us:
+                let scheme = if web_info.location.protocol == "https:" {
+                    "wss"
+                } else {
+                    "ws"
+                };
+                self.pubsub_url = format!("{}://{}/ws", scheme, web_info.location.host);
             }
         }
         if self.pubsub_url.is_empty() {
@@ -55,7 +83,10 @@ impl WsClientApp {
         let (ws_receiver, on_event) =
             ewebsock::WsReceiver::new_with_callback(move || frame.request_repaint());
 
+        #[cfg(target_arch = "wasm32")]
         let ws_sender = ewebsock::ws_connect(self.pubsub_url.clone(), on_event).unwrap();
-        self.frontend = Some(Viewer::new(ws_sender, ws_receiver));
+        #[cfg(not(target_arch = "wasm32"))]
+        let ws_sender = ws_client::ws_connect(self.pubsub_url.clone(), &self.root_certs, on_event);
+        self.frontend = Some(Viewer::new(ws_sender, ws_receiver, self.token.clone()));
     }
 }
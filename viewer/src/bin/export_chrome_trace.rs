@@ -0,0 +1,109 @@
+#![forbid(unsafe_code)]
+#![warn(clippy::all, rust_2018_idioms)]
+
+//! Download a topic from a pub-sub server and save it as Chrome Trace Event JSON.
+//!
+//! Usage: `export_chrome_trace [--pubsub ws://127.0.0.1:9002] [--root-cert PEM] <topic name or id> <output.json>`
+//!
+//! If several topics have the same name, the latest one is exported.
+
+use ewebsock::{WsEvent, WsMessage};
+use rr_data::{PubSubMsg, TopicMeta};
+use std::{sync::Arc, time::Duration};
+
+/// Assume we have the whole topic when the server has been quiet for this long.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(1);
+
+const USAGE: &str =
+    "Usage: export_chrome_trace [--pubsub URL] [--root-cert PEM] <topic name or id> <output.json>
+
+  --root-cert PEM   For wss:// urls, trust only the certificates in this file
+                    (e.g. the self-signed certificate of the server) instead of
+                    the system root certificates (or those in $SSL_CERT_FILE).";
+
+#[cfg(not(target_arch = "wasm32"))]
+#[tokio::main]
+async fn main() {
+    tracing_subscriber::fmt::init();
+    if let Err(err) = run().await {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+}
+
+async fn run() -> anyhow::Result<()> {
+    let mut pub_sub_url = format!("ws://127.0.0.1:{}", rr_data::DEFAULT_PUB_SUB_PORT);
+    let mut root_certs = ws_client::RootCerts::default();
+    let mut positional = vec![];
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--pubsub" {
+            pub_sub_url = args.next().ok_or_else(|| anyhow::anyhow!(USAGE))?;
+        } else if arg == "--root-cert" {
+            let path = args.next().ok_or_else(|| anyhow::anyhow!(USAGE))?;
+            root_certs = ws_client::RootCerts::from_pem_file(&path)?;
+        } else {
+            positional.push(arg);
+        }
+    }
+    let (topic, output_path) = match positional.as_slice() {
+        [topic, output_path] => (topic.clone(), output_path.clone()),
+        _ => anyhow::bail!(USAGE),
+    };
+
+    let (tx, mut ws_receiver) = tokio::sync::mpsc::unbounded_channel();
+    let on_event: ewebsock::EventHandler = Arc::new(move |event| {
+        tx.send(event).ok();
+        std::ops::ControlFlow::Continue(())
+    });
+    let mut ws_sender = ws_client::ws_connect(pub_sub_url.clone(), &root_certs, on_event);
+
+    let mut topic_meta: Option<TopicMeta> = None;
+    let mut messages = vec![];
+
+    loop {
+        let event = if topic_meta.is_some() {
+            match tokio::time::timeout(IDLE_TIMEOUT, ws_receiver.recv()).await {
+                Ok(event) => event,
+                Err(_) => break,
+            }
+        } else {
+            ws_receiver.recv().await
+        };
+        let event = event.ok_or_else(|| anyhow::anyhow!("Lost the connection"))?;
+
+        match event {
+            WsEvent::Opened => {
+                ws_sender.send(WsMessage::Binary(PubSubMsg::ListTopics.encode()));
+            }
+            WsEvent::Message(WsMessage::Binary(payload)) => match PubSubMsg::decode(&payload)? {
+                PubSubMsg::AllTopics(all_topics) if topic_meta.is_none() => {
+                    let found = all_topics
+                        .into_iter()
+                        .filter(|meta| meta.name == topic || meta.id.to_string() == topic)
+                        .max_by_key(|meta| meta.created);
+                    let found = found.ok_or_else(|| {
+                        anyhow::anyhow!("No topic named {:?} on {}", topic, pub_sub_url)
+                    })?;
+                    ws_sender.send(WsMessage::Binary(PubSubMsg::SubscribeTo(found.id).encode()));
+                    topic_meta = Some(found);
+                }
+                PubSubMsg::TopicMsg(topic_id, payload)
+                    if Some(topic_id) == topic_meta.as_ref().map(|meta| meta.id) =>
+                {
+                    messages.push(rr_data::Message::decode(&payload)?);
+                }
+                _ => {}
+            },
+            WsEvent::Message(_) => {}
+            WsEvent::Error(err) => anyhow::bail!("{}: {}", pub_sub_url, err),
+            WsEvent::Closed => anyhow::bail!("{} closed the connection", pub_sub_url),
+        }
+    }
+
+    let topic_meta = topic_meta.expect("we only stop after finding the topic");
+    let json = rr_data::chrome_trace::export(&messages, &topic_meta.name);
+    std::fs::write(&output_path, json)?;
+    eprintln!("Saved {:?} to {}", topic_meta.name, output_path);
+    Ok(())
+}
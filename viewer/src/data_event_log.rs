@@ -4,41 +4,172 @@ use crate::span_tree::SpanTree;
 
 // ----------------------------------------------------------------------------
 
+pub struct EventRow {
+    pub log_time: rr_data::Time,
+    /// Cached `log_time.format()`
+    pub time: String,
+    pub data_event: rr_data::DataEvent,
+}
+
 /// View every log event
 #[derive(Default)]
 pub struct DataEventLog {
-    events: Vec<(rr_data::Time, rr_data::DataEvent)>,
+    rows: Vec<EventRow>,
+
+    /// Some row has an earlier `log_time` than the one before it,
+    /// e.g. from an imported Chrome trace.
+    out_of_order: bool,
+
+    /// When `out_of_order`: the rows within a time range, updated as new rows arrive.
+    in_range: InRange,
+
+    /// Keep scrolled to the newest event.
+    follow: bool,
+}
+
+#[derive(Default)]
+struct InRange {
+    time_range: Option<(i64, i64)>,
+    /// How many of the rows we have looked at.
+    num_rows: usize,
+    row_nrs: Vec<usize>,
 }
 
 impl DataEventLog {
     pub fn on_message(&mut self, msg: &rr_data::Message) {
         if let rr_data::MessageEnum::DataEvent(data_event) = &msg.msg_enum {
-            self.events.push((msg.log_time, data_event.clone()));
+            if let Some(last) = self.rows.last() {
+                self.out_of_order |= msg.log_time < last.log_time;
+            }
+            self.rows.push(EventRow {
+                log_time: msg.log_time,
+                time: msg.log_time.format(),
+                data_event: data_event.clone(),
+            });
         }
     }
 
-    pub fn ui(&self, ui: &mut egui::Ui, span_tree: &SpanTree) {
-        ui.label("Hover to view call sites");
-        ui.separator();
-        egui::ScrollArea::vertical()
-            .auto_shrink([false; 2])
-            .show(ui, |ui| {
-                for (time, data_event) in &self.events {
-                    self.ui_event(ui, span_tree, *time, data_event);
-                }
-            });
+    /// Forget all events, but keep the view settings.
+    pub fn clear(&mut self) {
+        self.rows.clear();
+        self.out_of_order = false;
+        self.in_range = Default::default();
+    }
+
+    /// All events, in the order they were received.
+    pub fn rows(&self) -> &[EventRow] {
+        &self.rows
     }
 
-    fn ui_event(
-        &self,
-        ui: &mut egui::Ui,
-        span_tree: &SpanTree,
-        log_time: rr_data::Time,
-        data_event: &rr_data::DataEvent,
-    ) {
+    /// Only the events logged within `time_range` (nanoseconds since epoch) are shown, if given.
+    pub fn ui(&mut self, ui: &mut egui::Ui, span_tree: &SpanTree, time_range: Option<(i64, i64)>) {
         ui.horizontal(|ui| {
-            ui.label(egui::RichText::new(log_time.format()).weak().monospace());
-            span_tree.data_event_ui(ui, data_event);
+            ui.checkbox(&mut self.follow, "Follow")
+                .on_hover_text("Keep scrolled to the newest event. Scroll up to stop following.");
+            ui.separator();
+            ui.label("Hover to view call sites");
+        });
+        ui.separator();
+
+        self.update_in_range(time_range);
+        let row_nrs = self.row_nrs(time_range);
+        let rows = &self.rows;
+        let num_rows = row_nrs.len();
+
+        // Only lay out the rows that are visible:
+        let row_height = ui.spacing().interact_size.y;
+        let spacing = ui.spacing().item_spacing.y;
+        let content_height = (row_height + spacing) * num_rows as f32 - spacing;
+        let bottom_offset = (content_height - ui.available_height()).max(0.0);
+
+        let mut scroll_area = egui::ScrollArea::vertical().auto_shrink([false; 2]);
+        if self.follow {
+            scroll_area = scroll_area.vertical_scroll_offset(bottom_offset);
+        }
+
+        let output = scroll_area.show_rows(ui, row_height, num_rows, |ui, row_range| {
+            for row_nr in row_range {
+                crate::misc::fixed_height_row(ui, row_nr, row_height, |ui| {
+                    Self::ui_row(ui, span_tree, &rows[row_nrs.get(row_nr)]);
+                });
+            }
         });
+
+        if self.follow && output.state.offset.y < bottom_offset - 1.0 {
+            self.follow = false; // The user scrolled away from the bottom
+        }
+    }
+
+    /// The rows logged within `time_range`, if given.
+    fn row_nrs(&self, time_range: Option<(i64, i64)>) -> RowNrs<'_> {
+        match time_range {
+            None => RowNrs::Range(0..self.rows.len()),
+            Some(_) if self.out_of_order => RowNrs::List(&self.in_range.row_nrs),
+            Some((min_ns, max_ns)) => {
+                // The ones in the time range are contiguous:
+                let start = self
+                    .rows
+                    .partition_point(|row| row.log_time.nanos_since_epoch() < min_ns);
+                let end = self
+                    .rows
+                    .partition_point(|row| row.log_time.nanos_since_epoch() <= max_ns);
+                RowNrs::Range(start..end.max(start))
+            }
+        }
+    }
+
+    /// When `out_of_order`, only looks at the rows that are new since last time,
+    /// unless the time range changed.
+    fn update_in_range(&mut self, time_range: Option<(i64, i64)>) {
+        let (min_ns, max_ns) = match time_range {
+            Some(time_range) if self.out_of_order => time_range,
+            _ => return,
+        };
+        if self.in_range.time_range != time_range {
+            self.in_range = InRange {
+                time_range,
+                ..Default::default()
+            };
+        }
+        let new_rows = self.in_range.num_rows..self.rows.len();
+        let rows = &self.rows;
+        self.in_range.row_nrs.extend(new_rows.filter(|&row_nr| {
+            let ns = rows[row_nr].log_time.nanos_since_epoch();
+            min_ns <= ns && ns <= max_ns
+        }));
+        self.in_range.num_rows = self.rows.len();
+    }
+
+    fn ui_row(ui: &mut egui::Ui, span_tree: &SpanTree, row: &EventRow) {
+        let EventRow {
+            log_time: _,
+            time,
+            data_event,
+        } = row;
+        ui.label(egui::RichText::new(time).weak().monospace());
+        span_tree.data_event_ui(ui, data_event);
+    }
+}
+
+/// Which rows to show.
+enum RowNrs<'a> {
+    Range(std::ops::Range<usize>),
+    List(&'a [usize]),
+}
+
+impl RowNrs<'_> {
+    fn len(&self) -> usize {
+        match self {
+            Self::Range(range) => range.len(),
+            Self::List(row_nrs) => row_nrs.len(),
+        }
+    }
+
+    /// The `i`:th row number.
+    fn get(&self, i: usize) -> usize {
+        match self {
+            Self::Range(range) => range.start + i,
+            Self::List(row_nrs) => row_nrs[i],
+        }
     }
 }
@@ -0,0 +1,274 @@
+use eframe::egui;
+use rr_data::{CallsiteId, TopicId, TopicMeta};
+use std::collections::HashMap;
+
+use crate::flamegraph::NanoSecond;
+use crate::merged_flamegraph::{diff_color, format_change, merge_spans, MergedNode};
+use crate::misc::format_duration_ns;
+use crate::span_tree::SpanTree;
+
+/// Name and location, which identifies a callsite across recordings.
+///
+/// [`CallsiteId`]:s differ between runs of the same app, but name and location don't.
+type CallsiteKey = (String, String);
+
+fn callsite_key(span_tree: &SpanTree, callsite_id: &CallsiteId) -> CallsiteKey {
+    match span_tree.callsites.get(callsite_id) {
+        Some(callsite) => (callsite.name.to_string(), callsite.location.to_string()),
+        None => (callsite_id.to_string(), String::new()),
+    }
+}
+
+/// Count and summed time of the spans on one callsite ancestry path.
+#[derive(Clone, Copy, Default)]
+struct PathTimes {
+    count: usize,
+    total_ns: NanoSecond,
+}
+
+struct DiffRow {
+    /// Callsite names, e.g. `"main ➡ my_function"`.
+    path: String,
+    before: PathTimes,
+    after: PathTimes,
+}
+
+/// The comparison of two recordings.
+pub struct Diff {
+    /// The "before" recording merged by callsite ancestry path,
+    /// using the [`CallsiteId`]:s of the "after" recording.
+    ///
+    /// Paths that only exist in "before" are left out.
+    pub baseline: MergedNode,
+
+    /// Every path in either recording, biggest change first.
+    rows: Vec<DiffRow>,
+}
+
+impl Diff {
+    pub fn new(before: &SpanTree, after: &SpanTree) -> Self {
+        let before_merged = merge_spans(before, None);
+        let after_merged = merge_spans(after, None);
+
+        let after_callsite_ids: HashMap<CallsiteKey, CallsiteId> = after
+            .callsites
+            .keys()
+            .map(|callsite_id| (callsite_key(after, callsite_id), *callsite_id))
+            .collect();
+        let baseline = translate(before, &before_merged, &after_callsite_ids);
+
+        let mut rows: HashMap<Vec<CallsiteKey>, DiffRow> = Default::default();
+        collect_rows(
+            before,
+            &before_merged,
+            &mut vec![],
+            &mut rows,
+            |row, times| {
+                row.before = times;
+            },
+        );
+        collect_rows(
+            after,
+            &after_merged,
+            &mut vec![],
+            &mut rows,
+            |row, times| {
+                row.after = times;
+            },
+        );
+        let mut rows: Vec<DiffRow> = rows.into_values().collect();
+        rows.sort_by_key(|row| -(row.after.total_ns - row.before.total_ns).abs());
+
+        Self { baseline, rows }
+    }
+}
+
+/// Use the callsite ids of another recording.
+fn translate(
+    span_tree: &SpanTree,
+    node: &MergedNode,
+    callsite_ids: &HashMap<CallsiteKey, CallsiteId>,
+) -> MergedNode {
+    let children = node
+        .children
+        .iter()
+        .filter_map(|(callsite_id, child)| {
+            let new_id = *callsite_ids.get(&callsite_key(span_tree, callsite_id))?;
+            Some((new_id, translate(span_tree, child, callsite_ids)))
+        })
+        .collect();
+    MergedNode {
+        count: node.count,
+        total_ns: node.total_ns,
+        self_ns: node.self_ns,
+        children,
+    }
+}
+
+fn collect_rows(
+    span_tree: &SpanTree,
+    node: &MergedNode,
+    path: &mut Vec<CallsiteKey>,
+    rows: &mut HashMap<Vec<CallsiteKey>, DiffRow>,
+    set_times: impl Fn(&mut DiffRow, PathTimes) + Copy,
+) {
+    for (callsite_id, child) in &node.children {
+        path.push(callsite_key(span_tree, callsite_id));
+        let row = rows.entry(path.clone()).or_insert_with(|| DiffRow {
+            path: String::new(),
+            before: Default::default(),
+            after: Default::default(),
+        });
+        if row.path.is_empty() {
+            row.path = path
+                .iter()
+                .map(|(name, _location)| name.as_str())
+                .collect::<Vec<_>>()
+                .join(" ➡ ");
+        }
+        set_times(
+            row,
+            PathTimes {
+                count: child.count,
+                total_ns: child.total_ns,
+            },
+        );
+        collect_rows(span_tree, child, path, rows, set_times);
+        path.pop();
+    }
+}
+
+// ----------------------------------------------------------------------------
+
+/// Compare the span timings of two topics.
+#[derive(Default)]
+pub struct DiffView {
+    before: Option<TopicId>,
+    after: Option<TopicId>,
+
+    /// Show the table instead of the flame graph.
+    show_table: bool,
+
+    merged_graph: crate::merged_flamegraph::MergedFlameGraph,
+
+    /// Recomputed when either topic changes: ids and span tree generations.
+    diff: Option<Diff>,
+    diff_key: Option<(TopicId, u64, TopicId, u64)>,
+}
+
+impl DiffView {
+    /// `topics` are the open topics to choose from.
+    pub fn ui(&mut self, ui: &mut egui::Ui, topics: &[(&TopicMeta, &SpanTree)]) {
+        if self.before.is_none() && self.after.is_none() && topics.len() >= 2 {
+            // Compare the first and last opened topic:
+            self.before = Some(topics[0].0.id);
+            self.after = Some(topics[topics.len() - 1].0.id);
+        }
+
+        ui.horizontal(|ui| {
+            topic_selector(ui, "diff_before", "Before", topics, &mut self.before);
+            topic_selector(ui, "diff_after", "After", topics, &mut self.after);
+            ui.separator();
+            ui.radio_value(&mut self.show_table, false, "Flame graph");
+            ui.radio_value(&mut self.show_table, true, "Table");
+        });
+        ui.separator();
+
+        let find = |topic_id: Option<TopicId>| {
+            let topic_id = topic_id?;
+            topics
+                .iter()
+                .find(|(topic_meta, _)| topic_meta.id == topic_id)
+                .map(|(_, span_tree)| (topic_id, *span_tree))
+        };
+        let ((before_id, before), (after_id, after)) = match (find(self.before), find(self.after)) {
+            (Some(before), Some(after)) => (before, after),
+            _ => {
+                ui.label("Open two topics and select which ones to compare.");
+                return;
+            }
+        };
+
+        let key = Some((before_id, before.generation(), after_id, after.generation()));
+        if self.diff_key != key {
+            self.diff = Some(Diff::new(before, after));
+            self.diff_key = key;
+        }
+        let diff = match &self.diff {
+            Some(diff) => diff,
+            None => return,
+        };
+
+        if self.show_table {
+            table_ui(ui, diff);
+        } else {
+            ui.label("Bars are as wide as in the \"after\" topic, red if slower and green if faster than \"before\".");
+            self.merged_graph
+                .ui(ui, after, &Default::default(), Some(&diff.baseline));
+        }
+    }
+}
+
+fn topic_selector(
+    ui: &mut egui::Ui,
+    id_source: &str,
+    label: &str,
+    topics: &[(&TopicMeta, &SpanTree)],
+    selected: &mut Option<TopicId>,
+) {
+    let selected_text = topics
+        .iter()
+        .find(|(topic_meta, _)| Some(topic_meta.id) == *selected)
+        .map_or_else(
+            || "(none)".to_owned(),
+            |(topic_meta, _)| crate::viewer::topic_summary(topic_meta),
+        );
+    egui::ComboBox::new(id_source, label)
+        .selected_text(selected_text)
+        .show_ui(ui, |ui| {
+            for (topic_meta, _) in topics {
+                ui.selectable_value(
+                    selected,
+                    Some(topic_meta.id),
+                    crate::viewer::topic_summary(topic_meta),
+                );
+            }
+        });
+}
+
+fn table_ui(ui: &mut egui::Ui, diff: &Diff) {
+    egui::ScrollArea::vertical()
+        .auto_shrink([false; 2])
+        .show(ui, |ui| {
+            egui::Grid::new("diff_table")
+                .striped(true)
+                .num_columns(6)
+                .show(ui, |ui| {
+                    for header in [
+                        "Path",
+                        "Count before",
+                        "Count after",
+                        "Total before",
+                        "Total after",
+                        "Change",
+                    ] {
+                        ui.strong(header);
+                    }
+                    ui.end_row();
+
+                    for row in &diff.rows {
+                        ui.label(&row.path);
+                        ui.label(row.before.count.to_string());
+                        ui.label(row.after.count.to_string());
+                        ui.label(format_duration_ns(row.before.total_ns));
+                        ui.label(format_duration_ns(row.after.total_ns));
+                        let color = diff_color(row.before.total_ns, row.after.total_ns);
+                        ui.colored_label(
+                            color,
+                            format_change(row.before.total_ns, row.after.total_ns),
+                        );
+                        ui.end_row();
+                    }
+                });
+        });
+}
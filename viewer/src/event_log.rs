@@ -2,7 +2,11 @@ use eframe::egui;
 
 enum Line {
     Text(String),
-    Message(rr_data::Message),
+    Message {
+        /// Cached `msg.log_time.format()`
+        time: String,
+        msg: rr_data::Message,
+    },
 }
 
 // ----------------------------------------------------------------------------
@@ -12,46 +16,91 @@ enum Line {
 pub struct EventLog {
     span_tree: crate::span_tree::SpanTree,
     lines: Vec<Line>,
+    in_range: InRange,
+}
+
+/// The lines within a time range, updated as new lines arrive.
+///
+/// Messages from different topics are interleaved, so they are not sorted by time.
+#[derive(Default)]
+struct InRange {
+    time_range: Option<(i64, i64)>,
+    /// How many of the lines we have looked at.
+    num_lines: usize,
+    line_nrs: Vec<usize>,
 }
 
 impl EventLog {
     pub fn on_message(&mut self, msg: rr_data::Message) {
         let warnings = false; // we reuse the same structure and that will produce problems.
         self.span_tree.on_mesage(&msg, warnings);
-        self.lines.push(Line::Message(msg));
+        let time = msg.log_time.format();
+        self.lines.push(Line::Message { time, msg });
     }
 
     pub fn on_text(&mut self, text: String) {
         self.lines.push(Line::Text(text));
     }
 
-    pub fn ui(&self, ui: &mut egui::Ui) {
+    /// Only the messages logged within `time_range` (nanoseconds since epoch) are shown, if given.
+    pub fn ui(&mut self, ui: &mut egui::Ui, time_range: Option<(i64, i64)>) {
         ui.label("All the events that the viewer receives");
         ui.label("Hover to view call sites");
         ui.separator();
+
+        if let Some(time_range) = time_range {
+            self.update_in_range(time_range);
+        }
+        let num_rows = match time_range {
+            Some(_) => self.in_range.line_nrs.len(),
+            None => self.lines.len(),
+        };
+
+        // Only lay out the rows that are visible:
+        let row_height = ui.spacing().interact_size.y;
         egui::ScrollArea::vertical()
             .auto_shrink([false; 2])
-            .show(ui, |ui| {
-                for line in &self.lines {
-                    match line {
-                        Line::Text(text) => {
-                            ui.label(text);
-                        }
-                        Line::Message(msg) => {
-                            self.ui_msg(ui, msg);
+            .show_rows(ui, row_height, num_rows, |ui, row_range| {
+                for row_nr in row_range {
+                    let line_nr = match time_range {
+                        Some(_) => self.in_range.line_nrs[row_nr],
+                        None => row_nr,
+                    };
+                    crate::misc::fixed_height_row(ui, row_nr, row_height, |ui| {
+                        match &self.lines[line_nr] {
+                            Line::Text(text) => {
+                                ui.label(text);
+                            }
+                            Line::Message { time, msg } => {
+                                ui.label(egui::RichText::new(time).weak().monospace());
+                                self.ui_msg_enum(ui, &msg.msg_enum);
+                            }
                         }
-                    }
+                    });
                 }
             });
     }
 
-    fn ui_msg(&self, ui: &mut egui::Ui, msg: &rr_data::Message) {
-        let rr_data::Message { log_time, msg_enum } = msg;
-
-        ui.horizontal(|ui| {
-            ui.label(egui::RichText::new(log_time.format()).weak().monospace());
-            self.ui_msg_enum(ui, msg_enum);
-        });
+    /// Only looks at the lines that are new since last time, unless the time range changed.
+    fn update_in_range(&mut self, (min_ns, max_ns): (i64, i64)) {
+        if self.in_range.time_range != Some((min_ns, max_ns)) {
+            self.in_range = InRange {
+                time_range: Some((min_ns, max_ns)),
+                ..Default::default()
+            };
+        }
+        let new_lines = self.in_range.num_lines..self.lines.len();
+        let lines = &self.lines;
+        self.in_range
+            .line_nrs
+            .extend(new_lines.filter(|&line_nr| match &lines[line_nr] {
+                Line::Text(_) => false,
+                Line::Message { msg, .. } => {
+                    let ns = msg.log_time.nanos_since_epoch();
+                    min_ns <= ns && ns <= max_ns
+                }
+            }));
+        self.in_range.num_lines = self.lines.len();
     }
 
     fn ui_msg_enum(&self, ui: &mut egui::Ui, msg: &rr_data::MessageEnum) {
@@ -110,6 +159,15 @@ impl EventLog {
                 ui.strong("Event:");
                 self.span_tree.data_event_ui(ui, data_event);
             }
+            rr_data::MessageEnum::Metric(metric) => {
+                ui.strong("Metric:");
+                ui.label(format!(
+                    "{} {}",
+                    self.span_tree.callsite_name(&metric.callsite_id),
+                    metric.value
+                ))
+                .on_hover_ui(|ui| self.span_tree.callsite_ui_by_id(ui, &metric.callsite_id));
+            }
         }
     }
 }
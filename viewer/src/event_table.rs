@@ -0,0 +1,392 @@
+use eframe::egui;
+use rr_data::CallsiteId;
+use std::cmp::Ordering;
+
+use crate::data_event_log::EventRow;
+use crate::span_tree::SpanTree;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Column {
+    Time,
+    Level,
+    ParentSpan,
+    /// Index into [`rr_data::Callsite::field_names`].
+    Field(usize),
+}
+
+impl Column {
+    fn default_width(&self) -> f32 {
+        match self {
+            Self::Time => 110.0,
+            Self::Level => 50.0,
+            Self::ParentSpan => 150.0,
+            Self::Field(_) => 120.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct SortBy {
+    column: Column,
+    ascending: bool,
+}
+
+/// Cached row order, so we don't have to filter and sort every frame.
+#[derive(Default)]
+struct SortedRows {
+    /// What `rows` was computed for: callsite and sort order.
+    key: Option<(CallsiteId, Option<SortBy>)>,
+    /// How many of all the events we have looked at.
+    num_events: usize,
+    /// Indices into all the events.
+    rows: Vec<usize>,
+}
+
+// ----------------------------------------------------------------------------
+
+/// Show all the events from one callsite in a table, with one column per field.
+#[derive(Default)]
+pub struct EventTable {
+    callsite_id: Option<CallsiteId>,
+
+    /// `None` means in the order they were logged.
+    sort_by: Option<SortBy>,
+
+    /// Width of each column, in points.
+    column_widths: Vec<f32>,
+
+    sorted: SortedRows,
+}
+
+impl EventTable {
+    pub fn ui(&mut self, ui: &mut egui::Ui, span_tree: &SpanTree, events: &[EventRow]) {
+        self.callsite_selector_ui(ui, span_tree);
+        ui.separator();
+
+        let callsite = match self.callsite_id.and_then(|id| span_tree.callsites.get(&id)) {
+            Some(callsite) => callsite,
+            None => {
+                ui.label("Select a callsite to view its events.");
+                return;
+            }
+        };
+
+        let columns: Vec<Column> = [Column::Time, Column::Level, Column::ParentSpan]
+            .into_iter()
+            .chain((0..callsite.field_names.len()).map(Column::Field))
+            .collect();
+        if self.column_widths.len() != columns.len() {
+            self.column_widths = columns.iter().map(Column::default_width).collect();
+        }
+
+        self.sort_rows(span_tree, callsite, events);
+
+        let row_height = ui.spacing().interact_size.y;
+        let (header_rect, _) = ui.allocate_exact_size(
+            egui::vec2(ui.available_width(), row_height),
+            egui::Sense::hover(),
+        );
+
+        let column_widths = &self.column_widths;
+        let sorted_rows = &self.sorted.rows;
+        let output = egui::ScrollArea::both().auto_shrink([false; 2]).show_rows(
+            ui,
+            row_height,
+            sorted_rows.len(),
+            |ui, row_range| {
+                for row_nr in row_range {
+                    let row = &events[sorted_rows[row_nr]];
+                    table_row(ui, row_nr, row_height, column_widths, |ui, column_nr| {
+                        cell_ui(ui, span_tree, callsite, row, columns[column_nr]);
+                    });
+                }
+            },
+        );
+
+        // The header is painted last so we know how far the table is scrolled sideways:
+        let header_rect = header_rect.translate(egui::vec2(-output.state.offset.x, 0.0));
+        let mut header_ui = ui.child_ui(
+            header_rect,
+            egui::Layout::left_to_right().with_cross_align(egui::Align::Center),
+        );
+        header_ui.set_clip_rect(header_rect.intersect(ui.clip_rect()));
+        self.header_ui(&mut header_ui, row_height, &columns, &callsite.field_names);
+    }
+
+    /// Call when the events have been replaced, rather than just added to.
+    pub fn clear_cache(&mut self) {
+        self.sorted = Default::default();
+    }
+
+    fn callsite_selector_ui(&mut self, ui: &mut egui::Ui, span_tree: &SpanTree) {
+        let changed = crate::misc::event_callsite_selector(
+            ui,
+            "event_table_callsite",
+            &span_tree.callsites,
+            &mut self.callsite_id,
+        );
+        if changed {
+            self.sort_by = None;
+            self.column_widths.clear();
+        }
+    }
+
+    fn header_ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        row_height: f32,
+        columns: &[Column],
+        field_names: &[String],
+    ) {
+        let mut clicked_column = None;
+
+        let header_rect = table_row(
+            ui,
+            "header",
+            row_height,
+            &self.column_widths,
+            |ui, column_nr| {
+                let column = columns[column_nr];
+                let name = match column {
+                    Column::Time => "Time",
+                    Column::Level => "Level",
+                    Column::ParentSpan => "Parent span",
+                    Column::Field(field_nr) => field_names[field_nr].as_str(),
+                };
+                let sort_by = self.sort_by.filter(|sort_by| sort_by.column == column);
+                let text = match sort_by {
+                    Some(SortBy {
+                        ascending: true, ..
+                    }) => format!("{} ⏶", name),
+                    Some(SortBy {
+                        ascending: false, ..
+                    }) => format!("{} ⏷", name),
+                    None => name.to_owned(),
+                };
+                let is_sorted = sort_by.is_some();
+                if ui
+                    .selectable_label(is_sorted, egui::RichText::new(text).strong())
+                    .on_hover_text("Click to sort")
+                    .clicked()
+                {
+                    clicked_column = Some(column);
+                }
+            },
+        );
+
+        // Drag the right edge of a column to resize it:
+        let mut right = header_rect.left();
+        for column_nr in 0..self.column_widths.len() {
+            right += self.column_widths[column_nr];
+            let handle_rect =
+                egui::Rect::from_x_y_ranges((right - 4.0)..=(right + 2.0), header_rect.y_range());
+            let handle = ui.interact(handle_rect, ui.id().with(column_nr), egui::Sense::drag());
+            let stroke = if handle.hovered() || handle.dragged() {
+                ui.output().cursor_icon = egui::CursorIcon::ResizeHorizontal;
+                ui.visuals().widgets.hovered.fg_stroke
+            } else {
+                ui.visuals().widgets.noninteractive.bg_stroke
+            };
+            ui.painter().line_segment(
+                [
+                    egui::pos2(right - 2.0, header_rect.top()),
+                    egui::pos2(right - 2.0, header_rect.bottom()),
+                ],
+                stroke,
+            );
+            if handle.drag_delta().x != 0.0 {
+                let width = &mut self.column_widths[column_nr];
+                *width = (*width + handle.drag_delta().x).max(24.0);
+            }
+        }
+
+        if let Some(column) = clicked_column {
+            // Cycle through ascending, descending and unsorted:
+            self.sort_by = match self.sort_by.filter(|sort_by| sort_by.column == column) {
+                None => Some(SortBy {
+                    column,
+                    ascending: true,
+                }),
+                Some(SortBy {
+                    ascending: true, ..
+                }) => Some(SortBy {
+                    column,
+                    ascending: false,
+                }),
+                Some(SortBy {
+                    ascending: false, ..
+                }) => None,
+            };
+        }
+    }
+
+    /// Only re-sorts everything when the callsite or sort order changes.
+    /// New events are inserted where they belong.
+    fn sort_rows(
+        &mut self,
+        span_tree: &SpanTree,
+        callsite: &rr_data::Callsite,
+        events: &[EventRow],
+    ) {
+        let key = Some((callsite.id, self.sort_by));
+        if self.sorted.key != key || events.len() < self.sorted.num_events {
+            self.sorted = SortedRows {
+                key,
+                num_events: 0,
+                rows: Default::default(),
+            };
+        }
+
+        let new_rows = (self.sorted.num_events..events.len())
+            .filter(|&index| events[index].data_event.callsite_id == callsite.id);
+        let first_sort = self.sorted.num_events == 0;
+        self.sorted.num_events = events.len();
+
+        let (column, ascending) = match self.sort_by {
+            Some(SortBy { column, ascending }) => (column, ascending),
+            None => {
+                self.sorted.rows.extend(new_rows);
+                return;
+            }
+        };
+        let ordering = |a: usize, b: usize| {
+            let ordering = compare(span_tree, callsite, column, &events[a], &events[b]);
+            if ascending {
+                ordering
+            } else {
+                ordering.reverse()
+            }
+        };
+
+        let rows = &mut self.sorted.rows;
+        if first_sort {
+            rows.extend(new_rows);
+            // Stable sort, so ties stay in the order they were logged.
+            rows.sort_by(|&a, &b| ordering(a, b));
+        } else {
+            for new_row in new_rows {
+                // After any ties, to match the stable sort:
+                let position = rows.partition_point(|&row| ordering(row, new_row).is_le());
+                rows.insert(position, new_row);
+            }
+        }
+    }
+}
+
+// ----------------------------------------------------------------------------
+
+/// One row of fixed-width cells.
+fn table_row(
+    ui: &mut egui::Ui,
+    id_source: impl std::hash::Hash + Copy,
+    row_height: f32,
+    column_widths: &[f32],
+    mut cell_ui: impl FnMut(&mut egui::Ui, usize),
+) -> egui::Rect {
+    let total_width = column_widths.iter().sum();
+    let (rect, _) =
+        ui.allocate_exact_size(egui::vec2(total_width, row_height), egui::Sense::hover());
+
+    let layout = egui::Layout::left_to_right().with_cross_align(egui::Align::Center);
+    let mut left = rect.left();
+    for (column_nr, &width) in column_widths.iter().enumerate() {
+        let cell_rect = egui::Rect::from_x_y_ranges(left..=(left + width), rect.y_range());
+        let mut cell = ui.child_ui_with_id_source(cell_rect, layout, (id_source, column_nr));
+        let padding = egui::vec2(4.0, 0.0);
+        cell.set_clip_rect(
+            egui::Rect::from_min_max(cell_rect.min, cell_rect.max - padding)
+                .intersect(ui.clip_rect()),
+        );
+        cell.style_mut().wrap = Some(false);
+        cell_ui(&mut cell, column_nr);
+        left += width;
+    }
+    rect
+}
+
+fn cell_ui(
+    ui: &mut egui::Ui,
+    span_tree: &SpanTree,
+    callsite: &rr_data::Callsite,
+    row: &EventRow,
+    column: Column,
+) {
+    match column {
+        Column::Time => {
+            ui.label(egui::RichText::new(&row.time).weak().monospace());
+        }
+        Column::Level => {
+            crate::span_tree::log_level_ui(ui, callsite.level);
+        }
+        Column::ParentSpan => {
+            if let Some(parent_span_id) = &row.data_event.parent_span_id {
+                ui.label(span_tree.span_name(parent_span_id))
+                    .on_hover_ui(|ui| span_tree.span_summary_ui_by_id(ui, parent_span_id));
+            } else {
+                ui.weak("(none)");
+            }
+        }
+        Column::Field(field_nr) => {
+            if let Some(value) = field_value(callsite, row, field_nr) {
+                ui.label(value.to_string());
+            }
+        }
+    }
+}
+
+fn field_value<'a>(
+    callsite: &rr_data::Callsite,
+    row: &'a EventRow,
+    field_nr: usize,
+) -> Option<&'a rr_data::Value> {
+    let field_name = callsite.field_names.get(field_nr)?;
+    row.data_event
+        .fields
+        .iter()
+        .find(|(key, _)| key == field_name)
+        .map(|(_, value)| value)
+}
+
+fn compare(
+    span_tree: &SpanTree,
+    callsite: &rr_data::Callsite,
+    column: Column,
+    a: &EventRow,
+    b: &EventRow,
+) -> Ordering {
+    match column {
+        Column::Time => a.log_time.cmp(&b.log_time),
+        Column::Level => Ordering::Equal, // All events from the same callsite have the same level
+        Column::ParentSpan => {
+            let name = |row: &EventRow| {
+                row.data_event
+                    .parent_span_id
+                    .map(|span_id| span_tree.span_name(&span_id))
+            };
+            name(a).cmp(&name(b))
+        }
+        Column::Field(field_nr) => compare_values(
+            field_value(callsite, a, field_nr),
+            field_value(callsite, b, field_nr),
+        ),
+    }
+}
+
+/// Numbers are compared by value, everything else by how it is displayed.
+/// Missing values go first.
+fn compare_values(a: Option<&rr_data::Value>, b: Option<&rr_data::Value>) -> Ordering {
+    match (a, b) {
+        (None, None) => Ordering::Equal,
+        (None, Some(_)) => Ordering::Less,
+        (Some(_), None) => Ordering::Greater,
+        (Some(a), Some(b)) => match (a.as_f64(), b.as_f64()) {
+            (Some(a), Some(b)) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => match (a, b) {
+                (rr_data::Value::String(a), rr_data::Value::String(b)) => a.cmp(b),
+                _ => a.to_string().cmp(&b.to_string()),
+            },
+        },
+    }
+}
@@ -0,0 +1,138 @@
+use eframe::egui;
+use rr_data::{CallsiteId, Time};
+
+use crate::data_event_log::EventRow;
+use crate::span_tree::SpanTree;
+
+/// The values of one numeric field, collected incrementally as events come in.
+#[derive(Default)]
+struct Series {
+    /// Callsite and field name.
+    source: Option<(CallsiteId, String)>,
+    /// How many of the events we have looked at so far.
+    num_events_scanned: usize,
+    /// In the order they were logged.
+    points: Vec<(Time, f64)>,
+    /// Min and max of all the values.
+    value_range: Option<(f64, f64)>,
+}
+
+impl Series {
+    fn update(&mut self, events: &[EventRow]) {
+        let (callsite_id, field_name) = match &self.source {
+            Some(source) => source,
+            None => return,
+        };
+
+        for row in &events[self.num_events_scanned.min(events.len())..] {
+            if row.data_event.callsite_id != *callsite_id {
+                continue;
+            }
+            if let Some(value) = numeric_field(&row.data_event, field_name) {
+                self.points.push((row.log_time, value));
+                if !value.is_nan() {
+                    self.value_range = Some(match self.value_range {
+                        Some((min, max)) => (min.min(value), max.max(value)),
+                        None => (value, value),
+                    });
+                }
+            }
+        }
+        self.num_events_scanned = events.len();
+    }
+}
+
+fn numeric_field(data_event: &rr_data::DataEvent, field_name: &str) -> Option<f64> {
+    data_event
+        .fields
+        .iter()
+        .find(|(key, _)| key == field_name)
+        .and_then(|(_, value)| value.as_f64())
+}
+
+// ----------------------------------------------------------------------------
+
+/// A numeric field of an event callsite, plotted over time.
+#[derive(Default)]
+pub struct FieldPlot {
+    callsite_id: Option<CallsiteId>,
+    field_name: Option<String>,
+    series: Series,
+}
+
+impl FieldPlot {
+    /// Has the user selected something to plot?
+    pub fn is_active(&self) -> bool {
+        self.callsite_id.is_some() && self.field_name.is_some()
+    }
+
+    pub fn callsite_id(&self) -> Option<CallsiteId> {
+        self.callsite_id
+    }
+
+    pub fn field_name(&self) -> Option<&str> {
+        self.field_name.as_deref()
+    }
+
+    /// Time and value of each point, in the order they were logged.
+    pub fn points(&self) -> &[(Time, f64)] {
+        &self.series.points
+    }
+
+    /// Min and max value, or `None` if there are no points.
+    pub fn value_range(&self) -> Option<(f64, f64)> {
+        self.series.value_range
+    }
+
+    /// Catch up with new events.
+    pub fn update(&mut self, events: &[EventRow]) {
+        let source = self.callsite_id.zip(self.field_name.clone());
+        if self.series.source != source {
+            self.series = Series {
+                source,
+                ..Default::default()
+            };
+        }
+        self.series.update(events);
+    }
+
+    pub fn selector_ui(&mut self, ui: &mut egui::Ui, span_tree: &SpanTree, events: &[EventRow]) {
+        ui.horizontal(|ui| {
+            ui.label("Plot:");
+            if crate::misc::event_callsite_selector(
+                ui,
+                "field_plot_callsite",
+                &span_tree.callsites,
+                &mut self.callsite_id,
+            ) {
+                self.field_name = None;
+            }
+
+            if let Some(callsite) = self.callsite_id.and_then(|id| span_tree.callsites.get(&id)) {
+                egui::ComboBox::new("field_plot_field", "Field")
+                    .selected_text(self.field_name.as_deref().unwrap_or("(none)"))
+                    .show_ui(ui, |ui| {
+                        for field_name in &callsite.field_names {
+                            // Only offer the fields that have numeric values:
+                            let is_numeric = events.iter().any(|row| {
+                                row.data_event.callsite_id == callsite.id
+                                    && numeric_field(&row.data_event, field_name).is_some()
+                            });
+                            if is_numeric {
+                                ui.selectable_value(
+                                    &mut self.field_name,
+                                    Some(field_name.clone()),
+                                    field_name,
+                                );
+                            }
+                        }
+                    });
+            }
+
+            if self.callsite_id.is_some() && ui.button("ｘ").on_hover_text("Hide plot").clicked() {
+                self.callsite_id = None;
+                self.field_name = None;
+            }
+        });
+    }
+}
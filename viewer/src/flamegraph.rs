@@ -1,43 +1,146 @@
+use crate::data_event_log::EventRow;
 use crate::span_tree::{SpanNode, SpanTree};
 use eframe::egui;
 use egui::*;
 use rr_data::{CallsiteId, SpanId};
 use std::collections::{BinaryHeap, HashSet};
 
-type NanoSecond = i64;
+pub type NanoSecond = i64;
 
 const HOVER_COLOR: Rgba = Rgba::from_rgb(0.8, 0.8, 0.8);
 
 // ----------------------------------------------------------------------------
 
+/// Search for spans.
+///
+/// The filter is made up of space-separated terms that must all match.
+/// `key=value` matches a span field, anything else a substring of the span description.
 #[derive(Clone, Debug, Default)]
 struct Filter {
     filter: String,
+
+    /// All matching spans, ordered by when they started.
+    matches: Vec<SpanId>,
+    match_set: HashSet<SpanId>,
+    /// What the matches were computed for: span tree generation and filter.
+    matches_key: Option<(u64, String)>,
+
+    /// Index into `matches` of the span we last jumped to.
+    current: Option<usize>,
 }
 
 impl Filter {
-    fn ui(&mut self, ui: &mut egui::Ui) {
+    /// Returns a span to zoom to, if the user navigated to a match.
+    fn ui(&mut self, ui: &mut egui::Ui, span_tree: &SpanTree) -> Option<SpanId> {
+        let mut jump = None;
         ui.horizontal(|ui| {
             ui.label("Filter:");
-            ui.text_edit_singleline(&mut self.filter);
+            ui.text_edit_singleline(&mut self.filter)
+                .on_hover_text("Space-separated terms, e.g. `my_function task_nr=2`");
             self.filter = self.filter.to_lowercase();
             if ui.button("ｘ").clicked() {
                 self.filter.clear();
             }
+
+            self.update_matches(span_tree);
+            if self.is_empty() {
+                return;
+            }
+
+            match self.current {
+                Some(current) => {
+                    ui.label(format!("{} / {} matches", current + 1, self.matches.len()))
+                }
+                None => ui.label(format!("{} matches", self.matches.len())),
+            };
+
+            let num_matches = self.matches.len();
+            if num_matches > 0 {
+                if ui.button("⏴").on_hover_text("Previous match").clicked() {
+                    let current = self.current.map_or(num_matches - 1, |current| {
+                        (current + num_matches - 1) % num_matches
+                    });
+                    self.current = Some(current);
+                    jump = Some(self.matches[current]);
+                }
+                if ui.button("⏵").on_hover_text("Next match").clicked() {
+                    let current = self
+                        .current
+                        .map_or(0, |current| (current + 1) % num_matches);
+                    self.current = Some(current);
+                    jump = Some(self.matches[current]);
+                }
+            }
         });
+        jump
+    }
+
+    fn update_matches(&mut self, span_tree: &SpanTree) {
+        let key = Some((span_tree.generation(), self.filter.clone()));
+        if self.matches_key == key {
+            return;
+        }
+        let filter_changed =
+            self.matches_key.as_ref().map(|(_, filter)| filter) != Some(&self.filter);
+        self.matches_key = key;
+
+        let previous_match = self.current.map(|current| self.matches[current]);
+
+        let mut matches: Vec<(NanoSecond, SpanId)> = if self.is_empty() {
+            vec![]
+        } else {
+            span_tree
+                .nodes
+                .values()
+                .filter(|node| self.matches_node(span_tree, node))
+                .map(|node| (estimate_lifetime(span_tree, node).0, node.span.id))
+                .collect()
+        };
+        matches.sort_by_key(|(start_ns, _)| *start_ns);
+        self.matches = matches.into_iter().map(|(_, span_id)| span_id).collect();
+        self.match_set = self.matches.iter().copied().collect();
+
+        // Keep our place when new spans arrive:
+        self.current = if filter_changed {
+            None
+        } else {
+            previous_match.and_then(|span_id| self.matches.iter().position(|&id| id == span_id))
+        };
     }
 
     /// if true, show everything
     fn is_empty(&self) -> bool {
-        self.filter.is_empty()
+        self.filter.trim().is_empty()
     }
 
-    fn include(&self, id: &str) -> bool {
-        if self.filter.is_empty() {
-            true
-        } else {
-            id.to_lowercase().contains(&self.filter)
-        }
+    fn matches_node(&self, span_tree: &SpanTree, node: &SpanNode) -> bool {
+        let description = span_tree.span_description(&node.span.id).to_lowercase();
+        self.filter.split_whitespace().all(|term| {
+            if let Some((key, value)) = term.split_once('=') {
+                node.span.fields.iter().any(|(field_key, field_value)| {
+                    field_key.to_lowercase() == key && value_matches(field_value, value)
+                })
+            } else {
+                description.contains(term)
+            }
+        })
+    }
+
+    fn include(&self, span_id: &SpanId) -> bool {
+        self.is_empty() || self.match_set.contains(span_id)
+    }
+
+    /// The span we last jumped to.
+    fn current_match(&self) -> Option<SpanId> {
+        self.current.map(|current| self.matches[current])
+    }
+}
+
+/// Compare with a lowercase search string. Strings are compared without quotes.
+fn value_matches(value: &rr_data::Value, query: &str) -> bool {
+    match value {
+        rr_data::Value::String(string) => string.to_lowercase() == query,
+        _ => value.to_string().to_lowercase() == query,
     }
 }
 
@@ -76,6 +179,12 @@ impl Info {
             + self.canvas.width() * (ns.saturating_sub(self.min_ns) as f32 + options.pan_x_in_ns)
                 / options.canvas_width_ns
     }
+
+    fn ns_from_point(&self, options: &FlameGraph, x: f32) -> NanoSecond {
+        let relative_ns = (x - self.canvas.min.x) / self.canvas.width() * options.canvas_width_ns
+            - options.pan_x_in_ns;
+        self.min_ns + relative_ns as NanoSecond
+    }
 }
 
 // ----------------------------------------------------------------------------
@@ -90,6 +199,17 @@ pub struct FlameGraph {
     /// How much we have panned sideways:
     pub pan_x_in_ns: f32,
 
+    /// Keep the time window pinned to the newest data.
+    ///
+    /// Disengaged when the user pans or zooms.
+    pub follow: bool,
+
+    /// How many seconds of the newest data to show when following.
+    pub follow_seconds: f32,
+
+    /// Show the spans merged by callsite ancestry path instead of on a timeline.
+    pub merged: bool,
+
     // --------------------
     // Visuals:
     /// Events shorter than this many points aren't painted
@@ -101,10 +221,37 @@ pub struct FlameGraph {
     pub spacing: f32,
     pub rounding: f32,
 
+    /// Height of the plot of a numeric event field, under the flame graph.
+    pub plot_height: f32,
+
     // --------------------
     #[serde(skip)]
     filter: Filter,
 
+    #[serde(skip)]
+    field_plot: crate::field_plot::FieldPlot,
+
+    #[serde(skip)]
+    merged_graph: crate::merged_flamegraph::MergedFlameGraph,
+
+    /// Where the time axis starts (nanoseconds since epoch), if not at the first span.
+    ///
+    /// Used to align the flame graphs of several topics.
+    #[serde(skip)]
+    time_origin_ns: Option<NanoSecond>,
+
+    /// Time range selected with shift-drag (nanoseconds since epoch).
+    #[serde(skip)]
+    selection: Option<(NanoSecond, NanoSecond)>,
+
+    /// Where the ongoing shift-drag started.
+    #[serde(skip)]
+    selection_start_ns: Option<NanoSecond>,
+
+    /// Fade out all spans except the ones from this callsite.
+    #[serde(skip)]
+    highlight_callsite: Option<CallsiteId>,
+
     /// Used to animate zoom+pan.
     ///
     /// First part is `now()`, second is range.
@@ -118,6 +265,11 @@ impl Default for FlameGraph {
             canvas_width_ns: 0.0,
             pan_x_in_ns: 0.0,
 
+            follow: false,
+            follow_seconds: 5.0,
+
+            merged: false,
+
             // cull_width: 0.5, // save some CPU?
             cull_width: 0.0, // no culling
             min_width: 2.0,
@@ -126,7 +278,15 @@ impl Default for FlameGraph {
             spacing: 2.0,
             rounding: 3.0,
 
+            plot_height: 120.0,
+
             filter: Default::default(),
+            field_plot: Default::default(),
+            merged_graph: Default::default(),
+            time_origin_ns: None,
+            selection: None,
+            selection_start_ns: None,
+            highlight_callsite: None,
 
             zoom_to_relative_ns_range: None,
         }
@@ -134,9 +294,124 @@ impl Default for FlameGraph {
 }
 
 impl FlameGraph {
-    pub fn ui(&mut self, ui: &mut egui::Ui, span_tree: &SpanTree) {
-        self.filter.ui(ui);
-        flamegraph_ui(self, ui, span_tree);
+    pub fn ui(&mut self, ui: &mut egui::Ui, span_tree: &SpanTree, events: &[EventRow]) {
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut self.merged, false, "Timeline")
+                .on_hover_text("Show each span when it was active");
+            ui.radio_value(&mut self.merged, true, "Merged")
+                .on_hover_text("Merge all spans with the same callsite ancestry path");
+        });
+
+        if self.merged {
+            let style = crate::merged_flamegraph::Style {
+                rect_height: self.rect_height,
+                spacing: self.spacing,
+                rounding: self.rounding,
+            };
+            self.merged_graph.ui(ui, span_tree, &style, None);
+            return;
+        }
+
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.follow, "Follow")
+                .on_hover_text("Keep the newest data in view. Pan or zoom to stop following.");
+            ui.add(
+                egui::DragValue::new(&mut self.follow_seconds)
+                    .clamp_range(0.1..=3600.0)
+                    .speed(0.1)
+                    .suffix(" s"),
+            )
+            .on_hover_text("How much of the newest data to show when following");
+            ui.separator();
+            ui.weak("Shift-drag to select a time range");
+        });
+        if let Some(span_id) = self.filter.ui(ui, span_tree) {
+            self.zoom_to_span(ui, span_tree, &span_id);
+        }
+        if let Some(callsite_id) = self.highlight_callsite {
+            ui.horizontal(|ui| {
+                ui.label(format!(
+                    "Highlighting: {}",
+                    span_tree.callsite_name(&callsite_id)
+                ));
+                if ui.button("ｘ").on_hover_text("Stop highlighting").clicked() {
+                    self.highlight_callsite = None;
+                }
+            });
+        }
+        self.field_plot.selector_ui(ui, span_tree, events);
+        self.field_plot.update(events);
+
+        if self.field_plot.is_active() {
+            // Leave room for the plot under the flame graph:
+            let plot_height = self.plot_height + 2.0 * ui.spacing().item_spacing.y;
+            let flamegraph_size = ui.available_size() - vec2(0.0, plot_height);
+            let canvas = ui
+                .allocate_ui(flamegraph_size, |ui| flamegraph_ui(self, ui, span_tree))
+                .inner;
+            if let Some((canvas, min_ns, max_ns)) = canvas {
+                plot_ui(self, ui, canvas, min_ns, max_ns);
+            }
+        } else {
+            flamegraph_ui(self, ui, span_tree);
+        }
+    }
+
+    /// Zoom and pan, so that several flame graphs can share a time axis.
+    pub fn view(&self) -> (f32, f32) {
+        (self.canvas_width_ns, self.pan_x_in_ns)
+    }
+
+    pub fn set_view(&mut self, (canvas_width_ns, pan_x_in_ns): (f32, f32)) {
+        self.canvas_width_ns = canvas_width_ns;
+        self.pan_x_in_ns = pan_x_in_ns;
+    }
+
+    /// Start the time axis here (nanoseconds since epoch) instead of at the first span.
+    /// Only times before the first span have any effect.
+    pub fn set_time_origin(&mut self, time_origin_ns: Option<NanoSecond>) {
+        self.time_origin_ns = time_origin_ns;
+    }
+
+    /// The start of the time axis and the time of the last event.
+    fn ns_range(&self, span_tree: &SpanTree) -> Option<(NanoSecond, NanoSecond)> {
+        let (min_ns, max_ns) = span_tree.ns_range()?;
+        let min_ns = self
+            .time_origin_ns
+            .map_or(min_ns, |origin| origin.min(min_ns));
+        Some((min_ns, max_ns))
+    }
+
+    /// The time range selected with shift-drag, in nanoseconds since epoch.
+    pub fn selection(&self) -> Option<(NanoSecond, NanoSecond)> {
+        self.selection
+    }
+
+    pub fn clear_selection(&mut self) {
+        if self.merged_graph.time_range == self.selection {
+            self.merged_graph.time_range = None;
+        }
+        self.selection = None;
+    }
+
+    /// Animate the view to show the given span.
+    fn zoom_to_span(&mut self, ui: &egui::Ui, span_tree: &SpanTree, span_id: &SpanId) {
+        if let (Some(node), Some((start_ns, _))) =
+            (span_tree.nodes.get(span_id), self.ns_range(span_tree))
+        {
+            let (min_ns, max_ns) = estimate_lifetime(span_tree, node);
+            let margin_ns = (max_ns - min_ns) / 10 + 1;
+            self.follow = false;
+            self.zoom_to_relative_ns_range = Some((
+                ui.input().time,
+                (min_ns - margin_ns - start_ns, max_ns + margin_ns - start_ns),
+            ));
+        }
+    }
+
+    /// Fade out all spans except the ones from this callsite.
+    pub fn highlight_callsite(&mut self, callsite_id: Option<CallsiteId>) {
+        self.highlight_callsite = callsite_id;
     }
 
     fn pan_x_in_points(&self, info: &Info) -> f32 {
@@ -159,56 +434,88 @@ impl FlameGraph {
 
 // ----------------------------------------------------------------------------
 
-fn flamegraph_ui(options: &mut FlameGraph, ui: &mut egui::Ui, span_tree: &SpanTree) {
-    Frame::dark_canvas(ui.style()).show(ui, |ui| {
-        let available_height = ui.max_rect().bottom() - ui.min_rect().bottom();
-        ScrollArea::vertical().show(ui, |ui| {
-            let mut canvas_rect = ui.available_rect_before_wrap();
-            canvas_rect.max.y = f32::INFINITY;
-            let response = ui.interact(canvas_rect, ui.id(), Sense::click_and_drag());
-
-            let (min_ns, max_ns) = if let Some(ns_range) = span_tree.ns_range() {
-                ns_range
-            } else {
-                return;
-            };
-
-            let info = Info {
-                ctx: ui.ctx().clone(),
-                canvas: canvas_rect,
-                response,
-                painter: ui.painter_at(canvas_rect),
-                min_ns,
-                max_ns,
-                text_height: 15.0, // TODO
-                font_id: TextStyle::Body.resolve(ui.style()),
-            };
-
-            interact_with_canvas(options, &info.response, &info);
-
-            let where_to_put_timeline = info.painter.add(Shape::Noop);
-
-            let max_y = ui_canvas(options, &info, span_tree);
-
-            let mut used_rect = canvas_rect;
-            used_rect.max.y = max_y;
+/// Returns the canvas rectangle and the time range, if there is anything to show.
+fn flamegraph_ui(
+    options: &mut FlameGraph,
+    ui: &mut egui::Ui,
+    span_tree: &SpanTree,
+) -> Option<(Rect, NanoSecond, NanoSecond)> {
+    Frame::dark_canvas(ui.style())
+        .show(ui, |ui| {
+            let available_height = ui.max_rect().bottom() - ui.min_rect().bottom();
+            ScrollArea::vertical()
+                .show(ui, |ui| {
+                    let mut canvas_rect = ui.available_rect_before_wrap();
+                    canvas_rect.max.y = f32::INFINITY;
+                    let response = ui.interact(canvas_rect, ui.id(), Sense::click_and_drag());
+
+                    let (min_ns, max_ns) = options.ns_range(span_tree)?;
+
+                    let info = Info {
+                        ctx: ui.ctx().clone(),
+                        canvas: canvas_rect,
+                        response,
+                        painter: ui.painter_at(canvas_rect),
+                        min_ns,
+                        max_ns,
+                        text_height: 15.0, // TODO
+                        font_id: TextStyle::Body.resolve(ui.style()),
+                    };
+
+                    interact_with_canvas(options, &info.response, &info);
+                    update_view(options, &info);
+
+                    let where_to_put_timeline = info.painter.add(Shape::Noop);
+
+                    let max_y = ui_canvas(options, &info, span_tree);
+
+                    let mut used_rect = canvas_rect;
+                    used_rect.max.y = max_y;
+
+                    // Fill out space that we don't use so that the `ScrollArea` doesn't collapse in height:
+                    used_rect.max.y = used_rect.max.y.max(used_rect.min.y + available_height);
+
+                    let timeline = paint_timeline(&info, used_rect, options, min_ns);
+                    info.painter
+                        .set(where_to_put_timeline, Shape::Vec(timeline));
 
-            // Fill out space that we don't use so that the `ScrollArea` doesn't collapse in height:
-            used_rect.max.y = used_rect.max.y.max(used_rect.min.y + available_height);
+                    if let Some(selection) = options.selection {
+                        paint_selection(&info, used_rect, options, selection);
+                    }
 
-            let timeline = paint_timeline(&info, used_rect, options, min_ns);
-            info.painter
-                .set(where_to_put_timeline, Shape::Vec(timeline));
+                    ui.allocate_rect(used_rect, Sense::click_and_drag());
 
-            ui.allocate_rect(used_rect, Sense::click_and_drag());
-        });
-    });
+                    Some((canvas_rect, min_ns, max_ns))
+                })
+                .inner
+        })
+        .inner
 }
 
 fn interact_with_canvas(view: &mut FlameGraph, response: &Response, info: &Info) {
+    // Shift-drag to select a time range:
+    if response.drag_started() && info.ctx.input().modifiers.shift {
+        if let Some(pos) = response.interact_pointer_pos() {
+            view.selection_start_ns = Some(info.ns_from_point(view, pos.x));
+        }
+    }
+    if let Some(start_ns) = view.selection_start_ns {
+        if let Some(pos) = response.interact_pointer_pos() {
+            let end_ns = info.ns_from_point(view, pos.x);
+            view.selection = Some((start_ns.min(end_ns), start_ns.max(end_ns)));
+        }
+        if !response.dragged() {
+            // Done selecting. Let the merged flame graph show the same range:
+            view.selection_start_ns = None;
+            view.merged_graph.time_range = view.selection;
+        }
+        return; // Don't pan while selecting
+    }
+
     if response.drag_delta().x != 0.0 {
         view.apply_pan_x_delta_in_points(info, response.drag_delta().x);
         view.zoom_to_relative_ns_range = None;
+        view.follow = false;
     }
 
     if response.hovered() {
@@ -216,6 +523,7 @@ fn interact_with_canvas(view: &mut FlameGraph, response: &Response, info: &Info)
         if info.ctx.input().scroll_delta.x != 0.0 {
             view.apply_pan_x_delta_in_points(info, info.ctx.input().scroll_delta.x);
             view.zoom_to_relative_ns_range = None;
+            view.follow = false;
         }
 
         let mut zoom_factor = info.ctx.input().zoom_delta_2d().x;
@@ -235,6 +543,7 @@ fn interact_with_canvas(view: &mut FlameGraph, response: &Response, info: &Info)
                 );
             }
             view.zoom_to_relative_ns_range = None;
+            view.follow = false;
         }
     }
 
@@ -242,13 +551,26 @@ fn interact_with_canvas(view: &mut FlameGraph, response: &Response, info: &Info)
         // Reset view
         view.zoom_to_relative_ns_range =
             Some((info.ctx.input().time, (0, info.max_ns - info.min_ns)));
+        view.follow = false;
+    }
+}
+
+/// Follow the newest data, or animate zoom+pan. Call once per frame.
+fn update_view(view: &mut FlameGraph, info: &Info) {
+    if view.follow {
+        // Show the last `follow_seconds`, or everything from the start if there isn't that much yet:
+        let window_ns = (view.follow_seconds as f64 * 1e9) as NanoSecond;
+        let window_start_ns = (info.max_ns - info.min_ns - window_ns).max(0);
+        view.canvas_width_ns = window_ns as f32;
+        view.pan_x_in_ns = -(window_start_ns as f32);
+        view.zoom_to_relative_ns_range = None;
     }
 
     if let Some((start_time, (min_ns, max_ns))) = view.zoom_to_relative_ns_range {
         const ZOOM_DURATION: f32 = 0.75;
         let t = ((info.ctx.input().time - start_time) as f32 / ZOOM_DURATION).min(1.0);
 
-        let canvas_width = response.rect.width();
+        let canvas_width = info.canvas.width();
 
         let target_canvas_width_ns = (max_ns - min_ns) as f32;
         let target_pan_in_points = -canvas_width * min_ns as f32 / target_canvas_width_ns;
@@ -489,6 +811,7 @@ fn paint_span(
     };
 
     if is_hovered && info.response.clicked() {
+        options.follow = false;
         options.zoom_to_relative_ns_range = Some((
             info.ctx.input().time,
             (min_ns - info.min_ns, max_ns - info.min_ns),
@@ -504,8 +827,7 @@ fn paint_span(
     let mut min_width = options.min_width;
 
     if !options.filter.is_empty() {
-        let span_description = span_tree.span_description(&node.span.id);
-        if options.filter.include(&span_description) {
+        if options.filter.include(&node.span.id) {
             // keep full opacity
             min_width *= 2.0; // make it more visible even when thin
         } else {
@@ -513,6 +835,14 @@ fn paint_span(
         }
     }
 
+    if let Some(callsite_id) = options.highlight_callsite {
+        if node.span.callsite_id == callsite_id {
+            min_width = 2.0 * options.min_width;
+        } else {
+            rect_color = rect_color.multiply(0.075);
+        }
+    }
+
     paint_rect(options, info, min_width, rect, rect_color * 0.5);
 
     for interval in &node.intervals {
@@ -528,6 +858,11 @@ fn paint_span(
         }
     }
 
+    if options.filter.current_match() == Some(node.span.id) {
+        info.painter
+            .rect_stroke(rect, options.rounding, Stroke::new(2.0, Color32::WHITE));
+    }
+
     // TODO: paint events
 
     let wide_enough_for_text = max_x - min_x > 32.0;
@@ -553,6 +888,13 @@ fn paint_span(
 
     if is_hovered {
         egui::popup::show_tooltip_for(&info.ctx, Id::new("node-tooltip"), &rect, |ui| {
+            if let Some(times) = span_tree.span_times(node) {
+                ui.strong(format!(
+                    "{} total, {} self",
+                    crate::misc::format_duration_ns(times.total_ns),
+                    crate::misc::format_duration_ns(times.self_ns)
+                ));
+            }
             span_tree.span_summary_ui(ui, node);
         });
     }
@@ -563,6 +905,36 @@ fn paint_span(
     }
 }
 
+fn paint_selection(
+    info: &Info,
+    rect: Rect,
+    options: &FlameGraph,
+    (min_ns, max_ns): (NanoSecond, NanoSecond),
+) {
+    let min_x = info.point_from_ns(options, min_ns);
+    let max_x = info.point_from_ns(options, max_ns).max(min_x + 1.0);
+    let selection_rect = Rect::from_x_y_ranges(min_x..=max_x, rect.y_range());
+
+    let color = Color32::from_rgb(100, 150, 255);
+    info.painter
+        .rect_filled(selection_rect, 0.0, color.linear_multiply(0.15));
+    for x in [min_x, max_x] {
+        info.painter.line_segment(
+            [pos2(x, rect.top()), pos2(x, rect.bottom())],
+            Stroke::new(1.0, color),
+        );
+    }
+
+    let text = crate::misc::format_duration_ns(max_ns - min_ns);
+    info.painter.text(
+        pos2(selection_rect.center().x, rect.top() + info.text_height),
+        Align2::CENTER_TOP,
+        text,
+        info.font_id.clone(),
+        Color32::WHITE,
+    );
+}
+
 fn paint_rect(options: &FlameGraph, info: &Info, min_width: f32, rect: Rect, rect_color: Rgba) {
     if rect.width() <= min_width {
         // faster to draw it as a thin line
@@ -575,7 +947,7 @@ fn paint_rect(options: &FlameGraph, info: &Info, min_width: f32, rect: Rect, rec
     }
 }
 
-fn color_from_callsite_id(callsite_id: &CallsiteId) -> Rgba {
+pub fn color_from_callsite_id(callsite_id: &CallsiteId) -> Rgba {
     use rand::rngs::SmallRng;
     use rand::{Rng, SeedableRng};
 
@@ -641,6 +1013,148 @@ fn estimate_lifetime(span_tree: &SpanTree, node: &SpanNode) -> (NanoSecond, Nano
 
 // ----------------------------------------------------------------------------
 
+/// Plot of a numeric event field, with the same time axis as the flame graph above it.
+fn plot_ui(
+    options: &mut FlameGraph,
+    ui: &mut egui::Ui,
+    flamegraph_canvas: Rect,
+    min_ns: NanoSecond,
+    max_ns: NanoSecond,
+) {
+    Frame::dark_canvas(ui.style()).show(ui, |ui| {
+        let (rect, response) = ui.allocate_exact_size(
+            vec2(ui.available_width(), options.plot_height),
+            Sense::click_and_drag(),
+        );
+
+        let info = Info {
+            ctx: ui.ctx().clone(),
+            // Same horizontal extent as the flame graph, so that the time axes line up:
+            canvas: Rect::from_x_y_ranges(flamegraph_canvas.x_range(), rect.y_range()),
+            response,
+            painter: ui.painter_at(rect),
+            min_ns,
+            max_ns,
+            text_height: 15.0, // TODO
+            font_id: TextStyle::Body.resolve(ui.style()),
+        };
+
+        // Panning and zooming the plot pans and zooms the flame graph:
+        let view_before = (options.canvas_width_ns, options.pan_x_in_ns);
+        interact_with_canvas(options, &info.response, &info);
+        if view_before != (options.canvas_width_ns, options.pan_x_in_ns) {
+            info.ctx.request_repaint(); // the flame graph was already painted this frame
+        }
+
+        let timeline = paint_timeline(&info, info.canvas, options, min_ns);
+        info.painter.extend(timeline);
+
+        paint_plot(options, &info);
+    });
+}
+
+fn paint_plot(options: &FlameGraph, info: &Info) {
+    let field_plot = &options.field_plot;
+    let field_name = field_plot.field_name().unwrap_or_default();
+    let points = field_plot.points();
+    let canvas = info.canvas;
+
+    let (min_value, max_value) = if let Some(value_range) = field_plot.value_range() {
+        value_range
+    } else {
+        info.painter.text(
+            canvas.center(),
+            Align2::CENTER_CENTER,
+            format!("No numeric values of {:?}", field_name),
+            info.font_id.clone(),
+            Color32::GRAY,
+        );
+        return;
+    };
+
+    // Leave room for the time labels at the top and bottom:
+    let y_range = (canvas.bottom() - info.text_height)..=(canvas.top() + info.text_height);
+    let y_from_value = |value: f64| {
+        if min_value < max_value {
+            let t = (value - min_value) / (max_value - min_value);
+            lerp(y_range.clone(), t as f32)
+        } else {
+            canvas.center().y
+        }
+    };
+
+    // Only look at the visible points, plus one on each side so the line reaches the edges:
+    let visible_min_ns = info.ns_from_point(options, canvas.left());
+    let visible_max_ns = info.ns_from_point(options, canvas.right());
+    let first = points
+        .partition_point(|(time, _)| time.nanos_since_epoch() < visible_min_ns)
+        .saturating_sub(1);
+    let last = (points.partition_point(|(time, _)| time.nanos_since_epoch() <= visible_max_ns) + 1)
+        .min(points.len());
+    let visible_points = &points[first..last.max(first)];
+
+    let to_pos = |(time, value): &(rr_data::Time, f64)| {
+        pos2(
+            info.point_from_ns(options, time.nanos_since_epoch()),
+            y_from_value(*value),
+        )
+    };
+
+    let color = field_plot.callsite_id().map_or(Rgba::WHITE, |callsite_id| {
+        color_from_callsite_id(&callsite_id)
+    });
+
+    info.painter.add(Shape::line(
+        visible_points.iter().map(to_pos).collect(),
+        Stroke::new(1.5, color),
+    ));
+    if visible_points.len() < 200 {
+        for point in visible_points {
+            info.painter.circle_filled(to_pos(point), 2.5, color);
+        }
+    }
+
+    let label_color = Rgba::from_white_alpha(0.5);
+    info.painter.text(
+        pos2(canvas.left() + 4.0, *y_range.end()),
+        Align2::LEFT_TOP,
+        format!("{} (max {})", field_name, max_value),
+        info.font_id.clone(),
+        label_color.into(),
+    );
+    info.painter.text(
+        pos2(canvas.left() + 4.0, *y_range.start()),
+        Align2::LEFT_BOTTOM,
+        format!("min {}", min_value),
+        info.font_id.clone(),
+        label_color.into(),
+    );
+
+    // Show the value of the point closest to the mouse:
+    if let Some(mouse_pos) = info.response.hover_pos() {
+        let closest = visible_points.iter().min_by(|a, b| {
+            let distance = |point| (to_pos(point).x - mouse_pos.x).abs();
+            distance(a)
+                .partial_cmp(&distance(b))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        if let Some(point) = closest {
+            let pos = to_pos(point);
+            if (pos.x - mouse_pos.x).abs() < 16.0 {
+                info.painter
+                    .circle_stroke(pos, 5.0, Stroke::new(1.0, HOVER_COLOR));
+                let (time, value) = point;
+                egui::popup::show_tooltip_at_pointer(&info.ctx, Id::new("plot-tooltip"), |ui| {
+                    ui.label(format!("{} = {}", field_name, value));
+                    ui.weak(time.format());
+                });
+            }
+        }
+    }
+}
+
+// ----------------------------------------------------------------------------
+
 fn paint_timeline(
     info: &Info,
     canvas: Rect,
@@ -653,7 +1167,11 @@ fn paint_timeline(
         return shapes;
     }
 
-    let alpha_multiplier = if options.filter.is_empty() { 0.3 } else { 0.1 };
+    let alpha_multiplier = if options.filter.is_empty() && options.highlight_callsite.is_none() {
+        0.3
+    } else {
+        0.1
+    };
 
     // We show all measurements relative to min_ns
 
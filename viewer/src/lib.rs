@@ -6,10 +6,17 @@
 
 mod app;
 mod data_event_log;
+mod diff;
 mod event_log;
+mod event_table;
+mod field_plot;
 mod flamegraph;
+mod merged_flamegraph;
+mod metrics;
 mod misc;
+mod span_stats;
 mod span_tree;
+mod topic_search;
 mod viewer;
 pub use app::WsClientApp;
 
@@ -2,12 +2,44 @@
 #![cfg_attr(not(debug_assertions), deny(warnings))] // Forbid warnings in release builds
 #![warn(clippy::all, rust_2018_idioms)]
 
+#[cfg(not(target_arch = "wasm32"))]
+const USAGE: &str = "Usage: viewer [--root-cert PEM]
+
+Views the topics of a pub-sub server.
+
+  --root-cert PEM   For wss:// urls, trust only the certificates in this file
+                    (e.g. the self-signed certificate of the server) instead of
+                    the system root certificates (or those in $SSL_CERT_FILE).";
+
 // When compiling natively:
 #[cfg(not(target_arch = "wasm32"))]
 #[tokio::main]
 async fn main() {
     tracing_subscriber::fmt::init();
-    let app = viewer::WsClientApp::default();
+    let mut app = viewer::WsClientApp::default();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--root-cert" => {
+                let path = args.next().unwrap_or_default();
+                match ws_client::RootCerts::from_pem_file(&path) {
+                    Ok(root_certs) => app = app.with_root_certs(root_certs),
+                    Err(err) => exit_with_usage(&format!("Bad --root-cert {:?}: {}", path, err)),
+                }
+            }
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            _ => exit_with_usage(&format!("Unknown argument {:?}", arg)),
+        }
+    }
     let native_options = eframe::NativeOptions::default();
     eframe::run_native(Box::new(app), native_options);
 }
+
+#[cfg(not(target_arch = "wasm32"))]
+fn exit_with_usage(err: &str) -> ! {
+    eprintln!("{}\n\n{}", err, USAGE);
+    std::process::exit(2);
+}
@@ -0,0 +1,432 @@
+use eframe::egui;
+use egui::*;
+use rr_data::CallsiteId;
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+
+use crate::flamegraph::{color_from_callsite_id, NanoSecond};
+use crate::misc::format_duration_ns;
+use crate::span_tree::{SpanNode, SpanTree};
+
+/// All the spans with the same callsite ancestry path, merged into one.
+#[derive(Default)]
+pub struct MergedNode {
+    /// Number of spans merged into this node.
+    pub count: usize,
+    pub total_ns: NanoSecond,
+    pub self_ns: NanoSecond,
+    pub children: HashMap<CallsiteId, MergedNode>,
+}
+
+impl MergedNode {
+    /// Follow a callsite path down the tree.
+    pub fn get(&self, path: &[CallsiteId]) -> Option<&MergedNode> {
+        match path.split_first() {
+            Some((first, rest)) => self.children.get(first)?.get(rest),
+            None => Some(self),
+        }
+    }
+}
+
+/// Merge all spans by callsite ancestry path.
+///
+/// Only the time within `range` (nanoseconds since epoch) is counted, if given.
+/// The returned root has no callsite of its own; its time is the sum of its children.
+pub fn merge_spans(span_tree: &SpanTree, range: Option<(NanoSecond, NanoSecond)>) -> MergedNode {
+    fn add(
+        span_tree: &SpanTree,
+        range: Option<(NanoSecond, NanoSecond)>,
+        node: &SpanNode,
+        parent: &mut MergedNode,
+    ) {
+        let merged = parent.children.entry(node.span.callsite_id).or_default();
+        if let Some(times) = span_tree.span_times_in_range(node, range) {
+            merged.count += 1;
+            merged.total_ns += times.total_ns;
+            merged.self_ns += times.self_ns;
+        }
+        for child_id in &node.children {
+            if let Some(child) = span_tree.nodes.get(child_id) {
+                add(span_tree, range, child, merged);
+            }
+        }
+    }
+
+    fn prune(node: &mut MergedNode) {
+        node.children.retain(|_, child| {
+            prune(child);
+            child.total_ns > 0 || !child.children.is_empty()
+        });
+    }
+
+    let mut root = MergedNode::default();
+    for root_id in &span_tree.roots {
+        if let Some(node) = span_tree.nodes.get(root_id) {
+            add(span_tree, range, node, &mut root);
+        }
+    }
+    prune(&mut root);
+    root.total_ns = root.children.values().map(|child| child.total_ns).sum();
+    root
+}
+
+// ----------------------------------------------------------------------------
+
+/// A classic flame graph: the width of each bar is the summed time of all spans
+/// with that callsite ancestry path.
+#[derive(Default)]
+pub struct MergedFlameGraph {
+    /// Only count the time within this range (nanoseconds since epoch).
+    /// `None` means all of it.
+    pub time_range: Option<(NanoSecond, NanoSecond)>,
+
+    /// Zoomed in on this callsite path.
+    zoom_path: Vec<CallsiteId>,
+
+    /// Recomputed when the [`SpanTree`] or the time range changes.
+    merged: MergedNode,
+    merged_key: Option<(u64, Option<(NanoSecond, NanoSecond)>)>,
+}
+
+/// How to lay out the bars.
+pub struct Style {
+    pub rect_height: f32,
+    pub spacing: f32,
+    pub rounding: f32,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Self {
+            rect_height: 16.0,
+            spacing: 2.0,
+            rounding: 3.0,
+        }
+    }
+}
+
+impl MergedFlameGraph {
+    /// With a `baseline` (e.g. from [`crate::diff::Diff`]) the bars are colored by how much
+    /// slower (red) or faster (green) they are compared to it.
+    pub fn ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        span_tree: &SpanTree,
+        style: &Style,
+        baseline: Option<&MergedNode>,
+    ) {
+        let key = Some((span_tree.generation(), self.time_range));
+        if self.merged_key != key {
+            self.merged = merge_spans(span_tree, self.time_range);
+            self.merged_key = key;
+        }
+
+        if baseline.is_none() {
+            self.time_range_ui(ui, span_tree);
+        }
+        self.zoom_path_ui(ui, span_tree);
+
+        if self.merged.get(&self.zoom_path).is_none() {
+            self.zoom_path.clear();
+        }
+        let root = match self.merged.get(&self.zoom_path) {
+            Some(root) if root.total_ns > 0 => root,
+            _ => {
+                ui.label("No finished spans in this time range.");
+                return;
+            }
+        };
+
+        let mut clicked_path = None;
+        Frame::dark_canvas(ui.style()).show(ui, |ui| {
+            ScrollArea::vertical()
+                .auto_shrink([false; 2])
+                .show(ui, |ui| {
+                    let canvas = ui.available_rect_before_wrap();
+                    let response = ui.interact(canvas, ui.id(), Sense::click());
+                    let painter = ui.painter_at(canvas);
+                    let mut painter_info = PaintInfo {
+                        ctx: ui.ctx().clone(),
+                        painter,
+                        response,
+                        span_tree,
+                        style,
+                        font_id: TextStyle::Body.resolve(ui.style()),
+                        grand_total_ns: root.total_ns,
+                        is_diff: baseline.is_some(),
+                        path: self.zoom_path.clone(),
+                        max_y: canvas.top(),
+                        clicked_path: None,
+                    };
+
+                    let mut left = canvas.left();
+                    let mut children: Vec<(&CallsiteId, &MergedNode)> =
+                        root.children.iter().collect();
+                    sort_by_name(span_tree, &mut children);
+                    let baseline_root = baseline.and_then(|baseline| baseline.get(&self.zoom_path));
+                    for (callsite_id, child) in children {
+                        let baseline_child =
+                            baseline_root.and_then(|baseline| baseline.children.get(callsite_id));
+                        let width = canvas.width() * child.total_ns as f32 / root.total_ns as f32;
+                        paint_node(
+                            &mut painter_info,
+                            *callsite_id,
+                            child,
+                            baseline_child,
+                            left..=(left + width),
+                            canvas.top(),
+                        );
+                        left += width;
+                    }
+
+                    ui.allocate_rect(
+                        Rect::from_x_y_ranges(canvas.x_range(), canvas.top()..=painter_info.max_y),
+                        Sense::hover(),
+                    );
+                    clicked_path = painter_info.clicked_path;
+                });
+        });
+
+        if let Some(path) = clicked_path {
+            self.zoom_path = path;
+        }
+    }
+
+    fn time_range_ui(&mut self, ui: &mut egui::Ui, span_tree: &SpanTree) {
+        let (start_ns, end_ns) = match span_tree.ns_range() {
+            Some(range) => range,
+            None => return,
+        };
+
+        ui.horizontal(|ui| {
+            let mut limit = self.time_range.is_some();
+            if ui
+                .checkbox(&mut limit, "Limit time range")
+                .on_hover_text("Only count the time spent within a range")
+                .changed()
+            {
+                self.time_range = if limit {
+                    Some((start_ns, end_ns))
+                } else {
+                    None
+                };
+            }
+
+            if let Some((min_ns, max_ns)) = &mut self.time_range {
+                // Edit in seconds since the first span:
+                let to_s = |ns: NanoSecond| (ns - start_ns) as f64 * 1e-9;
+                let from_s = |s: f64| start_ns + (s * 1e9) as NanoSecond;
+                let duration_s = to_s(end_ns);
+
+                let mut min_s = to_s(*min_ns);
+                let mut max_s = to_s(*max_ns);
+                ui.label("from");
+                ui.add(
+                    DragValue::new(&mut min_s)
+                        .clamp_range(0.0..=duration_s)
+                        .speed(0.01)
+                        .suffix(" s"),
+                );
+                ui.label("to");
+                ui.add(
+                    DragValue::new(&mut max_s)
+                        .clamp_range(0.0..=duration_s)
+                        .speed(0.01)
+                        .suffix(" s"),
+                );
+                *min_ns = from_s(min_s.min(max_s));
+                *max_ns = from_s(max_s.max(min_s));
+            }
+        });
+    }
+
+    fn zoom_path_ui(&mut self, ui: &mut egui::Ui, span_tree: &SpanTree) {
+        if self.zoom_path.is_empty() {
+            ui.weak("Click a bar to zoom in on it.");
+            return;
+        }
+
+        ui.horizontal_wrapped(|ui| {
+            let mut new_len = None;
+            if ui.button("All").clicked() {
+                new_len = Some(0);
+            }
+            for (i, callsite_id) in self.zoom_path.iter().enumerate() {
+                ui.label("➡");
+                if ui.button(span_tree.callsite_name(callsite_id)).clicked() {
+                    new_len = Some(i + 1);
+                }
+            }
+            if let Some(new_len) = new_len {
+                self.zoom_path.truncate(new_len);
+            }
+        });
+    }
+}
+
+// ----------------------------------------------------------------------------
+
+struct PaintInfo<'a> {
+    ctx: egui::Context,
+    painter: egui::Painter,
+    response: Response,
+    span_tree: &'a SpanTree,
+    style: &'a Style,
+    font_id: FontId,
+
+    /// The width of the canvas corresponds to this much time.
+    grand_total_ns: NanoSecond,
+
+    /// Color by the change compared to a baseline.
+    is_diff: bool,
+
+    /// Path to the node currently being painted.
+    path: Vec<CallsiteId>,
+
+    /// Bottom of the lowest bar painted so far.
+    max_y: f32,
+
+    clicked_path: Option<Vec<CallsiteId>>,
+}
+
+fn sort_by_name(span_tree: &SpanTree, children: &mut [(&CallsiteId, &MergedNode)]) {
+    children.sort_by_key(|(callsite_id, _)| span_tree.callsite_name(callsite_id));
+}
+
+fn paint_node(
+    info: &mut PaintInfo<'_>,
+    callsite_id: CallsiteId,
+    node: &MergedNode,
+    baseline: Option<&MergedNode>,
+    x_range: RangeInclusive<f32>,
+    top_y: f32,
+) {
+    let canvas = info.painter.clip_rect();
+    let (min_x, max_x) = (*x_range.start(), *x_range.end());
+    if max_x < canvas.left() || canvas.right() < min_x || max_x - min_x < 0.5 {
+        return;
+    }
+
+    let rect = Rect::from_x_y_ranges(x_range.clone(), top_y..=(top_y + info.style.rect_height));
+    info.max_y = info.max_y.max(rect.bottom());
+    info.path.push(callsite_id);
+
+    let is_hovered = info
+        .response
+        .hover_pos()
+        .map_or(false, |pos| rect.contains(pos));
+    if is_hovered && info.response.clicked() {
+        info.clicked_path = Some(info.path.clone());
+    }
+
+    let baseline_ns = baseline.map_or(0, |baseline| baseline.total_ns);
+    let color = if is_hovered {
+        Rgba::from_rgb(0.8, 0.8, 0.8)
+    } else if info.is_diff {
+        diff_color(baseline_ns, node.total_ns)
+    } else {
+        color_from_callsite_id(&callsite_id)
+    };
+    info.painter
+        .rect_filled(rect.shrink2(vec2(0.5, 0.0)), info.style.rounding, color);
+
+    let name = info.span_tree.callsite_name(&callsite_id);
+    if rect.width() > 32.0 {
+        let painter = info.painter.sub_region(rect.intersect(canvas));
+        painter.text(
+            pos2(rect.left() + 4.0, rect.center().y),
+            Align2::LEFT_CENTER,
+            &name,
+            info.font_id.clone(),
+            Color32::BLACK,
+        );
+    }
+
+    if is_hovered {
+        let grand_total_ns = info.grand_total_ns;
+        egui::popup::show_tooltip_at_pointer(&info.ctx, Id::new("merged-tooltip"), |ui| {
+            ui.strong(&name);
+            ui.label(format!(
+                "{} total ({:.1}%), {} self",
+                format_duration_ns(node.total_ns),
+                100.0 * node.total_ns as f64 / grand_total_ns as f64,
+                format_duration_ns(node.self_ns)
+            ));
+            ui.label(format!("{} spans", node.count));
+            if info.is_diff {
+                ui.separator();
+                let baseline_count = baseline.map_or(0, |baseline| baseline.count);
+                ui.label(format!(
+                    "Before: {} total, {} spans",
+                    format_duration_ns(baseline_ns),
+                    baseline_count
+                ));
+                ui.label(format!(
+                    "Change: {}",
+                    format_change(baseline_ns, node.total_ns)
+                ));
+            }
+        });
+    }
+
+    // Children are placed left to right under the parent.
+    // Spawned children can be active when the parent isn't, so clamp them to the parent.
+    let ns_per_point = info.grand_total_ns as f32 / canvas.width();
+    let mut children: Vec<(&CallsiteId, &MergedNode)> = node.children.iter().collect();
+    sort_by_name(info.span_tree, &mut children);
+    let mut left = min_x;
+    let child_y = top_y + info.style.rect_height + info.style.spacing;
+    for (child_callsite_id, child) in children {
+        let right = (left + child.total_ns as f32 / ns_per_point).min(max_x);
+        let baseline_child = baseline.and_then(|baseline| baseline.children.get(child_callsite_id));
+        paint_node(
+            info,
+            *child_callsite_id,
+            child,
+            baseline_child,
+            left..=right,
+            child_y,
+        );
+        left = right;
+    }
+
+    info.path.pop();
+}
+
+/// Red if it got slower, green if it got faster, gray if about the same.
+pub fn diff_color(before_ns: NanoSecond, after_ns: NanoSecond) -> Rgba {
+    let same = Rgba::from_rgb(0.6, 0.6, 0.6);
+    let slower = Rgba::from_rgb(0.9, 0.2, 0.2);
+    let faster = Rgba::from_rgb(0.2, 0.8, 0.3);
+
+    if before_ns <= 0 {
+        return if after_ns > 0 { slower } else { same }; // New, or nothing at all
+    }
+    let change = (after_ns as f32 / before_ns as f32 - 1.0).clamp(-1.0, 1.0);
+    let target = if change > 0.0 { slower } else { faster };
+    let t = change.abs().sqrt(); // Make small changes visible
+    Rgba::from_rgb(
+        lerp(same.r()..=target.r(), t),
+        lerp(same.g()..=target.g(), t),
+        lerp(same.b()..=target.b(), t),
+    )
+}
+
+/// E.g. `"+1.200 ms (+12.0%)"`.
+pub fn format_change(before_ns: NanoSecond, after_ns: NanoSecond) -> String {
+    let delta_ns = after_ns - before_ns;
+    let sign = if delta_ns >= 0 { "+" } else { "" };
+    if before_ns > 0 {
+        format!(
+            "{}{} ({}{:.1}%)",
+            sign,
+            format_duration_ns(delta_ns),
+            sign,
+            100.0 * delta_ns as f64 / before_ns as f64
+        )
+    } else if after_ns > 0 {
+        format!("{}{} (new)", sign, format_duration_ns(delta_ns))
+    } else {
+        "-".to_owned()
+    }
+}
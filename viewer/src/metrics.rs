@@ -0,0 +1,125 @@
+use eframe::egui;
+use egui::plot::{Line, Plot, Value, Values};
+use rr_data::{CallsiteId, MetricKind, MetricValue, Time};
+use std::collections::HashMap;
+
+use crate::span_tree::SpanTree;
+
+/// All the values of one metric.
+#[derive(Default)]
+struct MetricSeries {
+    /// For counters this is the running total.
+    points: Vec<(Time, f64)>,
+}
+
+// ----------------------------------------------------------------------------
+
+/// Collect and chart all the metrics of a topic.
+#[derive(Default)]
+pub struct Metrics {
+    series: HashMap<CallsiteId, MetricSeries>,
+
+    /// When the first metric was logged. The origin of the time axis.
+    start_time: Option<Time>,
+}
+
+impl Metrics {
+    pub fn on_message(&mut self, msg: &rr_data::Message) {
+        if let rr_data::MessageEnum::Metric(metric) = &msg.msg_enum {
+            self.start_time.get_or_insert(msg.log_time);
+
+            let series = self.series.entry(metric.callsite_id).or_default();
+            let value = match metric.value {
+                MetricValue::CounterIncrement(increment) => {
+                    let total = series.points.last().map_or(0.0, |(_, total)| *total);
+                    total + increment as f64
+                }
+                MetricValue::GaugeSet(value) | MetricValue::HistogramSample(value) => value,
+            };
+            series.points.push((msg.log_time, value));
+        }
+    }
+
+    pub fn ui(&self, ui: &mut egui::Ui, span_tree: &SpanTree) {
+        if self.series.is_empty() {
+            ui.label("No metrics received.");
+            ui.label("Log them with event fields like `counter.requests = 1`, `gauge.queue_length = 3` or `histogram.latency_ms = 4.2`.");
+            return;
+        }
+
+        let mut metrics: Vec<(&rr_data::Callsite, MetricKind, &MetricSeries)> = self
+            .series
+            .iter()
+            .filter_map(|(callsite_id, series)| {
+                let callsite = span_tree.callsites.get(callsite_id)?;
+                if let rr_data::CallsiteKind::Metric(kind) = callsite.kind {
+                    Some((callsite, kind, series))
+                } else {
+                    None
+                }
+            })
+            .collect();
+        metrics.sort_by(|a, b| a.0.name.cmp(&b.0.name));
+
+        egui::ScrollArea::vertical()
+            .auto_shrink([false; 2])
+            .show(ui, |ui| {
+                for (callsite, kind, series) in metrics {
+                    egui::CollapsingHeader::new(format!("{} ({})", callsite.name, kind))
+                        .id_source(callsite.id)
+                        .default_open(true)
+                        .show(ui, |ui| {
+                            self.metric_ui(ui, callsite, kind, series);
+                        })
+                        .header_response
+                        .on_hover_ui(|ui| crate::misc::ui_callsite(ui, callsite));
+                }
+            });
+    }
+
+    fn metric_ui(
+        &self,
+        ui: &mut egui::Ui,
+        callsite: &rr_data::Callsite,
+        kind: MetricKind,
+        series: &MetricSeries,
+    ) {
+        let values = series.points.iter().map(|(_, value)| *value);
+        let latest = series.points.last().map_or(0.0, |(_, value)| *value);
+        let min = values.clone().fold(f64::INFINITY, f64::min);
+        let max = values.clone().fold(f64::NEG_INFINITY, f64::max);
+        let count = series.points.len();
+
+        match kind {
+            MetricKind::Counter => {
+                ui.label(format!("Total: {}", latest));
+                self.time_plot_ui(ui, callsite, series);
+            }
+            MetricKind::Gauge => {
+                ui.label(format!("Current: {}   min: {}   max: {}", latest, min, max));
+                self.time_plot_ui(ui, callsite, series);
+            }
+            MetricKind::Histogram => {
+                let samples: Vec<f64> = values.collect();
+                let mean = samples.iter().sum::<f64>() / count as f64;
+                ui.label(format!(
+                    "{} samples   mean: {:.3}   min: {}   max: {}",
+                    count, mean, min, max
+                ));
+                crate::misc::histogram_plot(ui, callsite.id, "samples", &samples);
+            }
+        }
+    }
+
+    fn time_plot_ui(&self, ui: &mut egui::Ui, callsite: &rr_data::Callsite, series: &MetricSeries) {
+        let start_ns = self.start_time.map_or(0, |time| time.nanos_since_epoch());
+        let values = series.points.iter().map(|(time, value)| {
+            let seconds = (time.nanos_since_epoch() - start_ns) as f64 * 1e-9;
+            Value::new(seconds, *value)
+        });
+        let line = Line::new(Values::from_values_iter(values)).name("value over time (s)");
+        Plot::new(callsite.id)
+            .height(150.0)
+            .show(ui, |plot_ui| plot_ui.line(line));
+    }
+}
@@ -42,3 +42,108 @@ pub fn ui_callsite(ui: &mut egui::Ui, callsite: &rr_data::Callsite) {
             ui.end_row();
         });
 }
+
+/// Show one row of a virtualized list (see [`egui::ScrollArea::show_rows`]).
+///
+/// The row is always exactly `row_height` tall: contents that don't fit
+/// (e.g. multi-line `Debug` values) are clipped rather than pushing the following rows down.
+pub fn fixed_height_row<R>(
+    ui: &mut egui::Ui,
+    row_nr: usize,
+    row_height: f32,
+    add_contents: impl FnOnce(&mut egui::Ui) -> R,
+) -> R {
+    let (rect, _) = ui.allocate_exact_size(
+        egui::vec2(ui.available_width(), row_height),
+        egui::Sense::hover(),
+    );
+    let layout = egui::Layout::left_to_right().with_cross_align(egui::Align::Center);
+    let mut row_ui = ui.child_ui_with_id_source(rect, layout, row_nr);
+    row_ui.set_clip_rect(rect.intersect(ui.clip_rect()));
+    row_ui.style_mut().wrap = Some(false);
+    add_contents(&mut row_ui)
+}
+
+/// Pick one of the callsites of kind [`rr_data::CallsiteKind::Event`].
+///
+/// Returns `true` if the selection changed.
+pub fn event_callsite_selector(
+    ui: &mut egui::Ui,
+    id_source: impl std::hash::Hash,
+    callsites: &std::collections::HashMap<rr_data::CallsiteId, rr_data::Callsite>,
+    selected: &mut Option<rr_data::CallsiteId>,
+) -> bool {
+    let callsite_label =
+        |callsite: &rr_data::Callsite| format!("{} ({})", callsite.name, callsite.location);
+
+    let selected_text = selected
+        .and_then(|id| callsites.get(&id))
+        .map_or_else(|| "(none)".to_owned(), callsite_label);
+
+    let mut event_callsites: Vec<&rr_data::Callsite> = callsites
+        .values()
+        .filter(|callsite| callsite.kind == rr_data::CallsiteKind::Event)
+        .collect();
+    event_callsites.sort_by_key(|callsite| callsite_label(callsite));
+
+    let before = *selected;
+    egui::ComboBox::new(id_source, "Callsite")
+        .selected_text(selected_text)
+        .show_ui(ui, |ui| {
+            for callsite in event_callsites {
+                ui.selectable_value(selected, Some(callsite.id), callsite_label(callsite));
+            }
+        });
+    *selected != before
+}
+
+/// Human-readable duration, e.g. `"12.345 ms"`.
+pub fn format_duration_ns(ns: i64) -> String {
+    let abs_ns = ns.abs();
+    if abs_ns < 1_000 {
+        format!("{} ns", ns)
+    } else if abs_ns < 1_000_000 {
+        format!("{:.3} µs", ns as f64 * 1e-3)
+    } else if abs_ns < 1_000_000_000 {
+        format!("{:.3} ms", ns as f64 * 1e-6)
+    } else {
+        format!("{:.3} s", ns as f64 * 1e-9)
+    }
+}
+
+/// Bar chart of how the values are distributed.
+pub fn histogram_plot(
+    ui: &mut egui::Ui,
+    id_source: impl std::hash::Hash,
+    name: &str,
+    values: &[f64],
+) {
+    const NUM_BINS: usize = 20;
+
+    let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let bin_width = if min < max {
+        (max - min) / NUM_BINS as f64
+    } else {
+        1.0
+    };
+
+    let mut counts = [0_usize; NUM_BINS];
+    for value in values {
+        let bin = ((value - min) / bin_width) as usize;
+        counts[bin.min(NUM_BINS - 1)] += 1;
+    }
+
+    let bars = counts
+        .iter()
+        .enumerate()
+        .map(|(bin, &count)| {
+            let center = min + (bin as f64 + 0.5) * bin_width;
+            egui::plot::Bar::new(center, count as f64).width(bin_width)
+        })
+        .collect();
+    let chart = egui::plot::BarChart::new(bars).name(name);
+    egui::plot::Plot::new(id_source)
+        .height(150.0)
+        .show(ui, |plot_ui| plot_ui.bar_chart(chart));
+}
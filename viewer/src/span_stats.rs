@@ -0,0 +1,331 @@
+use eframe::egui;
+use rr_data::CallsiteId;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use crate::flamegraph::NanoSecond;
+use crate::misc::format_duration_ns;
+use crate::span_tree::SpanTree;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Column {
+    Name,
+    Count,
+    Total,
+    SelfTime,
+    Min,
+    Mean,
+    Median,
+    P95,
+    Max,
+}
+
+const COLUMNS: [Column; 9] = [
+    Column::Name,
+    Column::Count,
+    Column::Total,
+    Column::SelfTime,
+    Column::Min,
+    Column::Mean,
+    Column::Median,
+    Column::P95,
+    Column::Max,
+];
+
+impl Column {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Name => "Name",
+            Self::Count => "Count",
+            Self::Total => "Total",
+            Self::SelfTime => "Self",
+            Self::Min => "Min",
+            Self::Mean => "Mean",
+            Self::Median => "Median",
+            Self::P95 => "P95",
+            Self::Max => "Max",
+        }
+    }
+
+    fn hover_text(&self) -> &'static str {
+        match self {
+            Self::Name => "Name of the span callsite",
+            Self::Count => "Number of spans that have been active",
+            Self::Total => "Sum of the time the spans were active",
+            Self::SelfTime => "Total time minus the time spent in direct child spans",
+            Self::Min => "Shortest active time of one span",
+            Self::Mean => "Mean active time of one span",
+            Self::Median => "Median active time of one span",
+            Self::P95 => "95th percentile of the active time of one span",
+            Self::Max => "Longest active time of one span",
+        }
+    }
+}
+
+/// While spans keep arriving, recompute the stats at most this often.
+const LIVE_REFRESH_INTERVAL_S: f64 = 0.5;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct SortBy {
+    column: Column,
+    ascending: bool,
+}
+
+// ----------------------------------------------------------------------------
+
+/// Durations of all the spans from one callsite.
+struct CallsiteStats {
+    callsite_id: CallsiteId,
+    name: String,
+    /// Active time of each span, sorted.
+    durations: Vec<NanoSecond>,
+    total: NanoSecond,
+    self_time: NanoSecond,
+}
+
+impl CallsiteStats {
+    fn count(&self) -> usize {
+        self.durations.len()
+    }
+
+    fn mean(&self) -> NanoSecond {
+        self.total / self.count().max(1) as NanoSecond
+    }
+
+    /// Nearest-rank percentile, with `fraction` in `[0, 1]`.
+    fn percentile(&self, fraction: f64) -> NanoSecond {
+        let rank = (fraction * self.count() as f64).ceil() as usize;
+        self.durations[rank.max(1).min(self.count()) - 1]
+    }
+
+    fn value(&self, column: Column) -> NanoSecond {
+        match column {
+            Column::Name => 0,
+            Column::Count => self.count() as NanoSecond,
+            Column::Total => self.total,
+            Column::SelfTime => self.self_time,
+            Column::Min => self.durations[0],
+            Column::Mean => self.mean(),
+            Column::Median => self.percentile(0.5),
+            Column::P95 => self.percentile(0.95),
+            Column::Max => self.durations[self.count() - 1],
+        }
+    }
+
+    fn compare(&self, other: &Self, column: Column) -> Ordering {
+        match column {
+            Column::Name => self.name.cmp(&other.name),
+            _ => self.value(column).cmp(&other.value(column)),
+        }
+    }
+}
+
+fn compute_stats(
+    span_tree: &SpanTree,
+    time_range: Option<(NanoSecond, NanoSecond)>,
+) -> Vec<CallsiteStats> {
+    let mut stats: HashMap<CallsiteId, CallsiteStats> = Default::default();
+
+    for (span_id, times) in span_tree.all_span_times(time_range) {
+        let callsite_id = match span_tree.nodes.get(&span_id) {
+            Some(node) => node.span.callsite_id,
+            None => continue,
+        };
+        let entry = stats.entry(callsite_id).or_insert_with(|| CallsiteStats {
+            callsite_id,
+            name: span_tree.callsite_name(&callsite_id),
+            durations: vec![],
+            total: 0,
+            self_time: 0,
+        });
+        entry.durations.push(times.total_ns);
+        entry.total += times.total_ns;
+        entry.self_time += times.self_ns;
+    }
+
+    let mut stats: Vec<CallsiteStats> = stats.into_values().collect();
+    for callsite_stats in &mut stats {
+        callsite_stats.durations.sort_unstable();
+    }
+    stats
+}
+
+// ----------------------------------------------------------------------------
+
+/// Duration statistics of the spans, grouped by callsite.
+pub struct SpanStats {
+    sort_by: SortBy,
+
+    /// Show the duration histogram of this callsite.
+    selected: Option<CallsiteId>,
+
+    /// Recomputed when the [`SpanTree`] or the time range changes.
+    stats: Vec<CallsiteStats>,
+    stats_key: Option<(u64, Option<(NanoSecond, NanoSecond)>)>,
+    /// When `stats` were computed, in [`egui::InputState::time`].
+    stats_time: f64,
+    /// How `stats` is currently sorted.
+    sorted_by: Option<SortBy>,
+}
+
+impl Default for SpanStats {
+    fn default() -> Self {
+        Self {
+            sort_by: SortBy {
+                column: Column::Total,
+                ascending: false,
+            },
+            selected: None,
+            stats: vec![],
+            stats_key: None,
+            stats_time: 0.0,
+            sorted_by: None,
+        }
+    }
+}
+
+impl SpanStats {
+    /// Only the time within `time_range` (nanoseconds since epoch) is counted, if given.
+    ///
+    /// Returns a callsite if the user wants to see its spans in the flame graph.
+    pub fn ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        span_tree: &SpanTree,
+        time_range: Option<(NanoSecond, NanoSecond)>,
+    ) -> Option<CallsiteId> {
+        let key = Some((span_tree.generation(), time_range));
+        if self.stats_key != key {
+            let time = ui.input().time;
+            let same_time_range = self.stats_key.map(|(_, range)| range) == Some(time_range);
+            if same_time_range && time - self.stats_time < LIVE_REFRESH_INTERVAL_S {
+                ui.ctx().request_repaint(); // so that we get to the latest spans
+            } else {
+                self.stats = compute_stats(span_tree, time_range);
+                self.stats_key = key;
+                self.stats_time = time;
+                self.sorted_by = None;
+            }
+        }
+
+        if self.stats.is_empty() {
+            ui.label("No finished spans in this time range.");
+            return None;
+        }
+
+        if self.sorted_by != Some(self.sort_by) {
+            let SortBy { column, ascending } = self.sort_by;
+            self.stats.sort_by(|a, b| {
+                let ordering = a.compare(b, column);
+                if ascending {
+                    ordering
+                } else {
+                    ordering.reverse()
+                }
+            });
+            self.sorted_by = Some(self.sort_by);
+        }
+
+        let mut highlight = None;
+
+        egui::ScrollArea::vertical()
+            .auto_shrink([false; 2])
+            .show(ui, |ui| {
+                egui::Grid::new("span_stats")
+                    .striped(true)
+                    .num_columns(COLUMNS.len() + 1)
+                    .show(ui, |ui| {
+                        self.header_ui(ui);
+                        ui.end_row();
+
+                        for stats in &self.stats {
+                            highlight = highlight.or(row_ui(ui, &mut self.selected, stats));
+                            ui.end_row();
+                        }
+                    });
+
+                if let Some(stats) = self
+                    .selected
+                    .and_then(|id| self.stats.iter().find(|stats| stats.callsite_id == id))
+                {
+                    ui.separator();
+                    ui.strong(format!("Durations of {} (ms)", stats.name));
+                    let durations_ms: Vec<f64> =
+                        stats.durations.iter().map(|&ns| ns as f64 * 1e-6).collect();
+                    crate::misc::histogram_plot(
+                        ui,
+                        ("span_durations", stats.callsite_id),
+                        "spans",
+                        &durations_ms,
+                    );
+                }
+            });
+
+        highlight
+    }
+
+    fn header_ui(&mut self, ui: &mut egui::Ui) {
+        for column in COLUMNS {
+            let is_sorted = self.sort_by.column == column;
+            let text = match (is_sorted, self.sort_by.ascending) {
+                (true, true) => format!("{} ⏶", column.name()),
+                (true, false) => format!("{} ⏷", column.name()),
+                (false, _) => column.name().to_owned(),
+            };
+            if ui
+                .selectable_label(is_sorted, egui::RichText::new(text).strong())
+                .on_hover_text(column.hover_text())
+                .clicked()
+            {
+                self.sort_by = if is_sorted {
+                    SortBy {
+                        column,
+                        ascending: !self.sort_by.ascending,
+                    }
+                } else {
+                    SortBy {
+                        column,
+                        // Names read best A-Z, durations biggest first:
+                        ascending: column == Column::Name,
+                    }
+                };
+            }
+        }
+        ui.label("");
+    }
+}
+
+/// Returns the callsite if the user wants to highlight it.
+fn row_ui(
+    ui: &mut egui::Ui,
+    selected: &mut Option<CallsiteId>,
+    stats: &CallsiteStats,
+) -> Option<CallsiteId> {
+    let is_selected = *selected == Some(stats.callsite_id);
+    if ui
+        .selectable_label(is_selected, &stats.name)
+        .on_hover_text("Click to show a histogram of the durations")
+        .clicked()
+    {
+        *selected = if is_selected {
+            None
+        } else {
+            Some(stats.callsite_id)
+        };
+    }
+
+    ui.label(stats.count().to_string());
+    for &column in &COLUMNS[2..] {
+        ui.label(egui::RichText::new(format_duration_ns(stats.value(column))).monospace());
+    }
+
+    if ui
+        .small_button("🔥")
+        .on_hover_text("Highlight these spans in the flame graph")
+        .clicked()
+    {
+        Some(stats.callsite_id)
+    } else {
+        None
+    }
+}
@@ -1,16 +1,21 @@
 use eframe::egui::{self, Color32};
 use rr_data::{SpanId, Time};
 use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
 
 const ERROR_COLOR: egui::Color32 = Color32::RED;
 
 /// Running index about spans and events, and their structure.
 #[derive(Default)]
 pub struct SpanTree {
-    callsites: HashMap<rr_data::CallsiteId, rr_data::Callsite>,
+    pub callsites: HashMap<rr_data::CallsiteId, rr_data::Callsite>,
     pub nodes: HashMap<SpanId, SpanNode>,
     pub roots: HashSet<SpanId>,
     orphan_events: Vec<(Time, rr_data::DataEvent)>,
+    /// Changes on each new message, so that derived data can be cached.
+    ///
+    /// Unique across all span trees, so a cache can't mistake a rebuilt tree for the old one.
+    generation: u64,
 }
 
 /// A span is created, and then is opened over many non-overlapping intervals.
@@ -32,6 +37,32 @@ impl SpanNode {
             .any(|interval| interval.is_active_at(time))
     }
 
+    /// The active intervals that have both a start and an end, in nanoseconds since epoch.
+    pub fn closed_intervals_ns(&self) -> Vec<(i64, i64)> {
+        self.intervals
+            .iter()
+            .filter_map(|interval| {
+                Some((
+                    interval.min?.nanos_since_epoch(),
+                    interval.max?.nanos_since_epoch(),
+                ))
+            })
+            .collect()
+    }
+
+    /// Does the lifetime of the span overlap the given range (nanoseconds since epoch)?
+    pub fn overlaps(&self, (min_ns, max_ns): (i64, i64)) -> bool {
+        let starts_after = self
+            .lifetime
+            .min
+            .map_or(false, |min| max_ns < min.nanos_since_epoch());
+        let ends_before = self
+            .lifetime
+            .max
+            .map_or(false, |max| max.nanos_since_epoch() < min_ns);
+        !starts_after && !ends_before
+    }
+
     /// True if the parent is active whenever the child is.
     pub fn is_direct_child_of(&self, parent: &SpanNode) -> bool {
         for interval in &self.intervals {
@@ -90,8 +121,19 @@ impl std::fmt::Display for TimeInterval {
     }
 }
 
+/// How long a span has been active, summed over its finished intervals.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SpanTimes {
+    /// All the active time of the span.
+    pub total_ns: i64,
+    /// Active time not covered by the active intervals of direct children.
+    pub self_ns: i64,
+}
+
 impl SpanTree {
     pub fn on_mesage(&mut self, message: &rr_data::Message, warnings: bool) {
+        static NEXT_GENERATION: AtomicU64 = AtomicU64::new(1);
+        self.generation = NEXT_GENERATION.fetch_add(1, Ordering::Relaxed);
         let rr_data::Message { log_time, msg_enum } = message;
         match &msg_enum {
             rr_data::MessageEnum::NewCallsite(callsite) => {
@@ -189,6 +231,22 @@ impl SpanTree {
                     self.orphan_events.push((*log_time, event.clone()));
                 }
             }
+            rr_data::MessageEnum::Metric(_) => {
+                // Collected by `Metrics`
+            }
+        }
+    }
+
+    /// Changes whenever the tree changes.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn callsite_name(&self, callsite_id: &rr_data::CallsiteId) -> String {
+        if let Some(callsite) = self.callsites.get(callsite_id) {
+            callsite.name.to_string()
+        } else {
+            callsite_id.to_string()
         }
     }
 
@@ -310,23 +368,99 @@ impl SpanTree {
 
         direct_children
     }
+
+    /// Total and self time of one span, or `None` if it has never finished an active interval.
+    pub fn span_times(&self, node: &SpanNode) -> Option<SpanTimes> {
+        self.span_times_in_range(node, None)
+    }
+
+    /// Like [`Self::span_times`], but only counting the time within the given range
+    /// (nanoseconds since epoch).
+    pub fn span_times_in_range(
+        &self,
+        node: &SpanNode,
+        range: Option<(i64, i64)>,
+    ) -> Option<SpanTimes> {
+        let mut intervals = node.closed_intervals_ns();
+        if let Some((range_min, range_max)) = range {
+            intervals = intervals
+                .into_iter()
+                .map(|(min, max)| (min.max(range_min), max.min(range_max)))
+                .filter(|(min, max)| min < max)
+                .collect();
+        }
+        if intervals.is_empty() {
+            return None;
+        }
+        let total_ns = intervals.iter().map(|(min, max)| max - min).sum();
+
+        let mut children_intervals = vec![];
+        for child_id in self.direct_children_of(node) {
+            if let Some(child) = self.nodes.get(&child_id) {
+                children_intervals.extend(child.closed_intervals_ns());
+            }
+        }
+        let self_ns = total_ns - covered_ns(&intervals, children_intervals);
+
+        Some(SpanTimes { total_ns, self_ns })
+    }
+
+    /// Analysis pass computing the total and self time of all spans
+    /// that have finished at least one active interval (within `range`, if given).
+    pub fn all_span_times(&self, range: Option<(i64, i64)>) -> HashMap<SpanId, SpanTimes> {
+        self.nodes
+            .iter()
+            .filter_map(|(span_id, node)| Some((*span_id, self.span_times_in_range(node, range)?)))
+            .collect()
+    }
+}
+
+/// How much of `intervals` is covered by the (possibly overlapping) `covering` intervals.
+fn covered_ns(intervals: &[(i64, i64)], mut covering: Vec<(i64, i64)>) -> i64 {
+    covering.sort_unstable();
+    let mut merged: Vec<(i64, i64)> = vec![];
+    for (min, max) in covering {
+        match merged.last_mut() {
+            Some(last) if min <= last.1 => last.1 = last.1.max(max),
+            _ => merged.push((min, max)),
+        }
+    }
+
+    let mut covered = 0;
+    for &(min, max) in intervals {
+        for &(cover_min, cover_max) in &merged {
+            covered += (max.min(cover_max) - min.max(cover_min)).max(0);
+        }
+    }
+    covered
 }
 
 /// ## UI memebers:
 impl SpanTree {
-    pub fn tree_ui(&self, ui: &mut egui::Ui) {
+    /// Only the spans and events within `time_range` (nanoseconds since epoch) are shown, if given.
+    pub fn tree_ui(&self, ui: &mut egui::Ui, time_range: Option<(i64, i64)>) {
         egui::ScrollArea::vertical()
             .auto_shrink([false; 2])
             .show(ui, |ui| {
                 for span_id in &self.roots {
-                    self.tree_node_ui(ui, 0, span_id);
+                    self.tree_node_ui(ui, 0, span_id, time_range);
                 }
 
-                if !self.orphan_events.is_empty() {
+                let orphan_events: Vec<&(Time, rr_data::DataEvent)> = self
+                    .orphan_events
+                    .iter()
+                    .filter(|(time, _)| {
+                        time_range.map_or(true, |(min_ns, max_ns)| {
+                            let ns = time.nanos_since_epoch();
+                            min_ns <= ns && ns <= max_ns
+                        })
+                    })
+                    .collect();
+                if !orphan_events.is_empty() {
                     ui.separator();
                     ui.label("Events outside of any span:");
                     ui.indent("events", |ui| {
-                        for (time, event) in &self.orphan_events {
+                        for (time, event) in orphan_events {
                             self.ui_timed_data_event(ui, time, event);
                         }
                     });
@@ -334,24 +468,41 @@ impl SpanTree {
             });
     }
 
-    fn tree_node_ui(&self, ui: &mut egui::Ui, depth: usize, span_id: &SpanId) {
+    fn tree_node_ui(
+        &self,
+        ui: &mut egui::Ui,
+        depth: usize,
+        span_id: &SpanId,
+        time_range: Option<(i64, i64)>,
+    ) {
         if let Some(node) = self.nodes.get(span_id) {
+            if let Some(time_range) = time_range {
+                if !node.overlaps(time_range) {
+                    return;
+                }
+            }
             let name = self.span_name(span_id);
             egui::CollapsingHeader::new(name)
                 .id_source(span_id)
                 .default_open(depth < 4)
                 .show(ui, |ui| {
-                    self.tree_node_ui_impl(ui, depth, node);
+                    self.tree_node_ui_impl(ui, depth, node, time_range);
                 });
         } else {
             ui.colored_label(ERROR_COLOR, "Missing span");
         }
     }
 
-    fn tree_node_ui_impl(&self, ui: &mut egui::Ui, depth: usize, node: &SpanNode) {
+    fn tree_node_ui_impl(
+        &self,
+        ui: &mut egui::Ui,
+        depth: usize,
+        node: &SpanNode,
+        time_range: Option<(i64, i64)>,
+    ) {
         self.span_summary_ui(ui, node);
         for child in &node.children {
-            self.tree_node_ui(ui, depth + 1, child);
+            self.tree_node_ui(ui, depth + 1, child, time_range);
         }
     }
 
@@ -494,6 +645,17 @@ impl SpanTree {
                 ui.label(intervals.iter().join(", "));
                 ui.end_row();
 
+                if let Some(times) = self.span_times(node) {
+                    ui.label("Total time:");
+                    ui.label(crate::misc::format_duration_ns(times.total_ns));
+                    ui.end_row();
+
+                    ui.label("Self time:");
+                    ui.label(crate::misc::format_duration_ns(times.self_ns))
+                        .on_hover_text("Active time not spent in direct child spans");
+                    ui.end_row();
+                }
+
                 ui.label("Events:");
                 if events.is_empty() {
                     ui.weak("(none)");
@@ -519,7 +681,7 @@ fn ui_fields(ui: &mut egui::Ui, fields: &rr_data::FieldSet) -> egui::Response {
     .response
 }
 
-fn log_level_ui(ui: &mut egui::Ui, level: rr_data::LogLevel) {
+pub fn log_level_ui(ui: &mut egui::Ui, level: rr_data::LogLevel) {
     match level {
         rr_data::LogLevel::Trace => {
             ui.weak("trace");
@@ -539,3 +701,93 @@ fn log_level_ui(ui: &mut egui::Ui, level: rr_data::LogLevel) {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rr_data::{CallsiteId, MessageEnum};
+
+    #[test]
+    fn covered_by_nested_children() {
+        assert_eq!(covered_ns(&[(0, 100)], vec![(10, 20), (30, 50)]), 30);
+    }
+
+    #[test]
+    fn covered_by_overlapping_siblings() {
+        assert_eq!(covered_ns(&[(0, 100)], vec![(30, 60), (10, 40)]), 50);
+        assert_eq!(covered_ns(&[(0, 100)], vec![(10, 40), (20, 30)]), 30);
+    }
+
+    #[test]
+    fn covered_outside_intervals() {
+        assert_eq!(covered_ns(&[(0, 100)], vec![(-50, -10), (150, 200)]), 0);
+        assert_eq!(covered_ns(&[(0, 100)], vec![(90, 120)]), 10);
+    }
+
+    #[test]
+    fn covered_across_reopened_intervals() {
+        assert_eq!(covered_ns(&[(0, 10), (20, 30)], vec![(5, 25)]), 10);
+    }
+
+    /// A root span 1 active in 0..100 (and 200..210 if `reopen`), with children:
+    /// 2 in 10..30 and 3 in 20..50 (overlapping), and 4 in 150..160 (outside the parent).
+    fn tree(reopen: bool) -> SpanTree {
+        let mut tree = SpanTree::default();
+        let mut on_message = |ns: i64, msg_enum: MessageEnum| {
+            let log_time = Time::from_nanos_since_epoch(ns);
+            tree.on_mesage(&rr_data::Message { log_time, msg_enum }, false);
+        };
+        let new_span = |id: u64, parent: Option<u64>| {
+            MessageEnum::NewSpan(rr_data::Span {
+                id: SpanId(id),
+                parent_span_id: parent.map(SpanId),
+                callsite_id: CallsiteId(0),
+                fields: Default::default(),
+            })
+        };
+
+        on_message(0, new_span(1, None));
+        for (id, min, max) in [(2, 10, 30), (3, 20, 50), (4, 150, 160)] {
+            on_message(0, new_span(id, Some(1)));
+            on_message(min, MessageEnum::EnterSpan(SpanId(id)));
+            on_message(max, MessageEnum::ExitSpan(SpanId(id)));
+        }
+        on_message(0, MessageEnum::EnterSpan(SpanId(1)));
+        on_message(100, MessageEnum::ExitSpan(SpanId(1)));
+        if reopen {
+            on_message(200, MessageEnum::EnterSpan(SpanId(1)));
+            on_message(210, MessageEnum::ExitSpan(SpanId(1)));
+        }
+        tree
+    }
+
+    fn times(tree: &SpanTree, id: u64, range: Option<(i64, i64)>) -> Option<(i64, i64)> {
+        let times = tree.span_times_in_range(&tree.nodes[&SpanId(id)], range)?;
+        Some((times.total_ns, times.self_ns))
+    }
+
+    #[test]
+    fn self_time_excludes_direct_children() {
+        let tree = tree(false);
+        // 2 and 3 cover 10..50; 4 is outside the parent, so not a direct child:
+        assert_eq!(times(&tree, 1, None), Some((100, 60)));
+        assert_eq!(times(&tree, 2, None), Some((20, 20)));
+        assert_eq!(times(&tree, 4, None), Some((10, 10)));
+    }
+
+    #[test]
+    fn self_time_of_reopened_span() {
+        let tree = tree(true);
+        assert_eq!(times(&tree, 1, None), Some((110, 70)));
+    }
+
+    #[test]
+    fn times_clamped_to_range() {
+        let tree = tree(false);
+        assert_eq!(times(&tree, 1, Some((25, 75))), Some((50, 25)));
+        assert_eq!(times(&tree, 2, Some((25, 75))), Some((5, 5)));
+        assert_eq!(times(&tree, 1, Some((120, 140))), None);
+        assert_eq!(tree.all_span_times(Some((120, 140))).len(), 0);
+        assert_eq!(tree.all_span_times(Some((0, 200))).len(), 4);
+    }
+}
@@ -0,0 +1,103 @@
+use eframe::egui;
+use rr_data::{SearchQuery, SearchResults, TopicId, TopicMeta, TopicStats};
+
+/// Stop searching after this many hits.
+const MAX_HITS: u64 = 200;
+
+/// Search box for finding data events in all topics on the server.
+#[derive(Default)]
+pub struct TopicSearch {
+    text: String,
+    /// We have asked the server, but not gotten a reply.
+    searching: bool,
+    results: Option<SearchResults>,
+}
+
+pub enum SearchAction {
+    /// Send this to the server.
+    Search(SearchQuery),
+    OpenTopic(TopicId),
+}
+
+impl TopicSearch {
+    pub fn on_results(&mut self, results: SearchResults) {
+        if results.query.text == self.text {
+            self.searching = false;
+        }
+        self.results = Some(results);
+    }
+
+    pub fn ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        topics: &[(TopicMeta, TopicStats)],
+    ) -> Option<SearchAction> {
+        let mut action = None;
+
+        ui.horizontal(|ui| {
+            let response = ui.add(
+                egui::TextEdit::singleline(&mut self.text)
+                    .hint_text("Search all topics")
+                    .desired_width(160.0),
+            );
+            let enter = response.lost_focus() && ui.input().key_pressed(egui::Key::Enter);
+            if (ui.button("🔍").clicked() || enter) && !self.text.is_empty() {
+                self.searching = true;
+                action = Some(SearchAction::Search(SearchQuery {
+                    text: self.text.clone(),
+                    topic_id: None,
+                    max_hits: MAX_HITS,
+                }));
+            }
+            if self.results.is_some() && ui.small_button("ｘ").on_hover_text("Clear").clicked() {
+                self.results = None;
+            }
+        });
+
+        if self.searching {
+            ui.weak("Searching…");
+        }
+
+        let results = match &self.results {
+            Some(results) => results,
+            None => return action,
+        };
+        ui.weak(format!(
+            "{}{} hit(s) for {:?}",
+            results.hits.len(),
+            if results.truncated { "+" } else { "" },
+            results.query.text
+        ));
+
+        egui::ScrollArea::vertical()
+            .id_source("search_results")
+            .max_height(200.0)
+            .show(ui, |ui| {
+                let mut last_topic = None;
+                for hit in &results.hits {
+                    if last_topic != Some(hit.topic_id) {
+                        last_topic = Some(hit.topic_id);
+                        let topic_name = topics
+                            .iter()
+                            .find(|(topic_meta, _)| topic_meta.id == hit.topic_id)
+                            .map_or_else(
+                                || hit.topic_id.to_string(),
+                                |(topic_meta, _)| crate::viewer::topic_summary(topic_meta),
+                            );
+                        if ui.button(topic_name).on_hover_text("Open topic").clicked() {
+                            action = Some(SearchAction::OpenTopic(hit.topic_id));
+                        }
+                    }
+                    ui.horizontal(|ui| {
+                        ui.weak(hit.log_time.format());
+                        ui.label(&hit.snippet).on_hover_text(format!(
+                            "{} (message #{})",
+                            hit.callsite_name, hit.index
+                        ));
+                    });
+                }
+            });
+
+        action
+    }
+}
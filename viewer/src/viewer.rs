@@ -1,33 +1,93 @@
+use crate::app::WsSender;
 use eframe::egui;
-use ewebsock::{WsEvent, WsMessage, WsReceiver, WsSender};
-use rr_data::TopicMeta;
+use ewebsock::{WsEvent, WsMessage, WsReceiver};
+use rr_data::{
+    Backlog, LogLevel, MessageQuery, Subscription, TopicId, TopicMeta, TopicRange, TopicStats,
+};
+use std::sync::Arc;
+
+/// How many of the last messages of a topic to get when opening it.
+const INITIAL_BACKLOG: u64 = 100_000;
+
+/// How many more older messages to get when asked to.
+const OLDER_PAGE_SIZE: u64 = 100_000;
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 enum View {
     Events,
     Log,
+    Table,
     SpanTree,
     Flamegraph,
+    Statistics,
+    Metrics,
+    Diff,
+}
+
+/// How the flame graphs of several topics relate to each other.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum TimeAxis {
+    /// Each topic is zoomed and panned on its own.
+    Independent,
+    /// Zoom and pan together, aligned on absolute time.
+    Absolute,
+    /// Zoom and pan together, aligned on the start of each topic.
+    TopicStart,
+}
+
+impl TimeAxis {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Independent => "Independent",
+            Self::Absolute => "Absolute time",
+            Self::TopicStart => "Topic start",
+        }
+    }
 }
 
 pub struct Viewer {
     ws_sender: WsSender,
     ws_receiver: WsReceiver,
-    topics: Vec<TopicMeta>,
+    /// Sent to the server when connected, if any.
+    token: Option<String>,
+    /// Why the server closed the connection, if it did.
+    rejection: Option<String>,
+    /// What the server said we may do, once it has.
+    permissions: Option<rr_data::Permissions>,
+    /// The stats are pushed by the server, if it knows how.
+    topics: Vec<(TopicMeta, TopicStats)>,
     view: View,
-    /// What we are viewing
-    topic_viewer: Option<TopicViewer>,
+    /// What we are viewing, in the order they were opened
+    topic_viewers: Vec<TopicViewer>,
+    /// The topic shown in views that only show one topic.
+    active_topic: Option<TopicId>,
+    /// Show the flame graphs of all open topics above each other.
+    stack_flame_graphs: bool,
+    time_axis: TimeAxis,
+    /// Zoom and pan of the flame graphs, unless the time axis is [`TimeAxis::Independent`].
+    shared_view: Option<(f32, f32)>,
+    diff_view: crate::diff::DiffView,
+    topic_search: crate::topic_search::TopicSearch,
     full_event_log: crate::event_log::EventLog,
 }
 
 impl Viewer {
-    pub fn new(ws_sender: WsSender, ws_receiver: WsReceiver) -> Self {
+    pub fn new(ws_sender: WsSender, ws_receiver: WsReceiver, token: Option<String>) -> Self {
         Self {
             ws_sender,
             ws_receiver,
+            token,
+            rejection: None,
+            permissions: None,
             topics: Default::default(),
             view: View::Flamegraph,
-            topic_viewer: None,
+            topic_viewers: Default::default(),
+            active_topic: None,
+            stack_flame_graphs: false,
+            time_axis: TimeAxis::Independent,
+            shared_view: None,
+            diff_view: Default::default(),
+            topic_search: Default::default(),
             full_event_log: Default::default(),
         }
     }
@@ -36,47 +96,147 @@ impl Viewer {
         while let Some(event) = self.ws_receiver.try_recv() {
             if let WsEvent::Opened = &event {
                 tracing::info!("Web-socket connection opened.");
+                if let Some(token) = &self.token {
+                    self.ws_sender.send(WsMessage::Binary(
+                        rr_data::PubSubMsg::Authenticate(token.clone()).encode(),
+                    ));
+                }
+                self.ws_sender.send(WsMessage::Binary(
+                    rr_data::PubSubMsg::QueryPermissions.encode(),
+                ));
+                // Older servers don't understand `ListTopicsWithStats`,
+                // so we only ask for stats once the server has answered `QueryPermissions`.
                 self.ws_sender
                     .send(WsMessage::Binary(rr_data::PubSubMsg::ListTopics.encode()));
             }
+            if let WsEvent::Message(WsMessage::Text(text)) = &event {
+                // The server only sends text when it rejects us.
+                tracing::warn!("Server: {}", text);
+                self.rejection = Some(text.clone());
+            }
             if let WsEvent::Message(WsMessage::Binary(payload)) = &event {
                 if let Ok(pub_sub_msg) = rr_data::PubSubMsg::decode(payload) {
                     match pub_sub_msg {
                         rr_data::PubSubMsg::NewTopic(topic_meta) => {
-                            if self.topic_viewer.is_none() {
-                                self.subscribe_to(topic_meta);
+                            if !self.topics.iter().any(|(meta, _)| meta.id == topic_meta.id) {
+                                self.topics.push((topic_meta.clone(), Default::default()));
+                            }
+                            if self.topic_viewers.is_empty() {
+                                self.open_topic(topic_meta);
                             }
-
-                            // Refresh list
-                            self.ws_sender
-                                .send(WsMessage::Binary(rr_data::PubSubMsg::ListTopics.encode()));
-
                             continue;
                         }
-                        rr_data::PubSubMsg::TopicMsg(_topic_id, payload) => {
+                        rr_data::PubSubMsg::TopicMsg(topic_id, payload) => {
                             if let Ok(rr_msg) = rr_data::Message::decode(&payload) {
-                                if let Some(topic_viewer) = &mut self.topic_viewer {
-                                    topic_viewer.on_message(&rr_msg);
+                                if let Some(topic_viewer) = self
+                                    .topic_viewers
+                                    .iter_mut()
+                                    .find(|topic_viewer| topic_viewer.topic_meta.id == topic_id)
+                                {
+                                    if topic_viewer.awaiting_range {
+                                        continue; // From before we asked for a new backlog
+                                    }
+                                    topic_viewer.on_topic_msg(payload, &rr_msg);
                                 }
                                 self.full_event_log.on_message(rr_msg);
                                 continue;
                             }
                         }
-                        rr_data::PubSubMsg::SubscribeTo(_) => {
+                        rr_data::PubSubMsg::TopicRange(topic_id, range) => {
+                            if let Some(topic_viewer) = self
+                                .topic_viewers
+                                .iter_mut()
+                                .find(|topic_viewer| topic_viewer.topic_meta.id == topic_id)
+                            {
+                                // The backlog follows, so start over (but keep the view):
+                                topic_viewer.clear_messages();
+                                topic_viewer.range = Some(range);
+                                topic_viewer.awaiting_range = false;
+                            }
+                        }
+                        rr_data::PubSubMsg::Messages(topic_id, query, messages) => {
+                            if let Some(topic_viewer) = self
+                                .topic_viewers
+                                .iter_mut()
+                                .find(|topic_viewer| topic_viewer.topic_meta.id == topic_id)
+                            {
+                                topic_viewer.on_older_messages(&query, messages);
+                            }
+                        }
+                        rr_data::PubSubMsg::SubscribeTo(_)
+                        | rr_data::PubSubMsg::Subscribe(_, _)
+                        | rr_data::PubSubMsg::UnsubscribeFrom(_)
+                        | rr_data::PubSubMsg::QueryTopicIndex(_)
+                        | rr_data::PubSubMsg::Search(_)
+                        | rr_data::PubSubMsg::Authenticate(_)
+                        | rr_data::PubSubMsg::QueryMessages(_, _)
+                        | rr_data::PubSubMsg::QueryPermissions => {
                             // weird
                         }
-                        rr_data::PubSubMsg::ListTopics => {
+                        rr_data::PubSubMsg::ListTopics
+                        | rr_data::PubSubMsg::ListTopicsWithStats => {
                             tracing::debug!("Server sent ListTopics message. Weird");
                         }
                         rr_data::PubSubMsg::AllTopics(all_topics) => {
+                            // Without stats, so keep the ones we have:
+                            tracing::debug!("Received {} topic(s)", all_topics.len());
+                            let all_topics = all_topics
+                                .into_iter()
+                                .map(|topic_meta| {
+                                    let stats = self
+                                        .topics
+                                        .iter()
+                                        .find(|(meta, _)| meta.id == topic_meta.id)
+                                        .map(|(_, stats)| *stats)
+                                        .unwrap_or_default();
+                                    (topic_meta, stats)
+                                })
+                                .collect();
+                            self.on_all_topics(all_topics);
+                        }
+                        rr_data::PubSubMsg::AllTopicsWithStats(all_topics) => {
                             tracing::debug!("Received {} topic(s)", all_topics.len());
-                            self.topics = all_topics;
-                            if self.topic_viewer.is_none() {
-                                if let Some(latest_topic) = self.topics.last().cloned() {
-                                    self.subscribe_to(latest_topic);
+                            self.on_all_topics(all_topics);
+                        }
+                        rr_data::PubSubMsg::Permissions(permissions) => {
+                            tracing::debug!("Permissions: {:?}", permissions);
+                            self.permissions = Some(permissions);
+                            // The server knows the newer messages, so we can ask for stats,
+                            // which the server will then keep fresh:
+                            if permissions.may_read {
+                                self.ws_sender.send(WsMessage::Binary(
+                                    rr_data::PubSubMsg::ListTopicsWithStats.encode(),
+                                ));
+                            }
+                        }
+                        rr_data::PubSubMsg::TopicRemoved(topic_id) => {
+                            // Open topics keep what they got, but get nothing more.
+                            tracing::debug!("Topic removed: {}", topic_id);
+                            self.topics
+                                .retain(|(topic_meta, _)| topic_meta.id != topic_id);
+                        }
+                        rr_data::PubSubMsg::TopicStatsChanged(changed) => {
+                            for (topic_id, new_stats) in changed {
+                                if let Some((_, stats)) = self
+                                    .topics
+                                    .iter_mut()
+                                    .find(|(topic_meta, _)| topic_meta.id == topic_id)
+                                {
+                                    *stats = new_stats;
                                 }
                             }
                         }
+                        rr_data::PubSubMsg::SearchResults(search_results) => {
+                            self.topic_search.on_results(search_results);
+                        }
+                        rr_data::PubSubMsg::TopicIndex(topic_id, topic_index) => {
+                            tracing::debug!(
+                                "Received index of topic {}: {} callsites, {} spans",
+                                topic_id,
+                                topic_index.callsites.len(),
+                                topic_index.spans.len()
+                            );
+                        }
                     }
                     continue;
                 }
@@ -84,15 +244,48 @@ impl Viewer {
             self.full_event_log.on_text(format!("Recevied {:?}", event));
         }
 
+        let dropped_files = std::mem::take(&mut ctx.input_mut().raw.dropped_files);
+        for dropped_file in dropped_files {
+            self.import_chrome_trace(&dropped_file);
+        }
+
         egui::SidePanel::left("left_bar")
             .resizable(false)
             .show(ctx, |ui| {
                 ui.style_mut().wrap = Some(false);
+                if let Some(rejection) = &self.rejection {
+                    ui.colored_label(egui::Color32::RED, format!("Disconnected: {}", rejection));
+                }
                 ui.heading("Available topics:");
+                ui.weak("Click to open, click again to close");
+                ui.weak("Drop a Chrome Trace JSON file to import it");
+                match self.topic_search.ui(ui, &self.topics) {
+                    Some(crate::topic_search::SearchAction::Search(query)) => {
+                        self.ws_sender.send(WsMessage::Binary(
+                            rr_data::PubSubMsg::Search(query).encode(),
+                        ));
+                    }
+                    Some(crate::topic_search::SearchAction::OpenTopic(topic_id)) => {
+                        let topic_meta = self
+                            .topics
+                            .iter()
+                            .find(|(topic_meta, _)| topic_meta.id == topic_id)
+                            .map(|(topic_meta, _)| topic_meta.clone());
+                        if let Some(topic_meta) = topic_meta {
+                            self.open_topic(topic_meta);
+                        }
+                    }
+                    None => {}
+                }
+                ui.separator();
                 egui::ScrollArea::vertical().show(ui, |ui| {
                     let clicked = self.show_topic_list(ui);
                     if let Some(topic_meta) = clicked {
-                        self.subscribe_to(topic_meta);
+                        if self.active_topic == Some(topic_meta.id) {
+                            self.close_topic(topic_meta.id);
+                        } else {
+                            self.open_topic(topic_meta);
+                        }
                     }
                 });
             });
@@ -102,65 +295,431 @@ impl Viewer {
                 egui::menu::bar(ui, |ui| {
                     ui.selectable_value(&mut self.view, View::Events, "Events");
                     ui.selectable_value(&mut self.view, View::Log, "Log");
+                    ui.selectable_value(&mut self.view, View::Table, "Table");
                     ui.selectable_value(&mut self.view, View::SpanTree, "Span tree");
                     ui.selectable_value(&mut self.view, View::Flamegraph, "Flame graph");
+                    ui.selectable_value(&mut self.view, View::Statistics, "Statistics");
+                    ui.selectable_value(&mut self.view, View::Metrics, "Metrics");
+                    ui.selectable_value(&mut self.view, View::Diff, "Diff");
                 });
+
+                let active = self.active_index();
+                if let Some(topic_viewer) = active.map(|index| &mut self.topic_viewers[index]) {
+                    let topic_id = topic_viewer.topic_meta.id;
+                    match topic_viewer.subscription_ui(ui) {
+                        Some(SubscriptionAction::Resubscribe(subscription)) => {
+                            self.ws_sender.send(WsMessage::Binary(
+                                rr_data::PubSubMsg::Subscribe(topic_id, subscription.clone())
+                                    .encode(),
+                            ));
+                            topic_viewer.subscription = Some(subscription);
+                            topic_viewer.awaiting_range = true;
+                        }
+                        Some(SubscriptionAction::LoadOlder(query)) => {
+                            self.ws_sender.send(WsMessage::Binary(
+                                rr_data::PubSubMsg::QueryMessages(topic_id, query.clone()).encode(),
+                            ));
+                            topic_viewer.older = Some((query, vec![]));
+                        }
+                        None => {}
+                    }
+
+                    ui.separator();
+                    if ui
+                        .button("Export…")
+                        .on_hover_text(EXPORT_HOVER_TEXT)
+                        .clicked()
+                    {
+                        let result = topic_viewer.export_chrome_trace(ui);
+                        self.full_event_log.on_text(result);
+                    }
+
+                    if let Some((min_ns, max_ns)) = topic_viewer.flame_graph.selection() {
+                        ui.separator();
+                        ui.label(format!(
+                            "Selected time range: {}",
+                            crate::misc::format_duration_ns(max_ns - min_ns)
+                        ))
+                        .on_hover_text("Shift-drag in the flame graph to change it");
+                        if ui.button("ｘ").on_hover_text("Clear selection").clicked() {
+                            topic_viewer.flame_graph.clear_selection();
+                        }
+                    }
+                }
             });
+
+            if self.topic_viewers.len() > 1 {
+                self.topic_tabs_ui(ui);
+            }
         });
 
-        egui::CentralPanel::default().show(ctx, |ui| match self.view {
-            View::Events => {
-                self.full_event_log.ui(ui);
+        let time_origin_ns = self.time_origin_ns();
+
+        // Selected in the flame graph:
+        let active = self.active_index();
+        let time_range = active.and_then(|index| self.topic_viewers[index].flame_graph.selection());
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            if self.view == View::Flamegraph && self.stack_flame_graphs {
+                self.stacked_flame_graphs_ui(ui);
+                return;
             }
-            View::Log => {
-                if let Some(topic_viewer) = &mut self.topic_viewer {
-                    topic_viewer.data_event_log.ui(ui, &topic_viewer.span_tree);
-                }
+            if self.view == View::Events {
+                self.full_event_log.ui(ui, time_range);
+                return;
+            }
+            if self.view == View::Diff {
+                let topics: Vec<(&TopicMeta, &crate::span_tree::SpanTree)> = self
+                    .topic_viewers
+                    .iter()
+                    .map(|topic_viewer| (&topic_viewer.topic_meta, &topic_viewer.span_tree))
+                    .collect();
+                self.diff_view.ui(ui, &topics);
+                return;
             }
-            View::SpanTree => {
-                if let Some(topic_viewer) = &mut self.topic_viewer {
-                    topic_viewer.span_tree.tree_ui(ui);
+
+            let topic_viewer = match active {
+                Some(index) => &mut self.topic_viewers[index],
+                None => {
+                    ui.label("Select a topic to view it.");
+                    return;
+                }
+            };
+
+            match self.view {
+                View::Events | View::Diff => {}
+                View::Log => {
+                    topic_viewer
+                        .data_event_log
+                        .ui(ui, &topic_viewer.span_tree, time_range);
+                }
+                View::Table => {
+                    topic_viewer.event_table.ui(
+                        ui,
+                        &topic_viewer.span_tree,
+                        topic_viewer.data_event_log.rows(),
+                    );
+                }
+                View::SpanTree => {
+                    topic_viewer.span_tree.tree_ui(ui, time_range);
+                }
+                View::Flamegraph => {
+                    if self.time_axis == TimeAxis::Independent {
+                        topic_viewer.flame_graph_ui(ui, None);
+                    } else {
+                        if let Some(view) = self.shared_view {
+                            topic_viewer.flame_graph.set_view(view);
+                        }
+                        topic_viewer.flame_graph_ui(ui, time_origin_ns);
+                        self.shared_view = Some(topic_viewer.flame_graph.view());
+                    }
+                }
+                View::Statistics => {
+                    let highlight =
+                        topic_viewer
+                            .span_stats
+                            .ui(ui, &topic_viewer.span_tree, time_range);
+                    if let Some(callsite_id) = highlight {
+                        topic_viewer
+                            .flame_graph
+                            .highlight_callsite(Some(callsite_id));
+                        self.view = View::Flamegraph;
+                    }
+                }
+                View::Metrics => {
+                    topic_viewer.metrics.ui(ui, &topic_viewer.span_tree);
                 }
             }
-            View::Flamegraph => {
-                if let Some(topic_viewer) = &mut self.topic_viewer {
-                    topic_viewer.flame_graph.ui(ui, &topic_viewer.span_tree);
+        });
+    }
+
+    fn active_index(&self) -> Option<usize> {
+        let active_topic = self.active_topic?;
+        self.topic_viewers
+            .iter()
+            .position(|topic_viewer| topic_viewer.topic_meta.id == active_topic)
+    }
+
+    /// Tabs for switching between the open topics, and how to compare them.
+    fn topic_tabs_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal_wrapped(|ui| {
+            let mut closed = None;
+            for topic_viewer in &self.topic_viewers {
+                let topic_id = topic_viewer.topic_meta.id;
+                ui.selectable_value(
+                    &mut self.active_topic,
+                    Some(topic_id),
+                    topic_summary(&topic_viewer.topic_meta),
+                );
+                if ui.small_button("ｘ").on_hover_text("Close topic").clicked() {
+                    closed = Some(topic_id);
                 }
+                ui.separator();
+            }
+            if let Some(topic_id) = closed {
+                self.close_topic(topic_id);
             }
+
+            ui.checkbox(&mut self.stack_flame_graphs, "Stack flame graphs")
+                .on_hover_text("Show the flame graphs of all open topics above each other");
+            egui::ComboBox::new("time_axis", "Time axis")
+                .selected_text(self.time_axis.name())
+                .show_ui(ui, |ui| {
+                    for time_axis in [
+                        TimeAxis::Independent,
+                        TimeAxis::Absolute,
+                        TimeAxis::TopicStart,
+                    ] {
+                        ui.selectable_value(&mut self.time_axis, time_axis, time_axis.name());
+                    }
+                });
         });
     }
 
+    fn stacked_flame_graphs_ui(&mut self, ui: &mut egui::Ui) {
+        let time_origin_ns = self.time_origin_ns();
+        let time_axis = self.time_axis;
+        let mut shared_view = self
+            .shared_view
+            .filter(|_| time_axis != TimeAxis::Independent);
+        let spacing = ui.spacing().item_spacing.y;
+        let height = (ui.available_height() / self.topic_viewers.len() as f32 - spacing).max(150.0);
+
+        egui::ScrollArea::vertical()
+            .auto_shrink([false; 2])
+            .show(ui, |ui| {
+                for topic_viewer in &mut self.topic_viewers {
+                    let (rect, _) = ui.allocate_exact_size(
+                        egui::vec2(ui.available_width(), height),
+                        egui::Sense::hover(),
+                    );
+                    // Separate ids, so the widgets of the different topics don't clash:
+                    let mut ui =
+                        ui.child_ui_with_id_source(rect, *ui.layout(), topic_viewer.topic_meta.id);
+                    ui.strong(topic_summary(&topic_viewer.topic_meta));
+                    if let Some(view) = shared_view {
+                        topic_viewer.flame_graph.set_view(view);
+                    }
+                    topic_viewer.flame_graph_ui(&mut ui, time_origin_ns);
+                    if time_axis != TimeAxis::Independent {
+                        shared_view = Some(topic_viewer.flame_graph.view());
+                    }
+                }
+            });
+        self.shared_view = shared_view;
+    }
+
+    /// The common start of the time axis, when aligning topics on absolute time.
+    fn time_origin_ns(&self) -> Option<i64> {
+        if self.time_axis == TimeAxis::Absolute {
+            self.topic_viewers
+                .iter()
+                .filter_map(|topic_viewer| Some(topic_viewer.span_tree.ns_range()?.0))
+                .min()
+        } else {
+            None
+        }
+    }
+
     fn show_topic_list(&self, ui: &mut egui::Ui) -> Option<TopicMeta> {
         let mut clicked = None;
-        for topic_meta in &self.topics {
-            let topic_summary = format!("{} - {}", topic_meta.created.format(), topic_meta.name);
-            let is_selected = self
-                .topic_viewer
-                .as_ref()
-                .map_or(false, |viewer| viewer.topic_meta.id == topic_meta.id);
-            if ui.selectable_label(is_selected, topic_summary).clicked() {
+        for (topic_meta, topic_stats) in &self.topics {
+            let is_open = self
+                .topic_viewers
+                .iter()
+                .any(|viewer| viewer.topic_meta.id == topic_meta.id);
+            let mut text =
+                egui::RichText::new(format!("{} ({})", topic_summary(topic_meta), topic_stats));
+            if self.active_topic == Some(topic_meta.id) {
+                text = text.strong();
+            }
+            if ui.selectable_label(is_open, text).clicked() {
                 clicked = Some(topic_meta.clone());
             }
         }
         clicked
     }
 
-    fn subscribe_to(&mut self, topic_meta: TopicMeta) {
+    fn on_all_topics(&mut self, all_topics: Vec<(TopicMeta, TopicStats)>) {
+        self.topics = all_topics;
+        if self.topic_viewers.is_empty() {
+            if let Some((latest_topic, _)) = self.topics.last().cloned() {
+                self.open_topic(latest_topic);
+            }
+        }
+    }
+
+    /// Subscribe to the topic, unless we already have, and make it the active one.
+    fn open_topic(&mut self, topic_meta: TopicMeta) {
+        self.active_topic = Some(topic_meta.id);
+        if self
+            .topic_viewers
+            .iter()
+            .any(|topic_viewer| topic_viewer.topic_meta.id == topic_meta.id)
+        {
+            return;
+        }
+
         tracing::info!("Subscribing to new topic: {:?}", topic_meta);
         self.full_event_log
             .on_text(format!("Subscribing to new topic: {:?}", topic_meta));
+        let subscription = Subscription {
+            backlog: Backlog::Last(INITIAL_BACKLOG),
+            filter: Default::default(),
+        };
+        self.ws_sender.send(WsMessage::Binary(
+            rr_data::PubSubMsg::Subscribe(topic_meta.id, subscription.clone()).encode(),
+        ));
+        let mut topic_viewer = TopicViewer::new(topic_meta);
+        topic_viewer.subscription = Some(subscription);
+        topic_viewer.awaiting_range = true;
+        self.topic_viewers.push(topic_viewer);
+    }
+
+    /// May we publish topics on this connection?
+    ///
+    /// Servers from before tokens don't answer [`rr_data::PubSubMsg::QueryPermissions`],
+    /// but then anyone may do anything.
+    fn may_publish(&self) -> bool {
+        match self.permissions {
+            Some(permissions) => permissions.may_publish,
+            None => self.token.is_none(),
+        }
+    }
+
+    /// Publish a Chrome Trace Event JSON file as a new topic, and open it.
+    ///
+    /// If we may not publish, we only open it here.
+    fn import_chrome_trace(&mut self, dropped_file: &egui::DroppedFile) {
+        let name = match &dropped_file.path {
+            Some(path) => path.file_name().map_or_else(
+                || path.display().to_string(),
+                |name| name.to_string_lossy().to_string(),
+            ),
+            None => dropped_file.name.clone(),
+        };
+
+        let bytes = match (&dropped_file.bytes, &dropped_file.path) {
+            (Some(bytes), _) => Ok(bytes.to_vec()),
+            (None, Some(path)) => std::fs::read(path).map_err(anyhow::Error::from),
+            (None, None) => Err(anyhow::anyhow!("No contents")),
+        };
+        let messages = bytes.and_then(|bytes| {
+            let json = String::from_utf8(bytes)?;
+            rr_data::chrome_trace::import(&json)
+        });
+        let messages = match messages {
+            Ok(messages) => messages,
+            Err(err) => {
+                tracing::error!("Failed to import {}: {}", name, err);
+                self.full_event_log
+                    .on_text(format!("Failed to import {}: {}", name, err));
+                return;
+            }
+        };
+
+        let topic_meta = TopicMeta {
+            id: TopicId::random(),
+            created: rr_data::Time::now(),
+            name,
+        };
+        self.full_event_log.on_text(format!(
+            "Imported {} messages as new topic: {:?}",
+            messages.len(),
+            topic_meta
+        ));
+
+        // Publishing without permission would make the server close the connection.
+        let publish = self.may_publish();
+        if publish {
+            self.ws_sender.send(WsMessage::Binary(
+                rr_data::PubSubMsg::NewTopic(topic_meta.clone()).encode(),
+            ));
+        } else {
+            tracing::warn!(
+                "Not publishing {}: this token may not publish",
+                topic_meta.name
+            );
+            self.full_event_log.on_text(format!(
+                "Not publishing {}: this token may not publish. It is only shown here.",
+                topic_meta.name
+            ));
+        }
+
+        // We already have the messages, so we don't subscribe to them:
+        let mut topic_viewer = TopicViewer::new(topic_meta.clone());
+        for message in &messages {
+            let payload: Arc<[u8]> = message.encode().into();
+            if publish {
+                self.ws_sender.send(WsMessage::Binary(
+                    rr_data::PubSubMsg::TopicMsg(topic_meta.id, payload.clone()).encode(),
+                ));
+            }
+            topic_viewer.on_topic_msg(payload, message);
+        }
+        self.topic_viewers.push(topic_viewer);
+        self.active_topic = Some(topic_meta.id);
+    }
+
+    fn close_topic(&mut self, topic_id: TopicId) {
         self.ws_sender.send(WsMessage::Binary(
-            rr_data::PubSubMsg::SubscribeTo(topic_meta.id).encode(),
+            rr_data::PubSubMsg::UnsubscribeFrom(topic_id).encode(),
         ));
-        self.topic_viewer = Some(TopicViewer::new(topic_meta));
+        self.topic_viewers
+            .retain(|topic_viewer| topic_viewer.topic_meta.id != topic_id);
+        if self.active_topic == Some(topic_id) {
+            self.active_topic = self
+                .topic_viewers
+                .last()
+                .map(|topic_viewer| topic_viewer.topic_meta.id);
+        }
     }
 }
 
+#[cfg(not(target_arch = "wasm32"))]
+const EXPORT_HOVER_TEXT: &str = "Save the topic as Chrome Trace Event JSON in the current directory, for Perfetto and chrome://tracing";
+
+#[cfg(target_arch = "wasm32")]
+const EXPORT_HOVER_TEXT: &str =
+    "Copy the topic as Chrome Trace Event JSON, for Perfetto and chrome://tracing";
+
+pub fn topic_summary(topic_meta: &TopicMeta) -> String {
+    format!("{} - {}", topic_meta.created.format(), topic_meta.name)
+}
+
+/// What the user wants from the server.
+enum SubscriptionAction {
+    /// Start over with a new subscription.
+    Resubscribe(Subscription),
+    /// Get these older messages, and put them before the ones we have.
+    LoadOlder(MessageQuery),
+}
+
 pub struct TopicViewer {
     topic_meta: TopicMeta,
     span_tree: crate::span_tree::SpanTree,
     flame_graph: crate::flamegraph::FlameGraph,
     data_event_log: crate::data_event_log::DataEventLog,
+    event_table: crate::event_table::EventTable,
+    metrics: crate::metrics::Metrics,
+    span_stats: crate::span_stats::SpanStats,
+
+    /// What we asked the server for, if we subscribed to the topic.
+    subscription: Option<Subscription>,
+
+    /// What part of the topic we got from the server, if we subscribed to it.
+    range: Option<TopicRange>,
+
+    /// We have asked for a new backlog, and ignore messages until the server replies.
+    awaiting_range: bool,
+
+    /// Everything we got from the server since the last [`TopicRange`], as encoded [`rr_data::Message`]s.
+    ///
+    /// Kept so that we can rebuild the views when older messages are loaded.
+    messages: Vec<Arc<[u8]>>,
+
+    /// The older messages we have asked for, and the parts of the reply we got so far.
+    older: Option<(MessageQuery, Vec<Arc<[u8]>>)>,
 }
 
 impl TopicViewer {
@@ -170,11 +729,179 @@ impl TopicViewer {
             span_tree: Default::default(),
             flame_graph: Default::default(),
             data_event_log: Default::default(),
+            event_table: Default::default(),
+            metrics: Default::default(),
+            span_stats: Default::default(),
+            subscription: None,
+            range: None,
+            awaiting_range: false,
+            messages: Default::default(),
+            older: None,
         }
     }
 
+    /// `rr_msg` is the decoded `payload`.
+    pub fn on_topic_msg(&mut self, payload: Arc<[u8]>, rr_msg: &rr_data::Message) {
+        self.messages.push(payload);
+        self.on_message(rr_msg);
+    }
+
     pub fn on_message(&mut self, rr_msg: &rr_data::Message) {
         self.data_event_log.on_message(rr_msg);
+        self.metrics.on_message(rr_msg);
         self.span_tree.on_mesage(rr_msg, true);
     }
+
+    /// Forget the messages, but keep the views as they are.
+    fn clear_messages(&mut self) {
+        self.span_tree = Default::default();
+        self.data_event_log.clear();
+        self.event_table.clear_cache();
+        self.metrics = Default::default();
+        self.messages.clear();
+        self.older = None;
+    }
+
+    /// A part of the reply to [`rr_data::PubSubMsg::QueryMessages`].
+    fn on_older_messages(&mut self, part: &MessageQuery, messages: Vec<Arc<[u8]>>) {
+        let (query, received) = match &mut self.older {
+            Some((query, received)) if query.filter == part.filter => (query, received),
+            _ => return, // Not what we asked for (anymore)
+        };
+        received.extend(messages);
+        if part.end < query.end {
+            return; // more to come
+        }
+        let start = query.start;
+        let older = std::mem::take(received);
+        self.older = None;
+
+        // Replay the older messages followed by the newer ones.
+        // Both start with the callsites and live spans from before them, so skip repeats of those.
+        let newer = std::mem::take(&mut self.messages);
+        self.clear_messages();
+        let mut callsites = std::collections::HashSet::new();
+        let mut spans = std::collections::HashSet::new();
+        for payload in older.into_iter().chain(newer) {
+            if let Ok(rr_msg) = rr_data::Message::decode(&payload) {
+                let repeat = match &rr_msg.msg_enum {
+                    rr_data::MessageEnum::NewCallsite(callsite) => !callsites.insert(callsite.id),
+                    rr_data::MessageEnum::NewSpan(span) => {
+                        !spans.insert((span.id, rr_msg.log_time))
+                    }
+                    _ => false,
+                };
+                if !repeat {
+                    self.on_topic_msg(payload, &rr_msg);
+                }
+            }
+        }
+        if let Some(range) = &mut self.range {
+            range.backlog_start = start;
+        }
+    }
+
+    /// Offer to get the older messages we skipped, and to filter the messages on the server.
+    ///
+    /// Returns what to ask the server for.
+    fn subscription_ui(&self, ui: &mut egui::Ui) -> Option<SubscriptionAction> {
+        let subscription = self.subscription.as_ref()?;
+        ui.separator();
+        if self.awaiting_range || self.older.is_some() {
+            ui.label("Loading…");
+            return None;
+        }
+        let backlog_start = self.range.map_or(0, |range| range.backlog_start);
+
+        let mut min_level = subscription.filter.min_level;
+        egui::ComboBox::from_id_source("min_level")
+            .selected_text(
+                min_level.map_or_else(|| "All levels".to_owned(), |level| format!("{}+", level)),
+            )
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut min_level, None, "All levels");
+                for level in [
+                    LogLevel::Trace,
+                    LogLevel::Debug,
+                    LogLevel::Info,
+                    LogLevel::Warn,
+                    LogLevel::Error,
+                ] {
+                    ui.selectable_value(&mut min_level, Some(level), format!("{}+", level));
+                }
+            })
+            .response
+            .on_hover_text("Only get events and spans of at least this level from the server");
+        if min_level != subscription.filter.min_level {
+            let mut subscription = subscription.clone();
+            subscription.backlog = Backlog::FromIndex(backlog_start);
+            subscription.filter.min_level = min_level;
+            return Some(SubscriptionAction::Resubscribe(subscription));
+        }
+
+        if backlog_start == 0 {
+            return None;
+        }
+
+        ui.label(format!("{} older messages not loaded", backlog_start));
+        let mut start = None;
+        if ui
+            .button("Load older")
+            .on_hover_text(format!(
+                "Load up to {} more of the older messages",
+                OLDER_PAGE_SIZE
+            ))
+            .clicked()
+        {
+            start = Some(backlog_start.saturating_sub(OLDER_PAGE_SIZE));
+        }
+        if ui.button("Load all").clicked() {
+            start = Some(0);
+        }
+        start.map(|start| {
+            SubscriptionAction::LoadOlder(MessageQuery {
+                start,
+                end: backlog_start,
+                filter: subscription.filter.clone(),
+            })
+        })
+    }
+
+    /// Returns what happened, for the event log.
+    fn export_chrome_trace(&self, ui: &egui::Ui) -> String {
+        let messages: Vec<rr_data::Message> = self
+            .messages
+            .iter()
+            .filter_map(|payload| rr_data::Message::decode(payload).ok())
+            .collect();
+        let json = rr_data::chrome_trace::export(&messages, &self.topic_meta.name);
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let _ = ui;
+            let file_name: String = self
+                .topic_meta
+                .name
+                .chars()
+                .map(|c| if c.is_alphanumeric() { c } else { '_' })
+                .collect();
+            let path = format!("{}_{}.trace.json", file_name, self.topic_meta.id);
+            match std::fs::write(&path, json) {
+                Ok(()) => format!("Exported Chrome trace to {}", path),
+                Err(err) => format!("Failed to write {}: {}", path, err),
+            }
+        }
+
+        #[cfg(target_arch = "wasm32")]
+        {
+            ui.output().copied_text = json;
+            "Copied Chrome trace JSON to the clipboard".to_owned()
+        }
+    }
+
+    fn flame_graph_ui(&mut self, ui: &mut egui::Ui, time_origin_ns: Option<i64>) {
+        self.flame_graph.set_time_origin(time_origin_ns);
+        self.flame_graph
+            .ui(ui, &self.span_tree, self.data_event_log.rows());
+    }
 }
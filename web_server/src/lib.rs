@@ -2,48 +2,372 @@
 #![warn(clippy::all, rust_2018_idioms)]
 #![allow(clippy::manual_range_contains)]
 
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
 use std::task::{Context, Poll};
 
 use futures_util::future;
+use hyper::header::{self, HeaderValue};
 use hyper::service::Service;
-use hyper::{Body, Request, Response};
+use hyper::{Body, Method, Request, Response, StatusCode};
+use once_cell::sync::Lazy;
+
+/// The viewer web app, compiled into the binary.
+const EMBEDDED_FILES: &[(&str, &[u8])] = &[
+    ("/index.html", include_bytes!("../../docs/index.html")),
+    ("/favicon.ico", include_bytes!("../../docs/favicon.ico")),
+    ("/icon-256.png", include_bytes!("../../docs/icon-256.png")),
+    ("/manifest.json", include_bytes!("../../docs/manifest.json")),
+    ("/sw.js", include_bytes!("../../docs/sw.js")),
+    (
+        "/viewer_bg.wasm",
+        include_bytes!("../../docs/viewer_bg.wasm"),
+    ),
+    ("/viewer.js", include_bytes!("../../docs/viewer.js")),
+];
+
+/// Hashing the viewer takes a while, so we only do it once.
+static EMBEDDED_ETAGS: Lazy<HashMap<&'static str, String>> = Lazy::new(|| {
+    EMBEDDED_FILES
+        .iter()
+        .map(|(path, bytes)| (*path, format!("\"{:016x}\"", hash(bytes))))
+        .collect()
+});
+
+/// The viewer web app, compiled into the binary.
+pub fn embedded_file(path: &str) -> Option<&'static [u8]> {
+    let path = if path == "/" { "/index.html" } else { path };
+    EMBEDDED_FILES
+        .iter()
+        .find(|(embedded_path, _)| *embedded_path == path)
+        .map(|(_, bytes)| *bytes)
+}
+
+/// Where the files of the viewer web app come from.
+#[derive(Clone, Debug, Default)]
+pub struct Assets {
+    /// Files in here take precedence over the [`embedded_file`]s.
+    dir: Option<PathBuf>,
+
+    /// Injected into `index.html`.
+    pub_sub_url: Option<String>,
+}
+
+impl Assets {
+    /// Only serve the [`embedded_file`]s.
+    pub fn embedded() -> Self {
+        Self::default()
+    }
+
+    /// Serve the files in the given directory (e.g. a freshly built `docs/`),
+    /// falling back to the [`embedded_file`]s.
+    ///
+    /// If the browser accepts it, `file.br` or `file.gz` is served instead of `file`.
+    pub fn from_dir(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: Some(dir.into()),
+            pub_sub_url: None,
+        }
+    }
+
+    /// Make the viewer connect to this pub-sub server,
+    /// unless the page is opened with a `?pubsub=` of its own.
+    pub fn with_pub_sub_url(mut self, pub_sub_url: impl Into<String>) -> Self {
+        self.pub_sub_url = Some(pub_sub_url.into());
+        self
+    }
+
+    /// Respond to a `GET` or `HEAD` request for one of the files.
+    ///
+    /// Responses have an `ETag`, and we reply `304 Not Modified` if it matches `If-None-Match`.
+    /// Files are only read when the browser doesn't already have them.
+    pub async fn serve(&self, req: &Request<Body>) -> Response<Body> {
+        if req.method() != Method::GET && req.method() != Method::HEAD {
+            return status_response(StatusCode::METHOD_NOT_ALLOWED);
+        }
+
+        let path = match req.uri().path() {
+            "/" => "/index.html",
+            path => path,
+        };
+        let inject_pub_sub_url = self
+            .pub_sub_url
+            .as_deref()
+            .filter(|_| path == "/index.html");
+        let accepted_encodings = if inject_pub_sub_url.is_some() {
+            vec![] // We can't inject into a compressed file
+        } else {
+            accepted_encodings(req)
+        };
+        let mut file = match self.file_from_dir(path, accepted_encodings).await {
+            Some(file) => file,
+            None => match embedded(path) {
+                Some(file) => file,
+                None => {
+                    tracing::warn!("404 path: {}", req.uri().path());
+                    return status_response(StatusCode::NOT_FOUND);
+                }
+            },
+        };
+        if let Some(pub_sub_url) = inject_pub_sub_url {
+            file.etag = format!(
+                "{}-{:016x}\"",
+                file.etag.trim_end_matches('"'),
+                hash(pub_sub_url.as_bytes())
+            );
+        }
+
+        let mut rsp = Response::builder()
+            .header(header::CONTENT_TYPE, content_type(path))
+            .header(header::ETAG, &file.etag)
+            // Always check the ETag, so that a rebuilt viewer is picked up:
+            .header(header::CACHE_CONTROL, "no-cache");
+        if self.dir.is_some() {
+            rsp = rsp.header(header::VARY, "Accept-Encoding");
+        }
+        if let Some(encoding) = file.encoding {
+            rsp = rsp.header(header::CONTENT_ENCODING, encoding);
+        }
+
+        if if_none_match(req, &file.etag) {
+            return rsp
+                .status(StatusCode::NOT_MODIFIED)
+                .body(Body::empty())
+                .unwrap();
+        }
+
+        let mut contents = match file.contents {
+            Contents::Embedded(bytes) => Cow::Borrowed(bytes),
+            Contents::OnDisk(file_path) => match tokio::fs::read(&file_path).await {
+                Ok(contents) => Cow::Owned(contents),
+                Err(err) => {
+                    tracing::warn!("Failed to read {:?}: {}", file_path, err);
+                    return status_response(StatusCode::INTERNAL_SERVER_ERROR);
+                }
+            },
+        };
+        if let Some(pub_sub_url) = inject_pub_sub_url {
+            contents = Cow::Owned(inject_pub_sub_url_script(&contents, pub_sub_url));
+        }
+
+        let rsp = rsp.header(header::CONTENT_LENGTH, contents.len());
+        if req.method() == Method::HEAD {
+            rsp.status(StatusCode::OK).body(Body::empty()).unwrap()
+        } else {
+            rsp.status(StatusCode::OK)
+                .body(Body::from(contents))
+                .unwrap()
+        }
+    }
+
+    /// Only looks at the metadata: the file is read when needed.
+    async fn file_from_dir(&self, path: &str, accepted_encodings: Vec<String>) -> Option<File> {
+        let dir = self.dir.as_ref()?;
+        let relative = path.trim_start_matches('/');
+        if Path::new(relative)
+            .components()
+            .any(|component| !matches!(component, std::path::Component::Normal(_)))
+        {
+            return None; // Don't let anyone escape the directory
+        }
+        let file_path = dir.join(relative);
+
+        let precompressed = [("br", "br"), ("gzip", "gz")];
+        let variants = precompressed
+            .iter()
+            .filter(|(encoding, _)| {
+                accepted_encodings
+                    .iter()
+                    .any(|accepted| accepted == encoding)
+            })
+            .map(|(encoding, extension)| {
+                let mut variant = file_path.clone().into_os_string();
+                variant.push(".");
+                variant.push(extension);
+                (Some(*encoding), PathBuf::from(variant))
+            })
+            .chain(std::iter::once((None, file_path.clone())));
+
+        for (encoding, variant_path) in variants {
+            let metadata = match tokio::fs::metadata(&variant_path).await {
+                Ok(metadata) if metadata.is_file() => metadata,
+                _ => continue,
+            };
+            let modified_ns = metadata
+                .modified()
+                .ok()
+                .and_then(|modified| modified.duration_since(std::time::UNIX_EPOCH).ok())
+                .map_or(0, |duration| duration.as_nanos());
+            return Some(File {
+                etag: format!(
+                    "\"{:x}-{:x}{}\"",
+                    metadata.len(),
+                    modified_ns,
+                    encoding.map_or_else(String::new, |encoding| format!("-{}", encoding))
+                ),
+                encoding,
+                contents: Contents::OnDisk(variant_path),
+            });
+        }
+        None
+    }
+}
+
+/// Respond with one of the [`embedded_file`]s, or 404.
+pub async fn serve_embedded(req: &Request<Body>) -> Response<Body> {
+    Assets::embedded().serve(req).await
+}
+
+struct File {
+    etag: String,
+    /// `Content-Encoding`
+    encoding: Option<&'static str>,
+    contents: Contents,
+}
+
+enum Contents {
+    Embedded(&'static [u8]),
+    /// Not read yet.
+    OnDisk(PathBuf),
+}
+
+/// Add a script to the page that sets `?pubsub=` before the viewer starts, unless already set.
+fn inject_pub_sub_url_script(html: &[u8], pub_sub_url: &str) -> Vec<u8> {
+    let script = format!(
+        r#"<script>
+        // Injected by web_server: the default pub-sub server.
+        (() => {{
+            const url = new URL(window.location.href);
+            if (!url.searchParams.has("pubsub")) {{
+                url.searchParams.set("pubsub", "{}");
+                window.history.replaceState(null, "", url);
+            }}
+        }})();
+    </script>
+</head>"#,
+        escape_js_string(pub_sub_url)
+    );
+    let html = String::from_utf8_lossy(html);
+    html.replacen("</head>", &script, 1).into_bytes()
+}
+
+/// So it can be put in a JavaScript string literal in an HTML `<script>`.
+fn escape_js_string(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' | '\\' | '\'' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '<' | '>' | '&' | '\n' | '\r' => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn hash(bytes: &[u8]) -> u64 {
+    use std::hash::{Hash as _, Hasher as _};
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    bytes.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn embedded(path: &str) -> Option<File> {
+    let bytes = embedded_file(path)?;
+    Some(File {
+        etag: EMBEDDED_ETAGS.get(path)?.clone(),
+        encoding: None,
+        contents: Contents::Embedded(bytes),
+    })
+}
+
+fn content_type(path: &str) -> &'static str {
+    let extension = Path::new(path)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default();
+    match extension.to_ascii_lowercase().as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "js" => "text/javascript; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "json" => "application/json",
+        "webmanifest" => "application/manifest+json",
+        "wasm" => "application/wasm",
+        "ico" => "image/x-icon",
+        "png" => "image/png",
+        "svg" => "image/svg+xml",
+        "txt" | "md" => "text/plain; charset=utf-8",
+        _ => "application/octet-stream",
+    }
+}
+
+/// The `Accept-Encoding`s of the request, without the ones with `q=0`.
+fn accepted_encodings(req: &Request<Body>) -> Vec<String> {
+    req.headers()
+        .get_all(header::ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|item| {
+            let mut parts = item.split(';').map(str::trim);
+            let encoding = parts.next()?.to_ascii_lowercase();
+            let refused = parts.any(|param| {
+                param.strip_prefix("q=").and_then(|q| q.parse::<f32>().ok()) == Some(0.0)
+            });
+            (!refused).then(|| encoding)
+        })
+        .collect()
+}
+
+fn if_none_match(req: &Request<Body>, etag: &str) -> bool {
+    req.headers()
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|tag| tag.trim().trim_start_matches("W/"))
+        .any(|tag| tag == "*" || tag == etag)
+}
+
+fn status_response(status: StatusCode) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_LENGTH, HeaderValue::from_static("0"))
+        .body(Body::empty())
+        .unwrap()
+}
+
+// ----------------------------------------------------------------------------
 
 #[derive(Debug)]
-pub struct Svc;
+pub struct Svc {
+    assets: Assets,
+}
 
 impl Service<Request<Body>> for Svc {
     type Response = Response<Body>;
     type Error = hyper::Error;
-    type Future = future::Ready<Result<Self::Response, Self::Error>>;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
 
     fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
         Ok(()).into()
     }
 
     fn call(&mut self, req: Request<Body>) -> Self::Future {
-        let rsp = Response::builder();
-
-        let bytes = match req.uri().path() {
-            "/" | "/index.html" => &include_bytes!("../../docs/index.html")[..],
-            "/favicon.ico" => &include_bytes!("../../docs/favicon.ico")[..],
-            "/sw.js" => &include_bytes!("../../docs/sw.js")[..],
-            "/viewer_bg.wasm" => &include_bytes!("../../docs/viewer_bg.wasm")[..],
-            "/viewer.js" => &include_bytes!("../../docs/viewer.js")[..],
-            _ => {
-                tracing::warn!("404 path: {}", req.uri().path());
-                let body = Body::from(Vec::new());
-                let rsp = rsp.status(404).body(body).unwrap();
-                return future::ok(rsp);
-            }
-        };
-
-        let body = Body::from(Vec::from(bytes));
-        let rsp = rsp.status(200).body(body).unwrap();
-        future::ok(rsp)
+        let assets = self.assets.clone();
+        Box::pin(async move { Ok(assets.serve(&req).await) })
     }
 }
 
-pub struct MakeSvc;
+pub struct MakeSvc {
+    assets: Assets,
+}
 
 impl<T> Service<T> for MakeSvc {
     type Response = Svc;
@@ -55,13 +379,80 @@ impl<T> Service<T> for MakeSvc {
     }
 
     fn call(&mut self, _: T) -> Self::Future {
-        future::ok(Svc)
+        future::ok(Svc {
+            assets: self.assets.clone(),
+        })
     }
 }
 
+/// How to run the web server.
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// Listen on this address.
+    ///
+    /// Default: `127.0.0.1`, i.e. only this computer can reach the viewer.
+    pub bind_ip: IpAddr,
+
+    /// Default: [`rr_data::DEFAULT_VIEWER_WEB_SERVER_PORT`].
+    pub port: u16,
+
+    /// The pub-sub server the viewer connects to, unless the page is opened with a `?pubsub=`.
+    ///
+    /// Default: the local pub-sub server, on [`rr_data::DEFAULT_PUB_SUB_PORT`].
+    /// With `None` the viewer connects to where it was served from,
+    /// which is only right when the pub-sub server serves it.
+    pub pub_sub_url: Option<String>,
+
+    /// Serve the viewer from this directory instead of the [`embedded_file`]s.
+    ///
+    /// See [`Assets::from_dir`].
+    pub asset_dir: Option<PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            bind_ip: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port: rr_data::DEFAULT_VIEWER_WEB_SERVER_PORT,
+            pub_sub_url: Some(format!("ws://127.0.0.1:{}", rr_data::DEFAULT_PUB_SUB_PORT)),
+            asset_dir: None,
+        }
+    }
+}
+
+impl Config {
+    pub fn assets(&self) -> Assets {
+        let assets = match &self.asset_dir {
+            Some(dir) => Assets::from_dir(dir),
+            None => Assets::embedded(),
+        };
+        match &self.pub_sub_url {
+            Some(pub_sub_url) => assets.with_pub_sub_url(pub_sub_url),
+            None => assets,
+        }
+    }
+}
+
+/// Serve the [`embedded_file`]s on `127.0.0.1`.
 pub async fn run(port: u16) -> Result<(), Box<dyn std::error::Error>> {
-    let bind_addr = format!("127.0.0.1:{}", port).parse().unwrap();
-    let server = hyper::Server::bind(&bind_addr).serve(MakeSvc);
+    run_with_config(Config {
+        port,
+        ..Default::default()
+    })
+    .await
+}
+
+pub async fn run_with_config(config: Config) -> Result<(), Box<dyn std::error::Error>> {
+    let bind_addr = SocketAddr::new(config.bind_ip, config.port);
+    if let Some(dir) = &config.asset_dir {
+        println!("Serving files from {:?}", dir);
+    }
+    if let Some(pub_sub_url) = &config.pub_sub_url {
+        println!("Default pub-sub server: {}", pub_sub_url);
+    }
+    let server = hyper::Server::try_bind(&bind_addr)?.serve(MakeSvc {
+        assets: config.assets(),
+    });
     println!("Serving viewer on http://{}", bind_addr);
     server.await?;
     Ok(())
@@ -2,9 +2,60 @@
 #![warn(clippy::all, rust_2018_idioms)]
 #![allow(clippy::manual_range_contains)]
 
+const USAGE: &str = "Usage: web_server [--bind IP] [--port PORT] [--pubsub URL] [--dir PATH]
+
+Serves the viewer web app.
+
+  --bind IP      Address to listen on (default: 127.0.0.1).
+                 Use 0.0.0.0 to make the viewer reachable from other computers.
+  --port PORT    Port to listen on (default: 8787).
+  --pubsub URL   Pub-sub server for the viewer to connect to, unless the page
+                 is opened with a ?pubsub= of its own (default: ws://127.0.0.1:9002).
+  --dir PATH     Serve the viewer from this directory (e.g. a freshly built docs/)
+                 instead of the one compiled into this binary.";
+
 #[tokio::main]
 async fn main() {
     tracing_subscriber::fmt::init();
-    let port = rr_data::DEFAULT_VIEWER_WEB_SERVER_PORT;
-    web_server::run(port).await.unwrap();
+    let config = match parse_args() {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, USAGE);
+            std::process::exit(2);
+        }
+    };
+    web_server::run_with_config(config).await.unwrap();
+}
+
+fn parse_args() -> Result<web_server::Config, String> {
+    let mut config = web_server::Config::default();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("Missing value for {}", arg))
+        };
+        match arg.as_str() {
+            "--bind" => {
+                let ip = value()?;
+                config.bind_ip = ip
+                    .parse()
+                    .map_err(|err| format!("Bad --bind {:?}: {}", ip, err))?;
+            }
+            "--port" => {
+                let port = value()?;
+                config.port = port
+                    .parse()
+                    .map_err(|err| format!("Bad --port {:?}: {}", port, err))?;
+            }
+            "--pubsub" => config.pub_sub_url = Some(value()?),
+            "--dir" => config.asset_dir = Some(value()?.into()),
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                std::process::exit(0);
+            }
+            _ => return Err(format!("Unknown argument {:?}", arg)),
+        }
+    }
+    Ok(config)
 }
@@ -0,0 +1,143 @@
+#![forbid(unsafe_code)]
+#![warn(clippy::all, rust_2018_idioms)]
+#![allow(clippy::manual_range_contains)]
+
+//! A native websocket client like [`ewebsock::ws_connect`],
+//! but where you choose which root certificates to trust for `wss://` urls.
+//!
+//! Used by the logger and the native viewer.
+
+use ewebsock::{EventHandler, WsEvent, WsMessage};
+use futures_util::{SinkExt as _, StreamExt as _};
+use std::{ops::ControlFlow, path::Path, sync::Arc};
+use tokio_tungstenite::{tungstenite, Connector};
+
+/// Which certificates to trust when connecting with `wss://`.
+///
+/// The default is the system root certificates, or only those in `$SSL_CERT_FILE` if set.
+#[derive(Clone, Debug, Default)]
+pub struct RootCerts {
+    /// `None` for the default.
+    roots: Option<rustls::RootCertStore>,
+}
+
+impl RootCerts {
+    /// Trust only the certificates in this PEM data, e.g. the self-signed certificate of the server.
+    pub fn from_pem(pem: &[u8]) -> std::io::Result<Self> {
+        let invalid = |msg: String| std::io::Error::new(std::io::ErrorKind::InvalidData, msg);
+        let certs = rustls_pemfile::certs(&mut std::io::BufReader::new(pem))?;
+        if certs.is_empty() {
+            return Err(invalid("No certificates in PEM".to_owned()));
+        }
+        let mut roots = rustls::RootCertStore::empty();
+        for cert in certs {
+            roots
+                .add(&rustls::Certificate(cert))
+                .map_err(|err| invalid(format!("Bad certificate: {}", err)))?;
+        }
+        Ok(Self { roots: Some(roots) })
+    }
+
+    /// Trust only the certificates in this PEM file.
+    pub fn from_pem_file(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let path = path.as_ref();
+        let pem = std::fs::read(path)?;
+        Self::from_pem(&pem)
+            .map_err(|err| std::io::Error::new(err.kind(), format!("{}: {}", path.display(), err)))
+    }
+
+    fn connector(&self) -> Option<Connector> {
+        let config = rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(self.roots.clone()?)
+            .with_no_client_auth();
+        Some(Connector::Rustls(Arc::new(config)))
+    }
+}
+
+/// Sends messages to the server, in order.
+///
+/// Messages sent before the connection is open are queued.
+pub struct WsSender {
+    tx: tokio::sync::mpsc::UnboundedSender<WsMessage>,
+}
+
+impl WsSender {
+    pub fn send(&mut self, msg: WsMessage) {
+        // If the connection is gone, the event handler has already been told.
+        self.tx.send(msg).ok();
+    }
+}
+
+/// Connect in the background, and call the given event handler on each new received event.
+///
+/// Must be called from within a tokio runtime.
+pub fn ws_connect(url: String, root_certs: &RootCerts, on_event: EventHandler) -> WsSender {
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    let connector = root_certs.connector();
+    tokio::spawn(async move {
+        if let Err(err) = run(url, connector, rx, &on_event).await {
+            let _ = on_event(WsEvent::Error(err.to_string())); // the connection is over anyway
+        }
+    });
+    WsSender { tx }
+}
+
+async fn run(
+    url: String,
+    connector: Option<Connector>,
+    mut rx: tokio::sync::mpsc::UnboundedReceiver<WsMessage>,
+    on_event: &EventHandler,
+) -> tungstenite::Result<()> {
+    let (ws_stream, _) =
+        tokio_tungstenite::connect_async_tls_with_config(url, None, connector).await?;
+    tracing::debug!("WebSocket handshake has been successfully completed");
+    if on_event(WsEvent::Opened) == ControlFlow::Break(()) {
+        return Ok(());
+    }
+
+    let (mut write, mut read) = ws_stream.split();
+    loop {
+        tokio::select! {
+            msg = rx.recv() => match msg {
+                Some(msg) => write.send(to_tungstenite(msg)).await?,
+                None => return Ok(()), // the `WsSender` was dropped
+            },
+            msg = read.next() => {
+                let event = match msg {
+                    Some(msg) => match from_tungstenite(msg?) {
+                        Some(event) => event,
+                        None => continue,
+                    },
+                    None => WsEvent::Closed,
+                };
+                let closed = matches!(event, WsEvent::Closed);
+                if on_event(event) == ControlFlow::Break(()) || closed {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+fn to_tungstenite(msg: WsMessage) -> tungstenite::Message {
+    match msg {
+        WsMessage::Binary(data) => tungstenite::Message::Binary(data),
+        WsMessage::Text(text) => tungstenite::Message::Text(text),
+        WsMessage::Ping(data) => tungstenite::Message::Ping(data),
+        WsMessage::Pong(data) => tungstenite::Message::Pong(data),
+        WsMessage::Unknown(_) => panic!("You cannot send WsMessage::Unknown"),
+    }
+}
+
+fn from_tungstenite(msg: tungstenite::Message) -> Option<WsEvent> {
+    let msg = match msg {
+        tungstenite::Message::Binary(data) => WsMessage::Binary(data),
+        tungstenite::Message::Text(text) => WsMessage::Text(text),
+        tungstenite::Message::Ping(data) => WsMessage::Ping(data),
+        tungstenite::Message::Pong(data) => WsMessage::Pong(data),
+        tungstenite::Message::Close(_) => return Some(WsEvent::Closed),
+        tungstenite::Message::Frame(_) => return None,
+    };
+    Some(WsEvent::Message(msg))
+}